@@ -0,0 +1,144 @@
+//! Counts the allocations `extract_triangle_meshes` makes per frame with 50k triangles,
+//! against handing a fresh `Vec` to `insert_or_spawn_batch` every frame as it used to.
+//!
+//! `cargo bench --bench extract_allocations`
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use bevy::asset::AssetPlugin;
+use bevy::core::CorePlugin;
+use bevy::ecs::schedule::IntoSystemDescriptor;
+use bevy::prelude::*;
+use bevy::transform::TransformPlugin;
+use bevy_experiments::render::plugin::{TriangleExtractPlugin, TriangleRenderConfig};
+use bevy_experiments::render::system::{extract_triangle_meshes, BatchBuffer};
+use bevy_experiments::render::TriangleUniform;
+use bevy_experiments::{Triangle, TriangleBundle, TriangleMeshHandle, TrianglePlugin};
+
+const TRIANGLES: usize = 50_000;
+const FRAMES: usize = 20;
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn main() {
+    let mut app = App::new();
+    app.add_plugin(CorePlugin)
+        .add_plugin(TransformPlugin)
+        .add_plugin(AssetPlugin)
+        .add_asset::<Mesh>()
+        .add_asset::<Image>()
+        .init_resource::<Windows>()
+        .init_resource::<Input<MouseButton>>()
+        .add_plugin(TrianglePlugin)
+        .add_plugin(TriangleExtractPlugin {
+            config: TriangleRenderConfig::default(),
+        });
+    for i in 0..TRIANGLES {
+        let translation = Vec3::new((i % 250) as f32, (i / 250) as f32, 0.0) * 4.0;
+        app.world
+            .spawn()
+            .insert_bundle(TriangleBundle::at(Triangle::side(2.0), translation));
+    }
+    app.update();
+
+    println!("{} triangles, per frame after the first:", TRIANGLES);
+    report("fresh Vec", measure(&mut app.world, fresh_batch));
+    report("BatchBuffer", measure(&mut app.world, reused_batch));
+    report(
+        "extract_triangle_meshes",
+        measure(&mut app.world, extract_triangle_meshes),
+    );
+}
+
+fn report(name: &str, (allocations, bytes): (usize, usize)) {
+    println!(
+        "{:>24}: {:>8} allocations, {:>10} bytes",
+        name, allocations, bytes
+    );
+}
+
+/// Runs `system` like the extract stage would for `FRAMES` frames, clearing the render
+/// world in between as Bevy does, and averages its allocations over all but the first.
+fn measure<Params>(
+    main_world: &mut World,
+    system: impl IntoSystemDescriptor<Params>,
+) -> (usize, usize) {
+    let mut render_world = World::new();
+    let mut stage = SystemStage::single_threaded().with_system(system);
+    stage.set_apply_buffers(false);
+    let mut total = (0, 0);
+    for frame in 0..FRAMES {
+        let before = (
+            ALLOCATIONS.load(Ordering::Relaxed),
+            BYTES.load(Ordering::Relaxed),
+        );
+        stage.run(main_world);
+        stage.apply_buffers(&mut render_world);
+        render_world.clear_entities();
+        if frame > 0 {
+            total.0 += ALLOCATIONS.load(Ordering::Relaxed) - before.0;
+            total.1 += BYTES.load(Ordering::Relaxed) - before.1;
+        }
+    }
+    (total.0 / (FRAMES - 1), total.1 / (FRAMES - 1))
+}
+
+type Extracted = (TriangleMeshHandle, TriangleUniform);
+
+fn extracted(handle: &TriangleMeshHandle, tform: &GlobalTransform) -> Extracted {
+    (
+        handle.clone_weak(),
+        TriangleUniform::new(tform.compute_matrix()),
+    )
+}
+
+/// How `extract_triangle_meshes` batched before `BatchBuffer`.
+fn fresh_batch(
+    mut commands: Commands,
+    mut previous_len: Local<usize>,
+    triangle_q: Query<(Entity, &TriangleMeshHandle, &GlobalTransform)>,
+) {
+    let mut batch = Vec::with_capacity(*previous_len);
+    for (entity, handle, tform) in triangle_q.iter() {
+        batch.push((entity, extracted(handle, tform)));
+    }
+    *previous_len = batch.len();
+    commands.insert_or_spawn_batch(batch);
+}
+
+fn reused_batch(
+    mut commands: Commands,
+    buffer: Local<BatchBuffer<Extracted>>,
+    triangle_q: Query<(Entity, &TriangleMeshHandle, &GlobalTransform)>,
+) {
+    let mut batch = buffer.take();
+    for (entity, handle, tform) in triangle_q.iter() {
+        batch.push((entity, extracted(handle, tform)));
+    }
+    buffer.insert_or_spawn(&mut commands, batch);
+}
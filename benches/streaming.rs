@@ -0,0 +1,162 @@
+//! Frame times of a 2k-vertex mesh deformed every frame, rebuilt through `Assets<Mesh>`
+//! against written straight into its buffer as a `StreamingTriangle`. Needs a GPU adapter;
+//! pick the backend with `WGPU_BACKEND` as for the render tests.
+//!
+//! `cargo bench --bench streaming`
+
+use std::time::{Duration, Instant};
+
+use bevy::asset::AssetPlugin;
+use bevy::core::CorePlugin;
+use bevy::core_pipeline::CorePipelinePlugin;
+use bevy::prelude::*;
+use bevy::render::options::{Backends, WgpuOptions};
+use bevy::render::RenderPlugin;
+use bevy::transform::TransformPlugin;
+use bevy::window::WindowPlugin;
+use bevy_experiments::render::plugin::TriangleRenderPlugin;
+use bevy_experiments::{
+    wgpu, StreamingTriangle, StreamingVertex, TriangleMeshHandle, TrianglePlugin,
+};
+
+/// 667 unindexed triangles, so 2001 vertices.
+const TRIANGLES: usize = 667;
+const WARMUP_FRAMES: usize = 10;
+const FRAMES: usize = 300;
+
+/// Marks the mesh `deform_mesh` rebuilds every frame.
+#[derive(Component)]
+struct Deforming;
+
+fn main() {
+    println!("{} vertices deformed every frame:", TRIANGLES * 3);
+    report("Assets<Mesh> rebuild", measure(spawn_mesh, deform_mesh));
+    report(
+        "StreamingTriangle",
+        measure(spawn_streaming, deform_streaming),
+    );
+}
+
+fn report(name: &str, frame_time: Duration) {
+    let ms = frame_time.as_secs_f64() * 1000.0;
+    let budget = if ms <= 1000.0 / 60.0 {
+        "within"
+    } else {
+        "over"
+    };
+    println!(
+        "{:>24}: {:>8.3} ms per frame, {} a 60 fps budget",
+        name, ms, budget
+    );
+}
+
+/// The average time of an `App::update` with `spawn`'s entity deformed by `deform` each
+/// frame, after a few frames for pipelines to compile.
+fn measure<M1, M2>(
+    spawn: impl IntoSystem<(), (), M1>,
+    deform: impl IntoSystem<(), (), M2>,
+) -> Duration {
+    let backends = match std::env::var("WGPU_BACKEND") {
+        Ok(_) => WgpuOptions::default().backends.unwrap(),
+        Err(_) => Backends::all(),
+    };
+    let mut app = App::new();
+    app.add_plugin(CorePlugin)
+        .add_plugin(TransformPlugin)
+        .add_plugin(AssetPlugin)
+        .add_plugin(WindowPlugin {
+            add_primary_window: false,
+            exit_on_close: false,
+        })
+        .init_resource::<Input<MouseButton>>()
+        .insert_resource(WgpuOptions {
+            backends: Some(backends),
+            ..Default::default()
+        })
+        .add_plugin(RenderPlugin)
+        .add_plugin(CorePipelinePlugin)
+        .add_plugin(TrianglePlugin)
+        .add_plugin(TriangleRenderPlugin::default())
+        // Visibility is only worked out for entities some camera can see.
+        .add_startup_system(|mut commands: Commands| {
+            commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+        })
+        .add_startup_system(spawn)
+        .add_system(deform);
+    for _ in 0..WARMUP_FRAMES {
+        app.update();
+    }
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        app.update();
+    }
+    start.elapsed() / FRAMES as u32
+}
+
+/// A row of triangles rippling with `time`.
+fn vertices(time: f32) -> impl Iterator<Item = StreamingVertex> {
+    (0..TRIANGLES * 3).map(move |i| {
+        let (triangle, corner) = ((i / 3) as f32, i % 3);
+        let x = triangle + [0.0, 1.0, 0.5][corner];
+        let y = [0.0, 0.0, 1.0][corner] + (x * 0.1 + time).sin();
+        StreamingVertex {
+            color: [1.0; 4],
+            position: [x, y, 0.0],
+            uv: [0.0; 2],
+        }
+    })
+}
+
+fn spawn_mesh(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>) {
+    let mut mesh = Mesh::new(wgpu::PrimitiveTopology::TriangleList);
+    let (positions, colors): (Vec<_>, Vec<_>) =
+        vertices(0.0).map(|v| (v.position, v.color)).unzip();
+    mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.set_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+    mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0.0f32; 2]; TRIANGLES * 3]);
+    commands
+        .spawn_bundle((
+            TriangleMeshHandle(meshes.add(mesh)),
+            Transform::default(),
+            GlobalTransform::default(),
+            Visibility::default(),
+            ComputedVisibility::default(),
+        ))
+        .insert(Deforming);
+}
+
+fn deform_mesh(
+    time: Res<Time>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mesh_q: Query<&TriangleMeshHandle, With<Deforming>>,
+) {
+    for handle in mesh_q.iter() {
+        let positions = vertices(time.seconds_since_startup() as f32)
+            .map(|v| v.position)
+            .collect::<Vec<_>>();
+        if let Some(mesh) = meshes.get_mut(&handle.0) {
+            mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        }
+    }
+}
+
+fn spawn_streaming(mut commands: Commands) {
+    commands.spawn_bundle((
+        StreamingTriangle {
+            vertices: vertices(0.0).collect(),
+        },
+        Transform::default(),
+        GlobalTransform::default(),
+        Visibility::default(),
+        ComputedVisibility::default(),
+    ));
+}
+
+fn deform_streaming(time: Res<Time>, mut streaming_q: Query<&mut StreamingTriangle>) {
+    for mut streaming in streaming_q.iter_mut() {
+        streaming.vertices.clear();
+        streaming
+            .vertices
+            .extend(vertices(time.seconds_since_startup() as f32));
+    }
+}
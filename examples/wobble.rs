@@ -0,0 +1,169 @@
+//! A triangle drawn with a custom shader that reads a per-triangle `Wobble` through
+//! `TriangleExtras`, on top of the built-in pipeline's bindings.
+
+use bevy::core_pipeline::Transparent2d;
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::render::render_phase::{AddRenderCommand, DrawFunctions, RenderPhase, SetItemPipeline};
+use bevy::render::render_resource::std140::AsStd140;
+use bevy::render::render_resource::{
+    BindGroupLayout, RenderPipelineCache, RenderPipelineDescriptor, SpecializedPipeline,
+    SpecializedPipelines,
+};
+use bevy::render::{RenderApp, RenderStage};
+use bevy_experiments::render::draw::{
+    DrawTriangle, DrawTriangleMesh, SetMeshBindGroup, SetViewBindGroup,
+};
+use bevy_experiments::render::extras::{SetExtrasBindGroup, TriangleExtras, TriangleExtrasLayout};
+use bevy_experiments::render::pipeline::{
+    TrianglePipeline, TrianglePipelineKey, TrianglePipelineSpecializations,
+};
+use bevy_experiments::render::plugin::{TriangleRenderPlugin, TriangleRenderSystem};
+use bevy_experiments::{Triangle, TriangleBundle, TrianglePlugin};
+
+const WOBBLE_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0x5d2b8a0e4c7f1936);
+
+/// How far, in pixels, the triangle's vertices sway from side to side.
+#[derive(Clone, Component, AsStd140)]
+struct Wobble {
+    amount: f32,
+}
+
+fn main() {
+    App::new()
+        .add_plugins(DefaultPlugins)
+        .insert_resource(Msaa { samples: 4 })
+        .insert_resource(ClearColor(Color::rgb(0.9, 0.9, 0.9)))
+        .add_plugin(TrianglePlugin)
+        .add_plugin(TriangleRenderPlugin::default())
+        .add_plugin(TriangleExtras::<Wobble>::default())
+        .add_plugin(WobblePlugin)
+        .add_startup_system(setup)
+        .add_system(pulse)
+        .run();
+}
+
+fn setup(mut commands: Commands) {
+    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+    commands
+        .spawn_bundle(TriangleBundle::new(
+            Triangle::side(500.0).with_rgba([0.2, 0.4, 1.0, 0.9]),
+        ))
+        .insert(Wobble { amount: 0.0 })
+        .insert(Name::new("Wobbly triangle"));
+}
+
+/// Swells the wobble and lets it settle again every few seconds.
+fn pulse(time: Res<Time>, mut wobble_q: Query<&mut Wobble>) {
+    let amount = 40.0 * (time.seconds_since_startup() as f32 * 0.5).sin().abs();
+    for mut wobble in wobble_q.iter_mut() {
+        wobble.amount = amount;
+    }
+}
+
+/// Registers the shader and swaps the pipeline and draw function of wobbly triangles'
+/// phase items once the built-in queue has added them.
+struct WobblePlugin;
+
+impl Plugin for WobblePlugin {
+    fn build(&self, app: &mut App) {
+        app.world
+            .get_resource_mut::<Assets<Shader>>()
+            .unwrap()
+            .set_untracked(
+                WOBBLE_SHADER_HANDLE,
+                Shader::from_wgsl(include_str!("wobble.wgsl")),
+            );
+        app.sub_app_mut(RenderApp)
+            .init_resource::<WobblePipeline>()
+            .init_resource::<SpecializedPipelines<WobblePipeline>>()
+            .add_render_command::<Transparent2d, DrawWobblyTriangle>()
+            .add_system_to_stage(
+                RenderStage::Queue,
+                queue_wobbly_triangles.after(TriangleRenderSystem::QueueTriangles),
+            );
+    }
+}
+
+type DrawWobblyTriangle = (
+    SetItemPipeline,
+    SetViewBindGroup<0>,
+    SetMeshBindGroup<1>,
+    SetExtrasBindGroup<Wobble, 2>,
+    DrawTriangleMesh,
+);
+
+/// `TrianglePipeline` with `Wobble`'s layout pushed after the mesh layout, and the
+/// shader swapped for `wobble.wgsl`.
+struct WobblePipeline {
+    triangle: TrianglePipeline,
+    extras_layout: BindGroupLayout,
+}
+
+impl FromWorld for WobblePipeline {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            triangle: world.get_resource::<TrianglePipeline>().unwrap().clone(),
+            extras_layout: world
+                .get_resource::<TriangleExtrasLayout<Wobble>>()
+                .unwrap()
+                .layout
+                .clone(),
+        }
+    }
+}
+
+impl SpecializedPipeline for WobblePipeline {
+    type Key = TrianglePipelineKey;
+
+    fn specialize(&self, key: Self::Key) -> RenderPipelineDescriptor {
+        let mut descriptor = self.triangle.specialize(key);
+        descriptor
+            .layout
+            .get_or_insert_with(Vec::new)
+            .push(self.extras_layout.clone());
+        descriptor.vertex.shader = WOBBLE_SHADER_HANDLE.typed();
+        if let Some(fragment) = &mut descriptor.fragment {
+            fragment.shader = WOBBLE_SHADER_HANDLE.typed();
+        }
+        descriptor
+    }
+}
+
+/// Redirects the built-in draws of triangles with a `Wobble` to `WobblePipeline`, keeping
+/// the key they were queued with. Textured and instanced triangles need more of the
+/// built-in shader than `wobble.wgsl` has and are left alone, as is any triangle whose
+/// wobbly pipeline is still compiling.
+fn queue_wobbly_triangles(
+    draw_functions: Res<DrawFunctions<Transparent2d>>,
+    pipeline: Res<WobblePipeline>,
+    mut pipelines: ResMut<SpecializedPipelines<WobblePipeline>>,
+    mut pipeline_cache: ResMut<RenderPipelineCache>,
+    specializations: Res<TrianglePipelineSpecializations>,
+    wobble_q: Query<(), With<Wobble>>,
+    mut phase_q: Query<&mut RenderPhase<Transparent2d>>,
+) {
+    let draw_functions = draw_functions.read();
+    let draw_triangle = draw_functions.get_id::<DrawTriangle>().unwrap();
+    let draw_wobbly_triangle = draw_functions.get_id::<DrawWobblyTriangle>().unwrap();
+    for mut phase in phase_q.iter_mut() {
+        for item in &mut phase.items {
+            if item.draw_function != draw_triangle || wobble_q.get(item.entity).is_err() {
+                continue;
+            }
+            let key = match specializations.key(item.pipeline) {
+                Some(key) => key,
+                None => continue,
+            };
+            if key.intersects(TrianglePipelineKey::TEXTURED | TrianglePipelineKey::INSTANCED) {
+                continue;
+            }
+            let id = pipelines.specialize(&mut pipeline_cache, &pipeline, key);
+            if pipeline_cache.get(id).is_some() {
+                item.pipeline = id;
+                item.draw_function = draw_wobbly_triangle;
+            }
+        }
+    }
+}
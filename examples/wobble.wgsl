@@ -0,0 +1,60 @@
+// The vertex layout and the first two bind groups are `TrianglePipeline`'s, for untextured,
+// uninstanced keys. Structs only declare the fields this shader reads from the start of
+// the built-in shader's.
+struct View {
+    view_proj: mat4x4<f32>;
+};
+
+struct Globals {
+    time: f32;
+};
+
+struct Mesh {
+    transform: mat4x4<f32>;
+    tint: vec4<f32>;
+    opacity: f32;
+};
+
+// `Wobble`, from `TriangleExtrasLayout<Wobble>` at group 2.
+struct Wobble {
+    amount: f32;
+};
+
+struct Vertex {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] color: vec4<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] color: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> view: View;
+
+[[group(0), binding(1)]]
+var<uniform> globals: Globals;
+
+[[group(1), binding(0)]]
+var<uniform> mesh: Mesh;
+
+[[group(2), binding(0)]]
+var<uniform> wobble: Wobble;
+
+[[stage(vertex)]]
+fn vertex(in: Vertex) -> VertexOutput {
+    var world_position = mesh.transform * vec4<f32>(in.position, 1.0);
+    world_position.x = world_position.x
+        + sin(world_position.y * 0.02 + globals.time * 3.0) * wobble.amount;
+
+    var out: VertexOutput;
+    out.clip_position = view.view_proj * world_position;
+    out.color = in.color * mesh.tint;
+    return out;
+}
+
+[[stage(fragment)]]
+fn fragment(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(in.color.rgb, in.color.a * mesh.opacity);
+}
@@ -0,0 +1,3841 @@
+//! Triangles drawn with a custom render pipeline, see `examples/triangle.rs`.
+
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::ecs::system::Command;
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::render::mesh::{Indices, VertexAttributeValues};
+use bevy::tasks::{AsyncComputeTaskPool, Task};
+use bevy::transform::TransformSystem;
+use bevy::utils::{BoxedFuture, HashMap};
+use futures_lite::future;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+/// `wgpu` as re-exported by Bevy, so its types always match the version Bevy renders with.
+/// The public API uses `wgpu::PrimitiveTopology`, `Face`, `FrontFace`, `FilterMode`,
+/// `AddressMode`, `IndexFormat` and `SamplerDescriptor` from here; downstream code can name
+/// them through this module instead of depending on `wgpu` itself.
+pub use bevy::render::render_resource as wgpu;
+
+/// A single triangle in local space.
+///
+/// `rgba` is linear color. The shader writes it unchanged to the `bevy_default` sRGB target,
+/// which does the linear-to-sRGB encoding, so prefer [`Triangle::with_color`] over passing sRGB
+/// values to [`Triangle::with_rgba`].
+#[derive(Clone, Debug, PartialEq, Component, Serialize, Deserialize)]
+pub struct Triangle {
+    a: Vec2,
+    b: Vec2,
+    c: Vec2,
+    rgba: [f32; 4],
+}
+
+pub use bundle::TriangleBundle;
+
+mod bundle {
+    // Bevy 0.6's `Bundle` derive `mem::forget`s each field after moving it out, and lint
+    // attributes on the struct don't reach the generated impl, so the allow is scoped to
+    // this module instead.
+    #![allow(clippy::forget_non_drop)]
+
+    use super::Triangle;
+    use bevy::prelude::*;
+
+    /// A `Triangle` with everything it needs to be drawn.
+    #[derive(Bundle, Clone)]
+    pub struct TriangleBundle {
+        pub triangle: Triangle,
+        pub transform: Transform,
+        pub global_transform: GlobalTransform,
+        pub visibility: Visibility,
+        pub computed_visibility: ComputedVisibility,
+    }
+
+    impl TriangleBundle {
+        pub fn new(triangle: Triangle) -> Self {
+            Self::with_transform(triangle, Transform::default())
+        }
+
+        pub fn at(triangle: Triangle, translation: Vec3) -> Self {
+            Self::with_transform(triangle, Transform::from_translation(translation))
+        }
+
+        pub fn with_transform(triangle: Triangle, transform: Transform) -> Self {
+            Self {
+                triangle,
+                transform,
+                global_transform: GlobalTransform::from(transform),
+                visibility: Visibility::default(),
+                computed_visibility: ComputedVisibility::default(),
+            }
+        }
+    }
+}
+
+/// The winding the built-in shapes put their front faces in: `Polygon` and `Ribbon`
+/// meshes, and `Triangle::facing`. Counter-clockwise unless `TriangleExtractPlugin` sets
+/// it from `TriangleRenderConfig::front_face`; `Triangle`s keep the winding they're
+/// given.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TriangleFrontFace(pub wgpu::FrontFace);
+
+impl Default for TriangleFrontFace {
+    fn default() -> Self {
+        Self(wgpu::FrontFace::Ccw)
+    }
+}
+
+/// The mesh an entity is drawn with.
+///
+/// A strong handle keeps the mesh alive while the component exists. Despawning the entity or
+/// removing the component frees it once no other strong handle is left, through Bevy's
+/// reference counting, with nothing to clean up by hand.
+///
+/// A weak handle leaves the lifetime to strong handles held elsewhere. Once the last is
+/// dropped the mesh is freed and the entity stops being drawn, until something replaces the
+/// handle; for `Triangle`s, `Polygon`s and `Ribbon`s that's their mesh system, with a strong
+/// handle, the next time they change or get `MeshDirty`.
+#[derive(Component)]
+pub struct TriangleMeshHandle(pub Handle<Mesh>);
+
+impl TriangleMeshHandle {
+    /// `handle` has to be strong, like the ones `Assets::add` returns; a weak one can be made
+    /// strong with `Handle::make_strong`.
+    pub fn new_strong(handle: &Handle<Mesh>) -> Self {
+        debug_assert!(
+            handle.is_strong(),
+            "TriangleMeshHandle::new_strong given a weak handle"
+        );
+        Self(handle.clone())
+    }
+
+    pub fn new_weak(handle: &Handle<Mesh>) -> Self {
+        Self(handle.clone_weak())
+    }
+
+    pub fn is_strong(&self) -> bool {
+        self.0.is_strong()
+    }
+
+    pub fn clone_weak(&self) -> Self {
+        Self(self.0.clone_weak())
+    }
+}
+
+/// Clips the triangle to a world-space rectangle, using the GPU scissor test rather than a
+/// stencil.
+#[derive(Clone, Copy, Debug, Component)]
+pub struct TriangleScissor(pub Rect<f32>);
+
+/// Draws the entity's mesh merged with those of other `TriangleBatched` entities, in as few
+/// draw calls as their order among everything else in `Transparent2d` allows, rather than in
+/// one of its own. For many small, plain triangles.
+///
+/// The mesh is copied into the batch every frame, moved by the entity's transform, so only its
+/// positions, colors and order count: textures, effects, `TriangleOpacity`, scissors and the
+/// like are ignored. Meshes that aren't triangle lists aren't drawn. See
+/// `TriangleRenderConfig::max_batch_vertices`.
+#[derive(Clone, Copy, Debug, Default, Component)]
+pub struct TriangleBatched;
+
+/// Marks a camera whose view draws into a `TriangleRenderConfig::HDR_FORMAT` target, e.g.
+/// in a pass of your own, so its triangles get pipelines for that format. Other views get
+/// pipelines for `TextureFormat::bevy_default()`.
+#[derive(Clone, Copy, Debug, Default, Component)]
+pub struct TriangleHdr;
+
+/// Layers the triangle explicitly instead of by z: higher orders draw on top, and z only
+/// breaks ties within an order. Triangles without one are order 0. z is clamped to
+/// ±`TriangleOrder::Z_RANGE`, which covers the default 2D camera, so no z lifts a
+/// triangle out of its order.
+///
+/// Order 0 sorts on world z like sprites do, so triangles and sprites interleave by z. Any
+/// other order draws above (or below) every sprite regardless of z.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Component)]
+pub struct TriangleOrder(pub i32);
+
+impl TriangleOrder {
+    pub const Z_RANGE: f32 = 1000.0;
+
+    /// The value sorted on in place of `z`. Orders are spaced by a power of two more than
+    /// twice `Z_RANGE` apart, so the sum stays exact where it matters and order 0 leaves z
+    /// within the range unchanged.
+    pub fn sort_z(order: Option<&Self>, z: f32) -> f32 {
+        let order = order.map_or(0, |order| order.0);
+        order as f32 * 2048.0 + z.clamp(-Self::Z_RANGE, Self::Z_RANGE)
+    }
+}
+
+/// A procedural fill pattern, computed from the UVs in the fragment shader. Patterns cut the
+/// color's alpha to zero between the stripes, squares or dots. Sizes are in UV units, i.e.
+/// fractions of the triangle's bounding box.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Component)]
+pub enum TrianglePattern {
+    #[default]
+    Solid,
+    /// `angle` in radians, 0 for vertical stripes.
+    Stripes {
+        spacing: f32,
+        angle: f32,
+    },
+    Checker {
+        size: f32,
+    },
+    Dots {
+        spacing: f32,
+        radius: f32,
+    },
+}
+
+impl TrianglePattern {
+    /// The pattern's parameters as packed into `TriangleFillUniform::pattern`.
+    pub fn params(&self) -> Vec4 {
+        match *self {
+            Self::Solid => Vec4::ZERO,
+            Self::Stripes { spacing, angle } => Vec4::new(spacing, angle, 0.0, 0.0),
+            Self::Checker { size } => Vec4::new(size, 0.0, 0.0, 0.0),
+            Self::Dots { spacing, radius } => Vec4::new(spacing, radius, 0.0, 0.0),
+        }
+    }
+}
+
+/// Marks the triangle's vertex colors as premultiplied by alpha, drawing it with
+/// `PREMULTIPLIED_ALPHA_BLENDING` instead of the `ALPHA_BLENDING` that straight (unmultiplied)
+/// colors need. Textures, edge and split colors and the global tint are still read as straight
+/// alpha and premultiplied in the shader.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Component)]
+pub struct PremultipliedVertexColor;
+
+/// Makes the triangle a cutout: fragments whose final alpha (after texture and pattern) is
+/// below the threshold are discarded, and the rest are drawn opaque without blending.
+#[derive(Clone, Copy, Debug, PartialEq, Component)]
+pub struct TriangleAlphaCutoff(pub f32);
+
+/// Keeps the triangle at least this many pixels across on screen, growing it about its local
+/// origin (the anchor) when the camera is zoomed out far enough to shrink it below that.
+/// Measured on the first camera with a window. Only applies to `Triangle`s.
+#[derive(Clone, Copy, Debug, PartialEq, Component)]
+pub struct MinPixelSize(pub f32);
+
+/// Makes the triangle face the camera: extract swaps its rotation for the first windowed
+/// camera's, keeping its translation and scale. For labels and markers in scenes with a
+/// rotated camera. Picking still tests the unrotated triangle.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Component)]
+pub struct Billboard;
+
+/// Paints everything within `thickness` local units of the triangle's edges in `color`
+/// (linear) instead of the fill, in the same draw. Only applies to `Triangle`s.
+#[derive(Clone, Copy, Debug, PartialEq, Component)]
+pub struct TriangleEdgeColor {
+    pub color: [f32; 4],
+    pub thickness: f32,
+}
+
+/// Antialiases the triangle's edges in its shader, fading the alpha over the pixel nearest
+/// each edge, for smooth edges without MSAA. It works at any `Msaa` sample count, though it
+/// only softens the inside of the edge. Only applies to `Triangle`s.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Component)]
+pub struct SmoothEdges;
+
+/// Colors (linear) of the edges opposite `a`, `b` and `c`, blended across the fill in place of
+/// the vertex colors. Each is pure along its own edge, and the three average out at the
+/// centroid. Only applies to `Triangle`s.
+#[derive(Clone, Copy, Debug, PartialEq, Component)]
+pub struct TriangleEdgeColors(pub [[f32; 4]; 3]);
+
+/// Splits the fill in two along the line through `point` perpendicular to `normal`, both in
+/// local space: `color_a` (linear) behind the line, `color_b` on the side `normal` points to.
+/// The colors blend over `softness` local units across the line, or switch sharply at 0.
+#[derive(Clone, Copy, Debug, PartialEq, Component)]
+pub struct TriangleSplit {
+    pub point: Vec2,
+    pub normal: Vec2,
+    pub color_a: [f32; 4],
+    pub color_b: [f32; 4],
+    pub softness: f32,
+}
+
+impl TriangleSplit {
+    /// The line as packed into `TriangleFillUniform::split`.
+    pub fn params(&self) -> Vec4 {
+        let normal = self.normal.normalize_or_zero();
+        Vec4::new(normal.x, normal.y, normal.dot(self.point), self.softness)
+    }
+}
+
+/// Wipes the triangle in along `axis` in UV space: fragments further along it than
+/// `progress` (0 hides everything, 1 shows everything) are discarded. Animate `progress` for
+/// transitions.
+#[derive(Clone, Copy, Debug, PartialEq, Component)]
+pub struct TriangleReveal {
+    pub progress: f32,
+    pub axis: Vec2,
+}
+
+impl TriangleReveal {
+    /// As packed into `TriangleFillUniform::reveal`.
+    pub fn params(&self) -> Vec4 {
+        let axis = self.axis.normalize_or_zero();
+        Vec4::new(axis.x, axis.y, self.progress, 0.0)
+    }
+}
+
+/// Eats the triangle away through value noise over its UVs: fragments whose noise is below
+/// `progress` (0 keeps everything, 1 removes everything) are discarded, and those within
+/// `edge_width` above it are filled with `edge_color` (linear), like a burning rim.
+#[derive(Clone, Copy, Debug, PartialEq, Component)]
+pub struct TriangleDissolve {
+    pub progress: f32,
+    pub edge_width: f32,
+    pub edge_color: [f32; 4],
+}
+
+impl TriangleDissolve {
+    /// Noise lattice cells across the UV square.
+    pub const NOISE_CELLS: f32 = 8.0;
+
+    /// As packed into `TriangleFillUniform::dissolve`.
+    pub fn params(&self) -> Vec4 {
+        Vec4::new(
+            self.progress,
+            self.edge_width.max(0.0),
+            Self::NOISE_CELLS,
+            0.0,
+        )
+    }
+}
+
+/// Fills the triangle's back face with this color (linear) instead of the vertex colors.
+/// Triangles with one are drawn without culling, whatever the plugin's `cull_mode`.
+#[derive(Clone, Copy, Debug, PartialEq, Component)]
+pub struct TriangleBackColor(pub [f32; 4]);
+
+/// Multiplies the triangle's final alpha, on top of its vertex colors, texture and pattern.
+/// Triangles without one are fully opaque (1.0). Doesn't fade the triangle's glow or shadow.
+#[derive(Clone, Copy, Debug, PartialEq, Component)]
+pub struct TriangleOpacity(pub f32);
+
+impl Default for TriangleOpacity {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+/// Puts the triangle in a group whose `TriangleGroupEffect` in `TriangleGroupEffects`
+/// applies to every member, so a whole group fades or tints without touching each entity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Component)]
+pub struct TriangleGroup(pub u32);
+
+/// Applied on top of the member's own color and `TriangleOpacity`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TriangleGroupEffect {
+    /// Linear color multiplied into the final color, like `TriangleGlobalTint`.
+    pub tint: [f32; 4],
+    pub opacity: f32,
+}
+
+impl Default for TriangleGroupEffect {
+    fn default() -> Self {
+        Self {
+            tint: [1.0; 4],
+            opacity: 1.0,
+        }
+    }
+}
+
+/// The effect for each `TriangleGroup` id. Groups without an entry are drawn unchanged.
+#[derive(Clone, Debug, Default)]
+pub struct TriangleGroupEffects(pub HashMap<u32, TriangleGroupEffect>);
+
+impl TriangleGroupEffects {
+    pub fn get(&self, group: u32) -> Option<&TriangleGroupEffect> {
+        self.0.get(&group)
+    }
+
+    /// The group's effect, inserting the default (no change) if it has none yet.
+    pub fn get_mut(&mut self, group: u32) -> &mut TriangleGroupEffect {
+        self.0.entry(group).or_default()
+    }
+}
+
+/// Multiplies the UVs a `TriangleTexture` is sampled at, before any `TriangleUvTransform`,
+/// so `Vec2::splat(2.0)` fits the texture in twice each way. Past 1 the sampler's
+/// `address_mode` decides what's drawn; use `AddressMode::Repeat` to tile.
+#[derive(Clone, Copy, Debug, PartialEq, Component)]
+pub struct TriangleUvScale(pub Vec2);
+
+impl Default for TriangleUvScale {
+    fn default() -> Self {
+        Self(Vec2::ONE)
+    }
+}
+
+/// Scrolls the UVs a `TriangleTexture` is sampled at by this many UV units per second, after
+/// `TriangleUvScale` and `TriangleUvTransform`, for flowing water or lava. Driven by the
+/// shader's global time, so it costs no CPU work. The offset wraps every whole unit, which
+/// only looks seamless with `AddressMode::Repeat`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Component)]
+pub struct TriangleUvScroll(pub Vec2);
+
+/// Rotates and flips the UVs a `TriangleTexture` is sampled at, about the middle of the UV
+/// square, without rebuilding the mesh. Flips apply first, then `rotation` (radians,
+/// counterclockwise).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Component)]
+pub struct TriangleUvTransform {
+    pub rotation: f32,
+    pub flip_x: bool,
+    pub flip_y: bool,
+}
+
+impl TriangleUvTransform {
+    pub fn matrix(&self) -> Mat3 {
+        let flip = Vec2::new(
+            if self.flip_x { -1.0 } else { 1.0 },
+            if self.flip_y { -1.0 } else { 1.0 },
+        );
+        Mat3::from_translation(Vec2::splat(0.5))
+            * Mat3::from_angle(self.rotation)
+            * Mat3::from_scale(flip)
+            * Mat3::from_translation(Vec2::splat(-0.5))
+    }
+}
+
+/// A drop shadow: the triangle's silhouette moved by `offset` world units, filled with `color`
+/// (linear) and feathered outward over `blur` local units. Drawn behind the triangle and its
+/// glow. Only applies to `Triangle`s.
+#[derive(Clone, Copy, Debug, PartialEq, Component)]
+pub struct TriangleShadow {
+    pub offset: Vec2,
+    pub color: [f32; 4],
+    pub blur: f32,
+}
+
+/// A named color scale for mapping scalars in `[0, 1]` to colors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Colormap {
+    Viridis,
+    Turbo,
+    Grayscale,
+}
+
+impl Colormap {
+    /// Evenly spaced sRGB stops, interpolated linearly between.
+    fn stops(&self) -> &'static [u32] {
+        match self {
+            Self::Viridis => &[
+                0x440154, 0x472d7b, 0x3b528b, 0x2c728e, 0x21918c, 0x28ae80, 0x5ec962, 0xaddc30,
+                0xfde725,
+            ],
+            Self::Turbo => &[
+                0x30123b, 0x4662d7, 0x36aaf9, 0x1ae4b6, 0x72fe5e, 0xc7ef34, 0xfbb938, 0xf56918,
+                0xc92903, 0x7a0403,
+            ],
+            Self::Grayscale => &[0x000000, 0xffffff],
+        }
+    }
+
+    /// The linear color for `value`, clamped to `[0, 1]`.
+    pub fn sample(&self, value: f32) -> [f32; 4] {
+        let stops = self.stops();
+        let scaled = value.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+        let i = (scaled.floor() as usize).min(stops.len() - 2);
+        let srgb = |hex: u32| {
+            Vec3::new(
+                (hex >> 16) as u8 as f32,
+                (hex >> 8) as u8 as f32,
+                hex as u8 as f32,
+            ) / 255.0
+        };
+        let rgb = srgb(stops[i]).lerp(srgb(stops[i + 1]), scaled - i as f32);
+        Color::rgb(rgb.x, rgb.y, rgb.z).as_linear_rgba_f32()
+    }
+}
+
+/// Colors the vertices by mapping `values` (one per vertex, in `[0, 1]`) through `colormap`
+/// when the mesh is built, replacing the triangle's own color.
+#[derive(Clone, Copy, Debug, PartialEq, Component)]
+pub struct TriangleScalarColor {
+    pub values: [f32; 3],
+    pub colormap: Colormap,
+}
+
+impl TriangleScalarColor {
+    pub fn colors(&self) -> [[f32; 4]; 3] {
+        self.values.map(|value| self.colormap.sample(value))
+    }
+}
+
+/// Which point of the triangle's bounding box sits at the transform origin, and so acts as the
+/// pivot for rotation and scale. Applied when the mesh is built.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Component)]
+pub enum TriangleAnchor {
+    #[default]
+    Center,
+    BottomLeft,
+    /// A point in the triangle's local space.
+    Custom(Vec2),
+}
+
+/// A soft halo drawn behind the triangle: a copy grown outward by `radius` local units,
+/// filled with `color` (linear) and fading to nothing at its outer edge. It is blended
+/// additively, so it brightens whatever is behind it. Only applies to `Triangle`s.
+#[derive(Clone, Copy, Debug, PartialEq, Component)]
+pub struct TriangleGlow {
+    pub color: [f32; 4],
+    pub radius: f32,
+}
+
+/// Added once the triangle's mesh has been uploaded to the GPU and it can actually be drawn,
+/// usually a frame or two after spawning.
+#[derive(Clone, Copy, Debug, Default, Component)]
+pub struct TriangleReady;
+
+/// Rounds the triangle's corners with arcs of this radius, in local units. Clamped to the
+/// inradius, where the arcs meet and the triangle becomes its inscribed circle.
+///
+/// Glows, shadows and edge colors expect the plain three-vertex mesh, so they don't follow
+/// the rounded outline.
+#[derive(Clone, Copy, Debug, PartialEq, Component)]
+pub struct TriangleCornerRadius(pub f32);
+
+impl TriangleCornerRadius {
+    /// Segments per corner arc used by `triangle_mesh_system`; see
+    /// [`Triangle::rounded_mesh`] for other counts.
+    pub const SEGMENTS: u32 = 8;
+}
+
+/// How the mesh builder assigns UVs. Triangles without one get
+/// `PlanarFromVertices { bounds: None }`. `TriangleTangents` still follow the default UVs.
+#[derive(Clone, Copy, Debug, PartialEq, Component)]
+pub enum UvMode {
+    /// Every vertex at `[0, 0]`, for untextured triangles.
+    None,
+    /// `bounds` in local space (y up, so `bottom` is the smaller y) mapped onto the unit
+    /// square, with v pointing down like image rows. `None` uses the triangle's own bounding
+    /// box, so the vertices get distinct UVs.
+    PlanarFromVertices { bounds: Option<Rect<f32>> },
+    /// UVs for `a`, `b` and `c`; any other vertices interpolate them.
+    Explicit([Vec2; 3]),
+}
+
+impl Default for UvMode {
+    fn default() -> Self {
+        Self::PlanarFromVertices { bounds: None }
+    }
+}
+
+/// Marker to build the triangle's mesh with tangents, see [`Triangle::mesh_with_tangents`].
+#[derive(Clone, Copy, Default, Component)]
+pub struct TriangleTangents;
+
+/// An image sampled across the triangle's UVs and multiplied with its color.
+#[derive(Clone, Component)]
+pub struct TriangleTexture(pub Handle<Image>);
+
+impl TriangleTexture {
+    pub fn clone_weak(&self) -> Self {
+        Self(self.0.clone_weak())
+    }
+}
+
+/// How a `TriangleTexture`'s alpha channel is read, as a component next to it. Sampling a
+/// straight texture with the wrong mode darkens the fringes where alpha is soft.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Component)]
+pub enum TextureAlphaMode {
+    /// Color and alpha are independent, as most PNGs are stored.
+    #[default]
+    Straight,
+    /// Color is already multiplied by alpha.
+    Premultiplied,
+    /// Alpha is ignored and read as 1.
+    Opaque,
+}
+
+/// How textures are filtered and addressed. The plugin holds the default; adding this as a
+/// component next to a `TriangleTexture` overrides it for that triangle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Component)]
+pub struct TriangleSamplerConfig {
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    pub address_mode: wgpu::AddressMode,
+}
+
+impl Default for TriangleSamplerConfig {
+    /// Nearest filtering, so pixel art stays crisp.
+    fn default() -> Self {
+        Self {
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            address_mode: wgpu::AddressMode::ClampToEdge,
+        }
+    }
+}
+
+impl TriangleSamplerConfig {
+    pub fn linear() -> Self {
+        Self {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        }
+    }
+
+    /// E.g. `AddressMode::Repeat` to tile textures scaled up with `TriangleUvScale`.
+    pub fn with_address_mode(mut self, address_mode: wgpu::AddressMode) -> Self {
+        self.address_mode = address_mode;
+        self
+    }
+
+    pub fn descriptor(&self) -> wgpu::SamplerDescriptor<'static> {
+        wgpu::SamplerDescriptor {
+            label: Some("triangle texture sampler"),
+            address_mode_u: self.address_mode,
+            address_mode_v: self.address_mode,
+            address_mode_w: self.address_mode,
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            ..Default::default()
+        }
+    }
+}
+
+impl Triangle {
+    /// An equilateral triangle `len` on a side, pointing up and centered in its bounding
+    /// box, wound counter-clockwise; see `facing` for clockwise front faces.
+    pub fn side(len: f32) -> Self {
+        let height = (len.powi(2) - (len / 2.0).powi(2)).sqrt();
+        Self {
+            a: Vec2::new(0.0, height / 2.0),
+            b: Vec2::new(-len / 2.0, -height / 2.0),
+            c: Vec2::new(len / 2.0, -height / 2.0),
+            rgba: [0.5, 0.5, 0.5, 0.5],
+        }
+    }
+
+    /// The same triangle with `b` and `c` swapped if it doesn't wind `front_face` already,
+    /// e.g. `Triangle::side(10.0).facing(wgpu::FrontFace::Cw)` for a clockwise front face.
+    /// Degenerate triangles are left as they are.
+    pub fn facing(self, front_face: wgpu::FrontFace) -> Self {
+        let cross = (self.b - self.a).perp_dot(self.c - self.a);
+        let flip = match front_face {
+            wgpu::FrontFace::Ccw => cross < 0.0,
+            wgpu::FrontFace::Cw => cross > 0.0,
+        };
+        match flip {
+            true => Self {
+                b: self.c,
+                c: self.b,
+                ..self
+            },
+            false => self,
+        }
+    }
+
+    pub fn with_rgba(self, rgba: [f32; 4]) -> Self {
+        Self { rgba, ..self }
+    }
+
+    pub fn with_color(self, color: Color) -> Self {
+        self.with_rgba(color.as_linear_rgba_f32())
+    }
+
+    /// The color as a `Color`, reading `rgba` as linear.
+    pub fn color(&self) -> Color {
+        let [r, g, b, a] = self.rgba;
+        Color::rgba_linear(r, g, b, a)
+    }
+
+    pub fn set_color(&mut self, color: Color) {
+        self.rgba = color.as_linear_rgba_f32();
+    }
+
+    /// Whether every vertex coordinate and color channel is finite, i.e. safe to upload.
+    pub fn is_finite(&self) -> bool {
+        [self.a, self.b, self.c].iter().all(|v| v.is_finite())
+            && self.rgba.iter().all(|x| x.is_finite())
+    }
+
+    /// The triangle moved so `anchor` lands on the local origin.
+    pub fn anchored(&self, anchor: TriangleAnchor) -> Self {
+        let min = self.a.min(self.b).min(self.c);
+        let max = self.a.max(self.b).max(self.c);
+        let origin = match anchor {
+            TriangleAnchor::Center => (min + max) / 2.0,
+            TriangleAnchor::BottomLeft => min,
+            TriangleAnchor::Custom(point) => point,
+        };
+        Self {
+            a: self.a - origin,
+            b: self.b - origin,
+            c: self.c - origin,
+            ..self.clone()
+        }
+    }
+
+    /// Whether `point`, in local space, is inside the triangle or on its edges. Works for
+    /// either winding.
+    pub fn contains(&self, point: Vec2) -> bool {
+        let d1 = (self.b - self.a).perp_dot(point - self.a);
+        let d2 = (self.c - self.b).perp_dot(point - self.b);
+        let d3 = (self.a - self.c).perp_dot(point - self.c);
+        let has_neg = d1 < 0.0 || d2 < 0.0 || d3 < 0.0;
+        let has_pos = d1 > 0.0 || d2 > 0.0 || d3 > 0.0;
+        !(has_neg && has_pos)
+    }
+
+    /// The vertices `a`, `b` and `c` transformed into world space and projected onto the xy
+    /// plane. Ignores any `TriangleAnchor`; pass `anchored` vertices to include it.
+    pub fn world_vertices(&self, transform: &GlobalTransform) -> [Vec2; 3] {
+        let matrix = transform.compute_matrix();
+        [self.a, self.b, self.c].map(|p| matrix.transform_point3(p.extend(0.0)).truncate())
+    }
+
+    /// Linearly interpolates the vertices and color towards `other`.
+    pub fn lerp(&self, other: &Triangle, t: f32) -> Self {
+        let mut rgba = self.rgba;
+        rgba.iter_mut()
+            .zip(other.rgba)
+            .for_each(|(x, y)| *x += (y - *x) * t);
+        Self {
+            a: self.a.lerp(other.a, t),
+            b: self.b.lerp(other.b, t),
+            c: self.c.lerp(other.c, t),
+            rgba,
+        }
+    }
+
+    pub fn mesh(&self) -> Mesh {
+        let mut mesh = Mesh::new(wgpu::PrimitiveTopology::TriangleList);
+        mesh.set_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            [self.a, self.b, self.c]
+                .into_iter()
+                .map(|p| [p.x, p.y, 0.0])
+                .collect_vec(),
+        );
+        mesh.set_attribute(Mesh::ATTRIBUTE_COLOR, vec![self.rgba; 3]);
+        mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, self.planar_uvs().to_vec());
+        mesh
+    }
+
+    /// Like [`Triangle::mesh`], but with each corner cut off by an arc of `segments` segments
+    /// and the given `radius`, clamped to the inradius. An indexed `TriangleList` fanned from
+    /// the incenter, with the incenter first and then `segments + 1` points per corner. A
+    /// `radius` of 0 or less gives the plain mesh.
+    pub fn rounded_mesh(&self, radius: f32, segments: u32) -> Mesh {
+        let radius = radius.min(self.inradius());
+        if radius <= 0.0 || segments == 0 {
+            return self.mesh();
+        }
+        let corners = [self.a, self.b, self.c];
+        let mut points = vec![self.incenter()];
+        for i in 0..3 {
+            let corner = corners[i];
+            let to_prev = (corners[(i + 2) % 3] - corner).normalize();
+            let to_next = (corners[(i + 1) % 3] - corner).normalize();
+            let half_angle = to_prev.angle_between(to_next).abs() / 2.0;
+            let center = corner + (to_prev + to_next).normalize() * radius / half_angle.sin();
+            let tangent = radius / half_angle.tan();
+            let start = corner + to_prev * tangent - center;
+            let end = corner + to_next * tangent - center;
+            let sweep = start.angle_between(end);
+            points.extend((0..=segments).map(|step| {
+                let angle = sweep * step as f32 / segments as f32;
+                center + bevy::math::Mat2::from_angle(angle) * start
+            }));
+        }
+        let outline = points.len() as u32 - 1;
+        let indices = (0..outline)
+            .flat_map(|i| [0, i + 1, (i + 1) % outline + 1])
+            .collect_vec();
+
+        let mut mesh = Mesh::new(wgpu::PrimitiveTopology::TriangleList);
+        mesh.set_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            points.iter().map(|p| [p.x, p.y, 0.0]).collect_vec(),
+        );
+        mesh.set_attribute(Mesh::ATTRIBUTE_COLOR, vec![self.rgba; points.len()]);
+        mesh.set_attribute(
+            Mesh::ATTRIBUTE_UV_0,
+            points.iter().map(|p| self.planar_uv(*p)).collect_vec(),
+        );
+        mesh.set_indices(Some(mesh_indices(points.len(), indices)));
+        mesh
+    }
+
+    /// A prism `depth` thick, centered on `z = 0`: the triangle as front (`+z`) and back
+    /// (`-z`) caps joined by three quads. Vertices aren't shared between faces, so each has
+    /// its face's outward `ATTRIBUTE_NORMAL`. An indexed `TriangleList` with positions, normals
+    /// and UVs, the layout Bevy's lit pipelines expect; it has no colors, so it won't draw
+    /// through the triangle pipeline.
+    ///
+    /// Caps use the planar UVs. Sides run u along the edge and v from front to back.
+    pub fn extrude(&self, depth: f32) -> Mesh {
+        let (front, back) = (depth / 2.0, -depth / 2.0);
+        // Wind counter-clockwise so the cross products below point out of the prism.
+        let corners = match (self.b - self.a).perp_dot(self.c - self.a) < 0.0 {
+            true => [self.a, self.c, self.b],
+            false => [self.a, self.b, self.c],
+        };
+        let mut positions = Vec::with_capacity(18);
+        let mut normals = Vec::with_capacity(18);
+        let mut uvs = Vec::with_capacity(18);
+        let mut indices = Vec::with_capacity(24);
+        for (z, normal, order) in [(front, 1.0, [0, 1, 2]), (back, -1.0, [0, 2, 1])] {
+            let start = positions.len() as u32;
+            for p in corners {
+                positions.push([p.x, p.y, z]);
+                normals.push([0.0, 0.0, normal]);
+                uvs.push(self.planar_uv(p));
+            }
+            indices.extend(order.map(|i| start + i));
+        }
+        for i in 0..3 {
+            let (p, q) = (corners[i], corners[(i + 1) % 3]);
+            let edge = q - p;
+            let normal = Vec2::new(edge.y, -edge.x).normalize_or_zero();
+            let start = positions.len() as u32;
+            for (point, z, uv) in [
+                (p, front, [0.0, 0.0]),
+                (q, front, [1.0, 0.0]),
+                (q, back, [1.0, 1.0]),
+                (p, back, [0.0, 1.0]),
+            ] {
+                positions.push([point.x, point.y, z]);
+                normals.push([normal.x, normal.y, 0.0]);
+                uvs.push(uv);
+            }
+            indices.extend([0, 3, 2, 0, 2, 1].map(|i| start + i));
+        }
+
+        let mut mesh = Mesh::new(wgpu::PrimitiveTopology::TriangleList);
+        mesh.set_indices(Some(mesh_indices(positions.len(), indices)));
+        mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh
+    }
+
+    /// Same as [`Triangle::mesh`] plus `ATTRIBUTE_TANGENT`, for normal-mapped shaders.
+    pub fn mesh_with_tangents(&self) -> Mesh {
+        let mut mesh = self.mesh();
+        mesh.set_attribute(Mesh::ATTRIBUTE_TANGENT, vec![self.tangent().to_array(); 3]);
+        mesh
+    }
+
+    /// The tangent of the flat face, pointing along increasing u, with the bitangent sign in `w`
+    /// (the face normal is +Z). Falls back to +X if the UVs are degenerate.
+    pub fn tangent(&self) -> Vec4 {
+        let uvs = self.planar_uvs().map(Vec2::from);
+        let (edge1, edge2) = (self.b - self.a, self.c - self.a);
+        let (duv1, duv2) = (uvs[1] - uvs[0], uvs[2] - uvs[0]);
+        let det = duv1.x * duv2.y - duv2.x * duv1.y;
+        if det.abs() <= f32::EPSILON {
+            return Vec4::new(1.0, 0.0, 0.0, 1.0);
+        }
+        let tangent = ((edge1 * duv2.y - edge2 * duv1.y) / det).normalize_or_zero();
+        let bitangent = (edge2 * duv1.x - edge1 * duv2.x) / det;
+        // cross(+Z, tangent) in the xy plane.
+        let handedness = match tangent.perp().dot(bitangent) < 0.0 {
+            true => -1.0,
+            false => 1.0,
+        };
+        tangent.extend(0.0).extend(handedness)
+    }
+
+    /// The average of the vertices, where the medians meet.
+    pub fn centroid(&self) -> Vec2 {
+        (self.a + self.b + self.c) / 3.0
+    }
+
+    /// Moves the vertices towards (`factor < 1`) or away from the centroid, in local space. Unlike
+    /// `Transform::scale` this grows the triangle in place whatever its anchor.
+    pub fn scale_about_centroid(&mut self, factor: f32) {
+        let centroid = self.centroid();
+        for p in [&mut self.a, &mut self.b, &mut self.c] {
+            *p = centroid + (*p - centroid) * factor;
+        }
+    }
+
+    /// The center of the inscribed circle, the one point equally far from every edge.
+    pub fn incenter(&self) -> Vec2 {
+        let [bc, ca, ab] = self.edge_lengths();
+        (self.a * bc + self.b * ca + self.c * ab) / (bc + ca + ab)
+    }
+
+    /// Radius of the inscribed circle; 0 for degenerate triangles.
+    pub fn inradius(&self) -> f32 {
+        let perimeter: f32 = self.edge_lengths().iter().sum();
+        match perimeter > 0.0 {
+            true => 2.0 * self.area() / perimeter,
+            false => 0.0,
+        }
+    }
+
+    /// Distances from `a`, `b` and `c` to their opposite edges.
+    pub fn heights(&self) -> [f32; 3] {
+        let double_area = 2.0 * self.area();
+        self.edge_lengths()
+            .map(|len| double_area / len.max(f32::EPSILON))
+    }
+
+    /// The weights of `a`, `b` and `c` that make up `point`.
+    fn barycentric(&self, point: Vec2) -> Vec3 {
+        let double_area = (self.b - self.a).perp_dot(self.c - self.a);
+        if double_area.abs() <= f32::EPSILON {
+            return Vec3::new(1.0, 0.0, 0.0);
+        }
+        let wb = (point - self.a).perp_dot(self.c - self.a) / double_area;
+        let wc = (self.b - self.a).perp_dot(point - self.a) / double_area;
+        Vec3::new(1.0 - wb - wc, wb, wc)
+    }
+
+    fn area(&self) -> f32 {
+        (self.b - self.a).perp_dot(self.c - self.a).abs() / 2.0
+    }
+
+    /// Lengths of the edges opposite `a`, `b` and `c`.
+    pub fn edge_lengths(&self) -> [f32; 3] {
+        [
+            self.b.distance(self.c),
+            self.c.distance(self.a),
+            self.a.distance(self.b),
+        ]
+    }
+
+    /// Interior angles at `a`, `b` and `c`, in radians. They sum to pi unless the triangle is
+    /// degenerate, where coincident vertices get an angle of 0.
+    pub fn angles(&self) -> [f32; 3] {
+        let angle = |p: Vec2, q: Vec2, r: Vec2| {
+            let (u, v) = (q - p, r - p);
+            match u.length_squared() > 0.0 && v.length_squared() > 0.0 {
+                true => u.angle_between(v).abs(),
+                false => 0.0,
+            }
+        };
+        [
+            angle(self.a, self.b, self.c),
+            angle(self.b, self.c, self.a),
+            angle(self.c, self.a, self.b),
+        ]
+    }
+
+    /// Angles within this many radians of a right angle count as right for `is_right`, and
+    /// not as acute or obtuse.
+    pub const RIGHT_ANGLE_TOLERANCE: f32 = 1e-3;
+
+    /// Whether every angle is less than a right angle.
+    pub fn is_acute(&self) -> bool {
+        self.largest_angle() < std::f32::consts::FRAC_PI_2 - Self::RIGHT_ANGLE_TOLERANCE
+    }
+
+    /// Whether one angle is more than a right angle.
+    pub fn is_obtuse(&self) -> bool {
+        self.largest_angle() > std::f32::consts::FRAC_PI_2 + Self::RIGHT_ANGLE_TOLERANCE
+    }
+
+    /// Whether one angle is a right angle, within `RIGHT_ANGLE_TOLERANCE`.
+    pub fn is_right(&self) -> bool {
+        (self.largest_angle() - std::f32::consts::FRAC_PI_2).abs() <= Self::RIGHT_ANGLE_TOLERANCE
+    }
+
+    fn largest_angle(&self) -> f32 {
+        self.angles().into_iter().fold(0.0, f32::max)
+    }
+
+    /// Subdivides the triangle `levels` times, splitting every triangle into four each time,
+    /// and lifts each vertex to `z = height(xy)`. The result is an indexed `TriangleList` with
+    /// the same attributes as [`Triangle::mesh`].
+    ///
+    /// The vertex count grows by about 4x per level, so keep `levels` small.
+    pub fn displace(&self, levels: u32, height: impl Fn(Vec2) -> f32) -> Mesh {
+        let n = 1u32 << levels;
+        // Row `i` starts after the `n + 1`, `n`, ... points of the rows before it.
+        let index = |i: u32, j: u32| i * (n + 1) - i * i.saturating_sub(1) / 2 + j;
+        let (edge_b, edge_c) = ((self.b - self.a) / n as f32, (self.c - self.a) / n as f32);
+        let points = (0..=n)
+            .flat_map(|i| (0..=n - i).map(move |j| (i, j)))
+            .map(|(i, j)| self.a + edge_b * i as f32 + edge_c * j as f32)
+            .collect_vec();
+        let indices = (0..n)
+            .flat_map(|i| (0..n - i).map(move |j| (i, j)))
+            .flat_map(|(i, j)| {
+                let mut tris = vec![index(i, j), index(i + 1, j), index(i, j + 1)];
+                if j + 1 < n - i {
+                    tris.extend([index(i + 1, j), index(i + 1, j + 1), index(i, j + 1)]);
+                }
+                tris
+            })
+            .collect_vec();
+
+        let mut mesh = Mesh::new(wgpu::PrimitiveTopology::TriangleList);
+        mesh.set_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            points.iter().map(|p| [p.x, p.y, height(*p)]).collect_vec(),
+        );
+        mesh.set_attribute(Mesh::ATTRIBUTE_COLOR, vec![self.rgba; points.len()]);
+        mesh.set_attribute(
+            Mesh::ATTRIBUTE_UV_0,
+            points.iter().map(|p| self.planar_uv(*p)).collect_vec(),
+        );
+        mesh.set_indices(Some(mesh_indices(points.len(), indices)));
+        mesh
+    }
+
+    /// The UV of `point`, in local space, under `mode`.
+    pub fn uv(&self, point: Vec2, mode: UvMode) -> [f32; 2] {
+        match mode {
+            UvMode::None => [0.0, 0.0],
+            UvMode::PlanarFromVertices { bounds: None } => self.planar_uv(point),
+            UvMode::PlanarFromVertices {
+                bounds: Some(bounds),
+            } => {
+                let min = Vec2::new(bounds.left, bounds.bottom);
+                let size =
+                    (Vec2::new(bounds.right, bounds.top) - min).max(Vec2::splat(f32::EPSILON));
+                let uv = (point - min) / size;
+                [uv.x, 1.0 - uv.y]
+            }
+            UvMode::Explicit([a, b, c]) => {
+                let w = self.barycentric(point);
+                (a * w.x + b * w.y + c * w.z).to_array()
+            }
+        }
+    }
+
+    fn planar_uvs(&self) -> [[f32; 2]; 3] {
+        [self.a, self.b, self.c].map(|p| self.planar_uv(p))
+    }
+
+    /// Maps the triangle's bounding box onto the unit square, with v pointing down like image
+    /// rows.
+    fn planar_uv(&self, point: Vec2) -> [f32; 2] {
+        let min = self.a.min(self.b).min(self.c);
+        let max = self.a.max(self.b).max(self.c);
+        let size = (max - min).max(Vec2::splat(f32::EPSILON));
+        let uv = (point - min) / size;
+        [uv.x, 1.0 - uv.y]
+    }
+}
+
+/// Keyframed shape and color animation, written into the entity's `Triangle`.
+///
+/// `keyframes` are `(time, triangle)` pairs sorted by time.
+#[derive(Clone, Component)]
+pub struct TriangleAnimation {
+    pub keyframes: Vec<(f32, Triangle)>,
+    pub time: f32,
+    pub looping: bool,
+}
+
+impl TriangleAnimation {
+    pub fn new(keyframes: Vec<(f32, Triangle)>) -> Self {
+        Self {
+            keyframes,
+            time: 0.0,
+            looping: false,
+        }
+    }
+
+    pub fn looping(self) -> Self {
+        Self {
+            looping: true,
+            ..self
+        }
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map(|(t, _)| *t).unwrap_or(0.0)
+    }
+
+    /// The interpolated triangle at `time`, clamped to the first and last keyframes (or wrapped
+    /// when looping).
+    pub fn sample(&self, time: f32) -> Option<Triangle> {
+        let duration = self.duration();
+        let time = match self.looping && duration > 0.0 {
+            true => time.rem_euclid(duration),
+            false => time,
+        };
+        let next = self.keyframes.iter().position(|(t, _)| *t > time);
+        match next {
+            Some(0) => self.keyframes.first().map(|(_, tri)| tri.clone()),
+            Some(i) => {
+                let (t0, from) = &self.keyframes[i - 1];
+                let (t1, to) = &self.keyframes[i];
+                Some(from.lerp(to, (time - t0) / (t1 - t0)))
+            }
+            None => self.keyframes.last().map(|(_, tri)| tri.clone()),
+        }
+    }
+}
+
+/// Indices for a mesh with `vertex_count` vertices, as `U16` when they all fit and `U32`
+/// otherwise. Shared by the indexed shape builders.
+///
+/// `0xFFFF` is left unused because indexed strips treat it as the primitive restart value.
+pub fn mesh_indices(vertex_count: usize, indices: Vec<u32>) -> Indices {
+    match vertex_count <= u16::MAX as usize {
+        true => Indices::U16(indices.into_iter().map(|i| i as u16).collect()),
+        false => Indices::U32(indices),
+    }
+}
+
+/// Resamples the closed outline `points` to `count` points spaced evenly by arc length,
+/// starting at the first point.
+pub fn resample_outline(points: &[Vec2], count: usize) -> Vec<Vec2> {
+    let first = match points.first() {
+        Some(first) => *first,
+        None => return Vec::new(),
+    };
+    let segments = points
+        .iter()
+        .copied()
+        .circular_tuple_windows::<(_, _)>()
+        .collect_vec();
+    let perimeter: f32 = segments.iter().map(|(a, b)| a.distance(*b)).sum();
+    if perimeter <= f32::EPSILON {
+        return vec![first; count];
+    }
+    let step = perimeter / count as f32;
+    let mut resampled = Vec::with_capacity(count);
+    let (mut segment, mut walked) = (0, 0.0);
+    for i in 0..count {
+        let target = step * i as f32;
+        // Skip whole segments until `target` falls inside one.
+        while segment + 1 < segments.len() {
+            let (a, b) = segments[segment];
+            if walked + a.distance(b) > target {
+                break;
+            }
+            walked += a.distance(b);
+            segment += 1;
+        }
+        let (a, b) = segments[segment];
+        let len = a.distance(b).max(f32::EPSILON);
+        resampled.push(a.lerp(b, ((target - walked) / len).min(1.0)));
+    }
+    resampled
+}
+
+/// Interpolates between two closed outlines with different point counts by resampling both
+/// to the larger count, e.g. to tween a triangle into a square. Both outlines should start at
+/// corresponding points and share a winding, or the shape twists mid-morph.
+pub fn morph_outline(from: &[Vec2], to: &[Vec2], t: f32) -> Vec<Vec2> {
+    let count = from.len().max(to.len());
+    resample_outline(from, count)
+        .into_iter()
+        .zip(resample_outline(to, count))
+        .map(|(a, b)| a.lerp(b, t))
+        .collect()
+}
+
+/// One vertex of a `StreamingTriangle`, laid out exactly like the interleaved mesh buffer the
+/// pipeline expects for untextured meshes without tangents (attributes in name order).
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StreamingVertex {
+    pub color: [f32; 4],
+    pub position: [f32; 3],
+    pub uv: [f32; 2],
+}
+
+// SAFETY: `repr(C)` and made only of `f32`s, so there's no padding and any bit pattern is valid.
+unsafe impl bevy::core::Zeroable for StreamingVertex {}
+unsafe impl bevy::core::Pod for StreamingVertex {}
+
+/// A triangle list written straight into a persistent GPU vertex buffer every frame, skipping
+/// `Assets<Mesh>`.
+///
+/// Use it for geometry that deforms every frame, like cloth, where rebuilding a `Mesh` asset
+/// and having it re-prepared each frame is the bottleneck. Anything that changes rarely is
+/// better off as a mesh asset, which is only uploaded when it changes; streaming uploads every
+/// frame regardless. Textures aren't supported.
+#[derive(Clone, Debug, Default, Component)]
+pub struct StreamingTriangle {
+    /// Three per triangle, counter-clockwise.
+    pub vertices: Vec<StreamingVertex>,
+}
+
+/// How a `Polygon` is split into triangles.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PolygonFill {
+    /// A fan from the first point. Fast, but only correct for convex polygons.
+    Convex,
+    /// Ear clipping, correct for any simple polygon including concave ones.
+    #[default]
+    EarClip,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PolygonError {
+    /// Fewer than three points.
+    TooFewPoints,
+    /// Two non-adjacent edges cross, so there is no well-defined inside.
+    SelfIntersecting,
+    /// Ear clipping found no ear with area still left to cover, e.g. from points too close
+    /// together for `f32` to tell which side of an edge they're on.
+    Degenerate,
+}
+
+impl std::fmt::Display for PolygonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::TooFewPoints => write!(f, "polygon has fewer than three points"),
+            Self::SelfIntersecting => write!(f, "polygon edges intersect each other"),
+            Self::Degenerate => write!(f, "polygon is too degenerate to triangulate"),
+        }
+    }
+}
+
+impl std::error::Error for PolygonError {}
+
+/// A filled simple polygon. `points` is the outline in either winding; the mesh is always
+/// emitted in the `TriangleFrontFace` winding.
+#[derive(Clone, Component)]
+pub struct Polygon {
+    pub points: Vec<Vec2>,
+    pub rgba: [f32; 4],
+    pub fill: PolygonFill,
+}
+
+impl Polygon {
+    /// Polygons with more points than this are triangulated on the `AsyncComputeTaskPool`
+    /// rather than in the frame, see [`PendingPolygonMesh`].
+    pub const ASYNC_POINTS: usize = 512;
+
+    pub fn new(points: Vec<Vec2>, rgba: [f32; 4]) -> Self {
+        Self {
+            points,
+            rgba,
+            fill: PolygonFill::default(),
+        }
+    }
+
+    pub fn with_fill(self, fill: PolygonFill) -> Self {
+        Self { fill, ..self }
+    }
+
+    /// Twice the signed area, positive for counter-clockwise outlines.
+    fn double_signed_area(&self) -> f32 {
+        self.points
+            .iter()
+            .circular_tuple_windows()
+            .map(|(a, b)| a.perp_dot(*b))
+            .sum()
+    }
+
+    /// Whether any two non-adjacent edges cross or touch.
+    pub fn is_self_intersecting(&self) -> bool {
+        let n = self.points.len();
+        let edge = |i: usize| (self.points[i], self.points[(i + 1) % n]);
+        (0..n).tuple_combinations().any(|(i, j)| {
+            // Adjacent edges share a point; that's not an intersection.
+            if j == i + 1 || (i == 0 && j == n - 1) {
+                return false;
+            }
+            let ((p1, p2), (q1, q2)) = (edge(i), edge(j));
+            let side = |a: Vec2, b: Vec2, p: Vec2| (b - a).perp_dot(p - a);
+            let (d1, d2) = (side(q1, q2, p1), side(q1, q2, p2));
+            let (d3, d4) = (side(p1, p2, q1), side(p1, p2, q2));
+            if d1 == 0.0 && d2 == 0.0 {
+                // Collinear edges only meet if their extents overlap.
+                return p1.min(p2).cmple(q1.max(q2)).all() && q1.min(q2).cmple(p1.max(p2)).all();
+            }
+            d1 * d2 <= 0.0 && d3 * d4 <= 0.0
+        })
+    }
+
+    /// Triangle indices into `points`, counter-clockwise.
+    pub fn triangulate(&self) -> Result<Vec<u32>, PolygonError> {
+        self.triangulate_facing(wgpu::FrontFace::Ccw)
+    }
+
+    /// Triangle indices into `points`, wound `front_face`.
+    pub fn triangulate_facing(
+        &self,
+        front_face: wgpu::FrontFace,
+    ) -> Result<Vec<u32>, PolygonError> {
+        if self.points.len() < 3 {
+            return Err(PolygonError::TooFewPoints);
+        }
+        if self.is_self_intersecting() {
+            return Err(PolygonError::SelfIntersecting);
+        }
+        let mut order = (0..self.points.len() as u32).collect_vec();
+        if self.double_signed_area() < 0.0 {
+            order.reverse();
+        }
+        let mut indices = match self.fill {
+            PolygonFill::Convex => (1..order.len() - 1)
+                .flat_map(|i| [order[0], order[i], order[i + 1]])
+                .collect(),
+            PolygonFill::EarClip => self.clip_ears(order)?,
+        };
+        if front_face == wgpu::FrontFace::Cw {
+            indices.chunks_exact_mut(3).for_each(|t| t.swap(1, 2));
+        }
+        Ok(indices)
+    }
+
+    /// Repeatedly cuts off a convex corner with no other point inside it. `remaining` must be
+    /// counter-clockwise.
+    fn clip_ears(&self, mut remaining: Vec<u32>) -> Result<Vec<u32>, PolygonError> {
+        let point = |i: u32| self.points[i as usize];
+        let mut indices = Vec::with_capacity((remaining.len() - 2) * 3);
+        while remaining.len() > 3 {
+            let n = remaining.len();
+            let corner = |i: usize| {
+                (
+                    remaining[(i + n - 1) % n],
+                    remaining[i],
+                    remaining[(i + 1) % n],
+                )
+            };
+            let ear = (0..n).find(|&i| {
+                let (prev, cur, next) = corner(i);
+                let (a, b, c) = (point(prev), point(cur), point(next));
+                if (b - a).perp_dot(c - b) <= 0.0 {
+                    return false;
+                }
+                let ear = Triangle {
+                    a,
+                    b,
+                    c,
+                    rgba: self.rgba,
+                };
+                remaining
+                    .iter()
+                    .filter(|i| ![prev, cur, next].contains(i))
+                    .all(|i| !ear.contains(point(*i)))
+            });
+            match ear {
+                Some(i) => {
+                    let (prev, cur, next) = corner(i);
+                    indices.extend([prev, cur, next]);
+                    remaining.remove(i);
+                }
+                None => {
+                    let left = remaining
+                        .iter()
+                        .circular_tuple_windows()
+                        .map(|(a, b)| point(*a).perp_dot(point(*b)))
+                        .sum::<f32>();
+                    // Only collinear points are left, which cover no area.
+                    if left.abs() <= self.double_signed_area().abs() * 1e-5 {
+                        return Ok(indices);
+                    }
+                    return Err(PolygonError::Degenerate);
+                }
+            }
+        }
+        indices.extend(remaining);
+        Ok(indices)
+    }
+
+    /// An indexed `TriangleList` with the same attributes as [`Triangle::mesh`], with UVs
+    /// mapping the bounding box onto the unit square, wound counter-clockwise.
+    pub fn mesh(&self) -> Result<Mesh, PolygonError> {
+        self.mesh_facing(wgpu::FrontFace::Ccw)
+    }
+
+    /// Like `mesh`, wound `front_face`.
+    pub fn mesh_facing(&self, front_face: wgpu::FrontFace) -> Result<Mesh, PolygonError> {
+        let indices = self.triangulate_facing(front_face)?;
+        let min = self
+            .points
+            .iter()
+            .fold(Vec2::splat(f32::MAX), |min, p| min.min(*p));
+        let max = self
+            .points
+            .iter()
+            .fold(Vec2::splat(f32::MIN), |max, p| max.max(*p));
+        let size = (max - min).max(Vec2::splat(f32::EPSILON));
+
+        let mut mesh = Mesh::new(wgpu::PrimitiveTopology::TriangleList);
+        mesh.set_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            self.points.iter().map(|p| [p.x, p.y, 0.0]).collect_vec(),
+        );
+        mesh.set_attribute(Mesh::ATTRIBUTE_COLOR, vec![self.rgba; self.points.len()]);
+        mesh.set_attribute(
+            Mesh::ATTRIBUTE_UV_0,
+            self.points
+                .iter()
+                .map(|p| {
+                    let uv = (*p - min) / size;
+                    [uv.x, 1.0 - uv.y]
+                })
+                .collect_vec(),
+        );
+        mesh.set_indices(Some(mesh_indices(self.points.len(), indices)));
+        Ok(mesh)
+    }
+}
+
+/// A strip of quads following a centerline, e.g. for motion trails.
+///
+/// Each point gets its own full width; if `widths` is shorter than `points` the extra points
+/// are dropped.
+#[derive(Clone, Component)]
+pub struct Ribbon {
+    pub points: Vec<Vec2>,
+    pub widths: Vec<f32>,
+    pub rgba: [f32; 4],
+}
+
+impl Ribbon {
+    pub fn new(points: Vec<Vec2>, widths: Vec<f32>, rgba: [f32; 4]) -> Self {
+        Self {
+            points,
+            widths,
+            rgba,
+        }
+    }
+
+    /// How far past its half width a corner's miter may reach, as a multiple of the half
+    /// width. Sharper corners are cut off there rather than spiking out.
+    pub const MITER_LIMIT: f32 = 4.0;
+
+    /// Builds a `TriangleStrip` mesh with two vertices per centerline point, offset to the left
+    /// and right along the point's normal. Interior points are mitered, offset along the
+    /// average of the adjacent segment normals far enough that both segments keep their full
+    /// width, up to `MITER_LIMIT`. A point where the centerline doubles back uses the normal
+    /// of the segment before it. Wound counter-clockwise.
+    pub fn mesh(&self) -> Mesh {
+        self.mesh_facing(wgpu::FrontFace::Ccw)
+    }
+
+    /// Like `mesh`, wound `front_face`.
+    pub fn mesh_facing(&self, front_face: wgpu::FrontFace) -> Mesh {
+        let count = self.points.len().min(self.widths.len());
+        let points = &self.points[..count];
+        let segment_normals = points
+            .iter()
+            .tuple_windows()
+            .map(|(a, b)| (*b - *a).perp().normalize_or_zero())
+            .collect_vec();
+        let positions = points
+            .iter()
+            .zip(self.widths.iter())
+            .enumerate()
+            .flat_map(|(i, (point, width))| {
+                let prev = i.checked_sub(1).and_then(|i| segment_normals.get(i));
+                let next = segment_normals.get(i);
+                let normal = match (prev, next) {
+                    (Some(prev), Some(next)) => match (*prev + *next).try_normalize() {
+                        // `1 / cos` of half the turn, so both segments keep the full width.
+                        Some(miter) => miter / miter.dot(*prev).max(1.0 / Self::MITER_LIMIT),
+                        None => *prev,
+                    },
+                    (Some(n), None) | (None, Some(n)) => *n,
+                    (None, None) => Vec2::ZERO,
+                };
+                let offset = match front_face {
+                    // Left then right keeps the first triangle counter-clockwise.
+                    wgpu::FrontFace::Ccw => normal * (width / 2.0),
+                    wgpu::FrontFace::Cw => -normal * (width / 2.0),
+                };
+                [*point + offset, *point - offset]
+            })
+            .map(|p| [p.x, p.y, 0.0])
+            .collect_vec();
+
+        let mut mesh = Mesh::new(wgpu::PrimitiveTopology::TriangleStrip);
+        mesh.set_attribute(Mesh::ATTRIBUTE_COLOR, vec![self.rgba; positions.len()]);
+        mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        // u runs along the ribbon, v across it.
+        let last = count.saturating_sub(1).max(1) as f32;
+        mesh.set_attribute(
+            Mesh::ATTRIBUTE_UV_0,
+            (0..count)
+                .flat_map(|i| [[i as f32 / last, 0.0], [i as f32 / last, 1.0]])
+                .collect_vec(),
+        );
+        mesh
+    }
+}
+
+/// World-space bounding box of a `Triangle`, kept up to date by `TriangleSystem::Bounds`.
+#[derive(Clone, Copy, Debug, PartialEq, Component)]
+pub struct TriangleAabb {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl TriangleAabb {
+    pub fn new(triangle: &Triangle, transform: &GlobalTransform) -> Self {
+        let (min, max) = triangle.world_vertices(transform).into_iter().fold(
+            (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+            |(min, max), p| (min.min(p), max.max(p)),
+        );
+        Self { min, max }
+    }
+}
+
+/// A uniform grid over `TriangleAabb`s, so picking only tests the triangles in the cell under
+/// the cursor. Each triangle is listed in every cell its box touches.
+pub struct TriangleSpatialIndex {
+    pub cell_size: f32,
+    cells: HashMap<IVec2, Vec<Entity>>,
+    entries: HashMap<Entity, (IVec2, IVec2)>,
+}
+
+impl Default for TriangleSpatialIndex {
+    fn default() -> Self {
+        Self::new(128.0)
+    }
+}
+
+impl TriangleSpatialIndex {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::default(),
+            entries: HashMap::default(),
+        }
+    }
+
+    fn cell(&self, point: Vec2) -> IVec2 {
+        (point / self.cell_size).floor().as_ivec2()
+    }
+
+    /// Adds `entity`, or moves it if it's already indexed.
+    pub fn insert(&mut self, entity: Entity, aabb: &TriangleAabb) {
+        self.remove(entity);
+        let (lo, hi) = (self.cell(aabb.min), self.cell(aabb.max));
+        for cell in (lo.x..=hi.x).cartesian_product(lo.y..=hi.y) {
+            self.cells
+                .entry(IVec2::from(cell))
+                .or_default()
+                .push(entity);
+        }
+        self.entries.insert(entity, (lo, hi));
+    }
+
+    pub fn remove(&mut self, entity: Entity) {
+        let (lo, hi) = match self.entries.remove(&entity) {
+            Some(x) => x,
+            None => return,
+        };
+        for cell in (lo.x..=hi.x).cartesian_product(lo.y..=hi.y) {
+            let cell = IVec2::from(cell);
+            if let Some(entities) = self.cells.get_mut(&cell) {
+                entities.retain(|e| *e != entity);
+                if entities.is_empty() {
+                    self.cells.remove(&cell);
+                }
+            }
+        }
+    }
+
+    /// Triangles whose bounding box might contain `point`.
+    pub fn candidates(&self, point: Vec2) -> &[Entity] {
+        self.cells
+            .get(&self.cell(point))
+            .map_or(&[], |entities| entities.as_slice())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Linear color multiplied into every triangle's final color, for screen-wide effects like
+/// damage flashes or fades. White leaves colors unchanged.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TriangleGlobalTint(pub [f32; 4]);
+
+impl Default for TriangleGlobalTint {
+    fn default() -> Self {
+        Self([1.0; 4])
+    }
+}
+
+/// Range every triangle's final alpha is clamped to, after the tint, e.g. a `min` above 0
+/// to keep faded triangles faintly visible as ghosts. The default 0 to 1 changes nothing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AlphaClamp {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Default for AlphaClamp {
+    fn default() -> Self {
+        Self { min: 0.0, max: 1.0 }
+    }
+}
+
+/// Fades triangles toward `color` (linear) with their depth in front of the camera, from not
+/// at all at `start` to `color`'s alpha at `end`, for atmospheric depth in 2.5D. Depth is
+/// along the camera's forward axis, so for the default 2d camera at `z = 999.9` it's
+/// `999.9 - z`. Additive glows fade out instead. Off by default, with a transparent `color`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fog {
+    pub color: [f32; 4],
+    pub start: f32,
+    pub end: f32,
+}
+
+impl Default for Fog {
+    fn default() -> Self {
+        Self {
+            color: [0.0; 4],
+            start: 0.0,
+            end: 1000.0,
+        }
+    }
+}
+
+/// The topmost triangle under the cursor, if any.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HoveredTriangle(pub Option<Entity>);
+
+/// Makes the triangle a button: its color follows `HoveredTriangle` and the left mouse
+/// button, and releasing the button over the triangle it was pressed on sends a
+/// `TriangleClicked`. Overrides the triangle's color.
+#[derive(Clone, Copy, Debug, PartialEq, Component)]
+pub struct TriangleButton {
+    pub normal: [f32; 4],
+    pub hovered: [f32; 4],
+    pub pressed: [f32; 4],
+}
+
+/// Sent when a `TriangleButton` is clicked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TriangleClicked(pub Entity);
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, SystemLabel)]
+pub enum TriangleSystem {
+    /// Builds and rebuilds triangle meshes from `Triangle` components. Runs in
+    /// `CoreStage::PostUpdate`, after every `Update` system has had its chance to mutate them.
+    Mesh,
+    /// Swaps in the meshes of finished `PendingPolygonMesh` tasks, as part of `Mesh`.
+    PendingMesh,
+    /// Updates `TriangleAabb`s, in `CoreStage::PostUpdate` after transform propagation.
+    Bounds,
+    /// Updates the `TriangleSpatialIndex` from changed bounds.
+    Index,
+    /// Updates `HoveredTriangle` from the index.
+    Pick,
+}
+
+/// Add to a triangle (or anything with a `TriangleMeshHandle`) to have the size of its mesh
+/// reported here in `CoreStage::Last` of every frame, e.g. to spot an empty mesh when
+/// nothing draws. Zero while there's no mesh or it isn't loaded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Component)]
+pub struct TriangleMeshStats {
+    pub vertex_count: usize,
+    /// `None` for non-indexed meshes.
+    pub index_count: Option<usize>,
+}
+
+/// Forces the entity's mesh to be rebuilt in the next `TriangleSystem::Mesh` pass, even if
+/// nothing it depends on was changed. Removed once the mesh is rebuilt.
+#[derive(Clone, Copy, Debug, Default, Component)]
+pub struct MeshDirty;
+
+/// Marks the entity `MeshDirty`, for changes that change detection can't see, like edits made
+/// without going through `DerefMut`. Does nothing if the entity is gone.
+///
+/// ```ignore
+/// commands.add(RemeshTriangle(entity));
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct RemeshTriangle(pub Entity);
+
+impl Command for RemeshTriangle {
+    fn write(self, world: &mut World) {
+        if let Some(mut entity) = world.get_entity_mut(self.0) {
+            entity.insert(MeshDirty);
+        }
+    }
+}
+
+/// The main-world half: meshing, bounds, picking and scene loading for triangles. Add
+/// `render::plugin::TriangleRenderPlugin` as well to draw them.
+#[derive(Default)]
+pub struct TrianglePlugin;
+
+impl Plugin for TrianglePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TriangleSpatialIndex>()
+            .init_resource::<TriangleFrontFace>()
+            .init_resource::<HoveredTriangle>()
+            .add_event::<TriangleClicked>()
+            .add_asset::<TriangleScene>()
+            .init_asset_loader::<TriangleSceneLoader>()
+            .add_system(triangle_scene_system)
+            .add_system(triangle_animation_system)
+            .add_system(triangle_button_system)
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                triangle_mesh_system.label(TriangleSystem::Mesh),
+            )
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                ribbon_mesh_system.label(TriangleSystem::Mesh),
+            )
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                pending_polygon_mesh_system
+                    .label(TriangleSystem::PendingMesh)
+                    .label(TriangleSystem::Mesh),
+            )
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                polygon_mesh_system
+                    .label(TriangleSystem::Mesh)
+                    .after(TriangleSystem::PendingMesh),
+            )
+            // After the stage the mesh systems insert new meshes' handles in.
+            .add_system_to_stage(CoreStage::Last, triangle_mesh_stats_system)
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                triangle_aabb_system
+                    .label(TriangleSystem::Bounds)
+                    .after(TransformSystem::TransformPropagate),
+            )
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                triangle_spatial_index_system
+                    .label(TriangleSystem::Index)
+                    .after(TriangleSystem::Bounds),
+            )
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                triangle_picking_system
+                    .label(TriangleSystem::Pick)
+                    .after(TriangleSystem::Index),
+            );
+    }
+}
+
+/// Marks an entity whose mesh is drawn once per `TriangleInstance` pointing at it, all in one
+/// instanced draw call, rather than once on its own. An entity with none visible isn't drawn.
+#[derive(Clone, Copy, Debug, Default, Component)]
+pub struct TriangleInstances;
+
+/// A copy of the mesh of the `TriangleInstances` entity `0`, placed by this entity's
+/// `GlobalTransform`. Hide it with `Visibility`; copies have no mesh of their own, so they
+/// aren't culled or picked individually.
+#[derive(Clone, Copy, Debug, Component)]
+pub struct TriangleInstance(pub Entity);
+
+/// The part of its group's texture a `TriangleInstance` samples: the mesh's UVs are scaled by
+/// `scale`, then moved by `offset`, before the group's own `TriangleUvTransform` applies. The
+/// whole texture without one.
+#[derive(Clone, Copy, Debug, PartialEq, Component)]
+pub struct TriangleInstanceUv {
+    pub offset: Vec2,
+    pub scale: Vec2,
+}
+
+impl Default for TriangleInstanceUv {
+    fn default() -> Self {
+        Self {
+            offset: Vec2::ZERO,
+            scale: Vec2::ONE,
+        }
+    }
+}
+
+impl TriangleInstanceUv {
+    /// Cell `index` of a sprite sheet of `columns` by `rows` equal cells, counted row by row
+    /// from the top left.
+    pub fn atlas_cell(columns: u32, rows: u32, index: u32) -> Self {
+        let scale = Vec2::new(columns.max(1) as f32, rows.max(1) as f32).recip();
+        let cell = Vec2::new(
+            (index % columns.max(1)) as f32,
+            (index / columns.max(1)) as f32,
+        );
+        Self {
+            offset: cell * scale,
+            scale,
+        }
+    }
+}
+
+/// Spawns `cols * rows` copies of `template` centered on the origin, `spacing` apart, as
+/// `TriangleInstance`s of one `TriangleInstances` entity holding the only mesh, and returns
+/// that entity. The copies are its children, so moving it moves the grid.
+///
+/// The copies have no `Triangle`, since editing the shared mesh would change every copy;
+/// they can still be moved or hidden one by one.
+pub fn spawn_triangle_grid(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    cols: u32,
+    rows: u32,
+    spacing: f32,
+    template: &Triangle,
+) -> Entity {
+    let handle = meshes.add(template.mesh());
+    let origin =
+        -Vec2::new(cols.saturating_sub(1) as f32, rows.saturating_sub(1) as f32) * spacing / 2.0;
+    let root = commands
+        .spawn_bundle((
+            TriangleMeshHandle(handle),
+            TriangleInstances,
+            Transform::default(),
+            GlobalTransform::default(),
+            Visibility::default(),
+            ComputedVisibility::default(),
+        ))
+        .id();
+    let instances = (0..rows)
+        .cartesian_product(0..cols)
+        .map(|(row, col)| {
+            let translation = origin + Vec2::new(col as f32, row as f32) * spacing;
+            commands
+                .spawn_bundle((
+                    TriangleInstance(root),
+                    Transform::from_translation(translation.extend(0.0)),
+                    GlobalTransform::default(),
+                    Visibility::default(),
+                    ComputedVisibility::default(),
+                ))
+                .id()
+        })
+        .collect_vec();
+    commands.entity(root).push_children(&instances);
+    root
+}
+
+/// Welds many triangles into one indexed mesh, sharing vertices that are within `epsilon` of
+/// each other and have the same color, e.g. for a terrain of edge-to-edge triangles. Each
+/// triangle keeps its winding. UVs are planar across the bounds of everything pushed.
+#[derive(Clone, Debug)]
+pub struct TriangleMeshBuilder {
+    epsilon: f32,
+    positions: Vec<Vec2>,
+    colors: Vec<[f32; 4]>,
+    indices: Vec<u32>,
+    /// Vertex indices by their position snapped to an `epsilon` grid.
+    cells: HashMap<(i64, i64), Vec<u32>>,
+}
+
+impl Default for TriangleMeshBuilder {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_EPSILON)
+    }
+}
+
+impl TriangleMeshBuilder {
+    pub const DEFAULT_EPSILON: f32 = 1e-4;
+
+    /// `epsilon` is clamped to `f32::EPSILON`, so only exact matches weld below that.
+    pub fn new(epsilon: f32) -> Self {
+        Self {
+            epsilon: epsilon.max(f32::EPSILON),
+            positions: Vec::new(),
+            colors: Vec::new(),
+            indices: Vec::new(),
+            cells: HashMap::default(),
+        }
+    }
+
+    pub fn push(&mut self, triangle: &Triangle) -> &mut Self {
+        for point in [triangle.a, triangle.b, triangle.c] {
+            let index = self.weld(point, triangle.rgba);
+            self.indices.push(index);
+        }
+        self
+    }
+
+    /// Unique vertices so far.
+    pub fn vertex_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn mesh(&self) -> Mesh {
+        let min = self
+            .positions
+            .iter()
+            .fold(Vec2::splat(f32::MAX), |min, p| min.min(*p));
+        let max = self
+            .positions
+            .iter()
+            .fold(Vec2::splat(f32::MIN), |max, p| max.max(*p));
+        let size = (max - min).max(Vec2::splat(f32::EPSILON));
+
+        let mut mesh = Mesh::new(wgpu::PrimitiveTopology::TriangleList);
+        mesh.set_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            self.positions.iter().map(|p| [p.x, p.y, 0.0]).collect_vec(),
+        );
+        mesh.set_attribute(Mesh::ATTRIBUTE_COLOR, self.colors.clone());
+        mesh.set_attribute(
+            Mesh::ATTRIBUTE_UV_0,
+            self.positions
+                .iter()
+                .map(|p| {
+                    let uv = (*p - min) / size;
+                    [uv.x, 1.0 - uv.y]
+                })
+                .collect_vec(),
+        );
+        mesh.set_indices(Some(mesh_indices(
+            self.positions.len(),
+            self.indices.clone(),
+        )));
+        mesh
+    }
+
+    /// The index of a matching vertex, adding one if there's none. A match within `epsilon`
+    /// can snap to a neighboring cell, so those are searched too.
+    fn weld(&mut self, point: Vec2, rgba: [f32; 4]) -> u32 {
+        let cell = (point / self.epsilon).round();
+        let (x, y) = (cell.x as i64, cell.y as i64);
+        let existing = (x - 1..=x + 1)
+            .cartesian_product(y - 1..=y + 1)
+            .filter_map(|key| self.cells.get(&key))
+            .flatten()
+            .find(|&&i| {
+                let i = i as usize;
+                self.colors[i] == rgba && self.positions[i].distance(point) <= self.epsilon
+            });
+        if let Some(&index) = existing {
+            return index;
+        }
+        let index = self.positions.len() as u32;
+        self.positions.push(point);
+        self.colors.push(rgba);
+        self.cells.entry((x, y)).or_default().push(index);
+        index
+    }
+}
+
+fn triangle_animation_system(
+    time: Res<Time>,
+    mut animation_q: Query<(&mut TriangleAnimation, &mut Triangle)>,
+) {
+    for (mut animation, mut triangle) in animation_q.iter_mut() {
+        // Only written when it moves, so a finished animation stops remeshing.
+        match animation.sample(animation.time) {
+            Some(sampled) if *triangle != sampled => *triangle = sampled,
+            _ => {}
+        }
+        animation.time += time.delta_seconds();
+    }
+}
+
+/// What `triangle_mesh_system` builds a triangle's mesh from.
+type TriangleMeshSources = (
+    Entity,
+    &'static Triangle,
+    Option<&'static TriangleMeshHandle>,
+    Option<&'static TriangleTangents>,
+    Option<&'static TriangleAnchor>,
+    Option<&'static TriangleScalarColor>,
+    Option<&'static TriangleCornerRadius>,
+    Option<&'static UvMode>,
+);
+
+/// Builds a mesh for new triangles and rebuilds it in place whenever the `Triangle` changes,
+/// gains or loses `TriangleTangents` or is marked `MeshDirty`. Any number of changes within a
+/// frame result in one rebuild.
+#[allow(clippy::type_complexity)]
+fn triangle_mesh_system(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut warned_non_finite: Local<bool>,
+    triangle_q: Query<
+        TriangleMeshSources,
+        Or<(
+            Changed<Triangle>,
+            Added<TriangleTangents>,
+            Changed<TriangleAnchor>,
+            Changed<TriangleScalarColor>,
+            Changed<TriangleCornerRadius>,
+            Changed<UvMode>,
+            With<MeshDirty>,
+        )>,
+    >,
+    removed_tangents: RemovedComponents<TriangleTangents>,
+    unchanged_q: Query<TriangleMeshSources>,
+) {
+    // Triangles that changed otherwise as well are already in `triangle_q`.
+    let untangented = removed_tangents
+        .iter()
+        .filter(|&entity| triangle_q.get(entity).is_err())
+        .filter_map(|entity| unchanged_q.get(entity).ok());
+    for (entity, triangle, mesh_handle, tangents, anchor, scalar_color, corner_radius, uv_mode) in
+        triangle_q.iter().chain(untangented)
+    {
+        commands.entity(entity).remove::<MeshDirty>();
+        // NaN or infinite vertices would end up in the vertex buffer; drop the mesh instead so
+        // the triangle stops rendering until it's fixed.
+        if !triangle.is_finite() {
+            if !*warned_non_finite {
+                warn!(
+                    "skipping triangle {:?} with non-finite vertices or color",
+                    entity
+                );
+                *warned_non_finite = true;
+            }
+            commands.entity(entity).remove::<TriangleMeshHandle>();
+            continue;
+        }
+        let triangle = triangle.anchored(anchor.copied().unwrap_or_default());
+        let mut new_mesh = match corner_radius {
+            Some(radius) => triangle.rounded_mesh(radius.0, TriangleCornerRadius::SEGMENTS),
+            None => triangle.mesh(),
+        };
+        if let (Some(uv_mode), Some(VertexAttributeValues::Float32x3(positions))) =
+            (uv_mode, new_mesh.attribute(Mesh::ATTRIBUTE_POSITION))
+        {
+            let uvs = positions
+                .iter()
+                .map(|p| triangle.uv(Vec2::new(p[0], p[1]), *uv_mode))
+                .collect_vec();
+            new_mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        }
+        if tangents.is_some() {
+            let count = new_mesh.count_vertices();
+            new_mesh.set_attribute(
+                Mesh::ATTRIBUTE_TANGENT,
+                vec![triangle.tangent().to_array(); count],
+            );
+        }
+        if let Some(scalar_color) = scalar_color {
+            // Rounded meshes have more vertices than corners; blend the corner colors.
+            let [a, b, c] = scalar_color.colors().map(Vec4::from);
+            let colors = match new_mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+                Some(VertexAttributeValues::Float32x3(positions)) => positions
+                    .iter()
+                    .map(|p| {
+                        let w = triangle.barycentric(Vec2::new(p[0], p[1]));
+                        (a * w.x + b * w.y + c * w.z).to_array()
+                    })
+                    .collect_vec(),
+                _ => scalar_color.colors().to_vec(),
+            };
+            new_mesh.set_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+        }
+        match mesh_handle.and_then(|handle| meshes.get_mut(&handle.0)) {
+            Some(mesh) => *mesh = new_mesh,
+            None => {
+                let handle = meshes.add(new_mesh);
+                commands.entity(entity).insert(TriangleMeshHandle(handle));
+            }
+        }
+    }
+}
+
+/// Recomputes the bounds of triangles that changed shape, anchor or transform.
+#[allow(clippy::type_complexity)]
+fn triangle_aabb_system(
+    mut commands: Commands,
+    mut triangle_q: Query<
+        (
+            Entity,
+            &Triangle,
+            Option<&TriangleAnchor>,
+            &GlobalTransform,
+            Option<&mut TriangleAabb>,
+        ),
+        Or<(
+            Changed<Triangle>,
+            Changed<TriangleAnchor>,
+            Changed<GlobalTransform>,
+        )>,
+    >,
+) {
+    for (entity, triangle, anchor, tform, aabb) in triangle_q.iter_mut() {
+        let triangle = triangle.anchored(anchor.copied().unwrap_or_default());
+        let new_aabb = TriangleAabb::new(&triangle, tform);
+        match aabb {
+            // Keep `Changed<TriangleAabb>` quiet when a transform change didn't move it.
+            Some(aabb) if *aabb == new_aabb => {}
+            Some(mut aabb) => *aabb = new_aabb,
+            None => {
+                commands.entity(entity).insert(new_aabb);
+            }
+        }
+    }
+}
+
+fn triangle_spatial_index_system(
+    mut index: ResMut<TriangleSpatialIndex>,
+    removed: RemovedComponents<TriangleAabb>,
+    aabb_q: Query<(Entity, &TriangleAabb), Changed<TriangleAabb>>,
+) {
+    for entity in removed.iter() {
+        index.remove(entity);
+    }
+    for (entity, aabb) in aabb_q.iter() {
+        index.insert(entity, aabb);
+    }
+}
+
+/// Where the ray from `origin` along `direction` crosses the local `z = 0` plane of `transform`,
+/// in local coordinates. `None` if it runs parallel to the plane or away from it.
+fn ray_plane_hit(origin: Vec3, direction: Vec3, transform: Mat4) -> Option<Vec2> {
+    let inverse = transform.inverse();
+    let (origin, direction) = (
+        inverse.transform_point3(origin),
+        inverse.transform_vector3(direction),
+    );
+    if direction.z.abs() <= f32::EPSILON {
+        return None;
+    }
+    let t = -origin.z / direction.z;
+    (t >= 0.0).then(|| (origin + direction * t).truncate())
+}
+
+/// Picks the triangle under the cursor of the first window that has one, preferring the
+/// highest `z`. Only the index cell under the cursor is tested.
+///
+/// The cursor is cast as a ray through the camera's projection, so orthographic and
+/// perspective cameras both work. The index is looked up where the ray crosses `z = 0`, so
+/// with perspective cameras triangles far from that plane can be missed.
+fn triangle_picking_system(
+    windows: Res<Windows>,
+    index: Res<TriangleSpatialIndex>,
+    mut hovered: ResMut<HoveredTriangle>,
+    camera_q: Query<(&Camera, &GlobalTransform)>,
+    triangle_q: Query<(&Triangle, Option<&TriangleAnchor>, &GlobalTransform)>,
+) {
+    let ray = camera_q.iter().find_map(|(camera, tform)| {
+        let window = windows.get(camera.window)?;
+        let cursor = window.cursor_position()?;
+        let size = Vec2::new(window.width(), window.height());
+        let ndc = cursor / size * 2.0 - Vec2::ONE;
+        let world = tform.compute_matrix() * camera.projection_matrix.inverse();
+        // Projections are reverse-z: 1 is the near plane, and 0 may be infinitely far away.
+        let near = world.project_point3(ndc.extend(1.0));
+        let far = world.project_point3(ndc.extend(0.5));
+        Some((near, (far - near).normalize_or_zero()))
+    });
+    let picked = ray.and_then(|(origin, direction)| {
+        let cursor = ray_plane_hit(origin, direction, Mat4::IDENTITY)?;
+        index
+            .candidates(cursor)
+            .iter()
+            .filter_map(|entity| {
+                let (triangle, anchor, tform) = triangle_q.get(*entity).ok()?;
+                let local = ray_plane_hit(origin, direction, tform.compute_matrix())?;
+                let triangle = triangle.anchored(anchor.copied().unwrap_or_default());
+                triangle
+                    .contains(local)
+                    .then(|| (*entity, tform.translation.z))
+            })
+            .max_by(|(_, z1), (_, z2)| z1.total_cmp(z2))
+            .map(|(entity, _)| entity)
+    });
+    if hovered.0 != picked {
+        hovered.0 = picked;
+    }
+}
+
+fn triangle_mesh_stats_system(
+    meshes: Res<Assets<Mesh>>,
+    mut stats_q: Query<(Option<&TriangleMeshHandle>, &mut TriangleMeshStats)>,
+) {
+    for (handle, mut stats) in stats_q.iter_mut() {
+        let new_stats = handle.and_then(|handle| meshes.get(&handle.0)).map_or_else(
+            TriangleMeshStats::default,
+            |mesh| TriangleMeshStats {
+                vertex_count: mesh.count_vertices(),
+                index_count: mesh.indices().map(|indices| indices.len()),
+            },
+        );
+        if *stats != new_stats {
+            *stats = new_stats;
+        }
+    }
+}
+
+/// Colors `TriangleButton`s and sends `TriangleClicked`. Reads last frame's
+/// `HoveredTriangle`, so the color can be a frame behind the cursor. A button only shows
+/// `pressed` while the press that started on it is held over it, and other buttons don't
+/// light up on hover in the meantime.
+fn triangle_button_system(
+    mut pressed: Local<Option<Entity>>,
+    hovered: Res<HoveredTriangle>,
+    mouse: Res<Input<MouseButton>>,
+    mut clicked: EventWriter<TriangleClicked>,
+    mut button_q: Query<(Entity, &TriangleButton, &mut Triangle)>,
+) {
+    if mouse.just_pressed(MouseButton::Left) {
+        *pressed = hovered.0.filter(|entity| button_q.get(*entity).is_ok());
+    }
+    if mouse.just_released(MouseButton::Left) {
+        if let Some(entity) = pressed.take().filter(|entity| hovered.0 == Some(*entity)) {
+            clicked.send(TriangleClicked(entity));
+        }
+    }
+    for (entity, button, mut triangle) in button_q.iter_mut() {
+        let rgba = match (*pressed, hovered.0 == Some(entity)) {
+            (Some(pressed), true) if pressed == entity => button.pressed,
+            (None, true) => button.hovered,
+            _ => button.normal,
+        };
+        // Only write on change, so idle buttons aren't remeshed every frame.
+        if triangle.rgba != rgba {
+            triangle.rgba = rgba;
+        }
+    }
+}
+
+/// A large [`Polygon`] being triangulated on the `AsyncComputeTaskPool`. Its previous mesh,
+/// if any, stays until the task finishes; changing the polygon again drops the task.
+#[derive(Component)]
+pub struct PendingPolygonMesh(Task<Result<Mesh, PolygonError>>);
+
+/// Like `triangle_mesh_system`; polygons that can't be triangulated lose their mesh until
+/// they're fixed. Polygons over `Polygon::ASYNC_POINTS` are handed to a task instead.
+#[allow(clippy::type_complexity)]
+fn polygon_mesh_system(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    pool: Res<AsyncComputeTaskPool>,
+    front_face: Res<TriangleFrontFace>,
+    polygon_q: Query<
+        (Entity, &Polygon, Option<&TriangleMeshHandle>),
+        Or<(Changed<Polygon>, With<MeshDirty>)>,
+    >,
+) {
+    for (entity, polygon, mesh_handle) in polygon_q.iter() {
+        commands.entity(entity).remove::<MeshDirty>();
+        if polygon.points.len() > Polygon::ASYNC_POINTS {
+            let polygon = polygon.clone();
+            let front_face = front_face.0;
+            let task = pool.spawn(async move { polygon.mesh_facing(front_face) });
+            commands.entity(entity).insert(PendingPolygonMesh(task));
+            continue;
+        }
+        commands.entity(entity).remove::<PendingPolygonMesh>();
+        let result = polygon.mesh_facing(front_face.0);
+        set_polygon_mesh(&mut commands, &mut meshes, entity, mesh_handle, result);
+    }
+}
+
+/// Swaps in the meshes of finished `PendingPolygonMesh` tasks. Runs before
+/// `polygon_mesh_system`, so a task it replaces this frame isn't removed with the old one.
+fn pending_polygon_mesh_system(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut pending_q: Query<(Entity, &mut PendingPolygonMesh, Option<&TriangleMeshHandle>)>,
+) {
+    for (entity, mut pending, mesh_handle) in pending_q.iter_mut() {
+        if let Some(result) = future::block_on(future::poll_once(&mut pending.0)) {
+            commands.entity(entity).remove::<PendingPolygonMesh>();
+            set_polygon_mesh(&mut commands, &mut meshes, entity, mesh_handle, result);
+        }
+    }
+}
+
+fn set_polygon_mesh(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    entity: Entity,
+    mesh_handle: Option<&TriangleMeshHandle>,
+    result: Result<Mesh, PolygonError>,
+) {
+    let new_mesh = match result {
+        Ok(mesh) => mesh,
+        Err(err) => {
+            warn!("skipping polygon {:?}: {}", entity, err);
+            commands.entity(entity).remove::<TriangleMeshHandle>();
+            return;
+        }
+    };
+    match mesh_handle.and_then(|handle| meshes.get_mut(&handle.0)) {
+        Some(mesh) => *mesh = new_mesh,
+        None => {
+            let handle = meshes.add(new_mesh);
+            commands.entity(entity).insert(TriangleMeshHandle(handle));
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn ribbon_mesh_system(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    front_face: Res<TriangleFrontFace>,
+    ribbon_q: Query<
+        (Entity, &Ribbon, Option<&TriangleMeshHandle>),
+        Or<(Changed<Ribbon>, With<MeshDirty>)>,
+    >,
+) {
+    for (entity, ribbon, mesh_handle) in ribbon_q.iter() {
+        commands.entity(entity).remove::<MeshDirty>();
+        match mesh_handle.and_then(|handle| meshes.get_mut(&handle.0)) {
+            Some(mesh) => *mesh = ribbon.mesh_facing(front_face.0),
+            None => {
+                let handle = meshes.add(ribbon.mesh_facing(front_face.0));
+                commands.entity(entity).insert(TriangleMeshHandle(handle));
+            }
+        }
+    }
+}
+
+/// Many triangles loaded from a `.tris` file, JSON like
+/// `{"triangles": [{"triangle": {"a": [0, 0], "b": [1, 0], "c": [0, 1], "rgba": [1, 0, 0, 1]},
+/// "translation": [0, 0, 0]}]}`. Spawned by giving an entity its `Handle<TriangleScene>`.
+#[derive(Clone, Serialize, Deserialize, TypeUuid)]
+#[uuid = "5c3e0d6c-0b8a-4f4e-9d1e-3f7b2a96c41d"]
+pub struct TriangleScene {
+    pub triangles: Vec<SceneTriangle>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SceneTriangle {
+    pub triangle: Triangle,
+    /// Relative to the entity holding the scene handle.
+    #[serde(default)]
+    pub translation: Vec3,
+}
+
+/// Loads `.tris` files as `TriangleScene`s. Malformed files fail the load with the parse
+/// error, logged by the asset server, and leave the handle's `LoadState` as `Failed`.
+#[derive(Default)]
+pub struct TriangleSceneLoader;
+
+impl AssetLoader for TriangleSceneLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let scene: TriangleScene = serde_json::from_slice(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(scene));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tris"]
+    }
+}
+
+/// Spawns the triangles of each entity's `Handle<TriangleScene>` as its children once the
+/// scene loads, and respawns them whenever it changes, e.g. on hot reload with
+/// `AssetServerSettings::watch_for_changes`.
+fn triangle_scene_system(
+    mut commands: Commands,
+    mut scene_events: EventReader<AssetEvent<TriangleScene>>,
+    scenes: Res<Assets<TriangleScene>>,
+    root_q: Query<(Entity, &Handle<TriangleScene>, Option<&Children>)>,
+) {
+    for event in scene_events.iter() {
+        let handle = match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => handle,
+            AssetEvent::Removed { .. } => continue,
+        };
+        let scene = match scenes.get(handle) {
+            Some(scene) => scene,
+            None => continue,
+        };
+        for (root, _, children) in root_q.iter().filter(|(_, h, _)| *h == handle) {
+            for child in children.into_iter().flat_map(|children| children.iter()) {
+                commands.entity(*child).despawn_recursive();
+            }
+            commands.entity(root).with_children(|parent| {
+                for scene_triangle in &scene.triangles {
+                    parent.spawn_bundle(TriangleBundle::at(
+                        scene_triangle.triangle.clone(),
+                        scene_triangle.translation,
+                    ));
+                }
+            });
+        }
+    }
+}
+
+/// Writes the mesh of every entity with a `TriangleMeshHandle` to a Wavefront OBJ file at
+/// `path`, in world space, as one group per entity named after its `Name` (or its index).
+/// Every entity writes its own vertices, so a mesh shared between entities is repeated, and
+/// indexed meshes keep their indices. Meshes that aren't built yet are left out.
+pub fn export_obj(world: &World, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+    let meshes = match world.get_resource::<Assets<Mesh>>() {
+        Some(meshes) => meshes,
+        None => return out.flush(),
+    };
+    // OBJ indices are 1-based and count every vertex written so far.
+    let mut vertex_offset = 1;
+    let entities = world
+        .archetypes()
+        .iter()
+        .flat_map(|archetype| archetype.entities());
+    for &entity in entities {
+        let mesh = match world.get::<TriangleMeshHandle>(entity) {
+            Some(handle) => meshes.get(&handle.0),
+            None => continue,
+        };
+        let (mesh, positions) = match mesh
+            .map(|mesh| (mesh, mesh.attribute(Mesh::ATTRIBUTE_POSITION)))
+        {
+            Some((mesh, Some(VertexAttributeValues::Float32x3(positions)))) => (mesh, positions),
+            _ => continue,
+        };
+        match world.get::<Name>(entity) {
+            Some(name) => writeln!(out, "g {}", name.as_str().replace(char::is_whitespace, "_"))?,
+            None => writeln!(out, "g entity_{}", entity.id())?,
+        }
+        let transform = world
+            .get::<GlobalTransform>(entity)
+            .map_or(Mat4::IDENTITY, |tform| tform.compute_matrix());
+        for position in positions {
+            let p = transform.transform_point3(Vec3::from(*position));
+            writeln!(out, "v {} {} {}", p.x, p.y, p.z)?;
+        }
+        let indices = match mesh.indices() {
+            Some(Indices::U16(indices)) => indices.iter().map(|&i| i as usize).collect_vec(),
+            Some(Indices::U32(indices)) => indices.iter().map(|&i| i as usize).collect_vec(),
+            None => (0..positions.len()).collect_vec(),
+        };
+        let faces = match mesh.primitive_topology() {
+            wgpu::PrimitiveTopology::TriangleList => indices
+                .chunks_exact(3)
+                .map(|f| [f[0], f[1], f[2]])
+                .collect_vec(),
+            // Every other triangle of a strip winds the other way.
+            wgpu::PrimitiveTopology::TriangleStrip => indices
+                .windows(3)
+                .enumerate()
+                .map(|(i, f)| match i % 2 {
+                    0 => [f[0], f[1], f[2]],
+                    _ => [f[1], f[0], f[2]],
+                })
+                .filter(|[a, b, c]| a != b && b != c && a != c)
+                .collect_vec(),
+            _ => Vec::new(),
+        };
+        for [a, b, c] in faces {
+            let [a, b, c] = [a, b, c].map(|i| i + vertex_offset);
+            writeln!(out, "f {} {} {}", a, b, c)?;
+        }
+        vertex_offset += positions.len();
+    }
+    out.flush()
+}
+
+/// Reads the `<polygon>`s and `<path>`s of an SVG file as `Polygon`s with their transform and
+/// fill color (linear, also set on the polygon), flipping y since SVG's points down. Spawned
+/// with their transforms, they're meshed like any other `Polygon`.
+///
+/// Only straight outlines are supported: `points`, and paths of `M`, `L`, `H`, `V` and `Z`
+/// commands, each subpath becoming its own polygon. Curves and arcs fail the import, as does
+/// a `transform` other than `translate`. The fill comes from the `fill` and `fill-opacity`
+/// attributes as `#rgb` or `#rrggbb`, black if missing, and shapes with `fill="none"` are
+/// skipped. Styles, strokes, gradients, groups and `<use>` are ignored, and so is anything
+/// in a comment or CDATA section.
+pub fn import_svg(
+    path: impl AsRef<std::path::Path>,
+) -> anyhow::Result<Vec<(Polygon, Transform, [f32; 4])>> {
+    let svg = svg::without_comments(&std::fs::read_to_string(path)?);
+    let mut shapes = Vec::new();
+    for tag in svg.split('<').skip(1) {
+        let tag = tag.split('>').next().unwrap_or_default();
+        let (name, attributes) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+        let attributes = svg::attributes(attributes);
+        let attribute = |name| {
+            attributes
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, value)| value.trim())
+        };
+        let name = name.trim_end_matches('/');
+        if name != "polygon" && name != "path" {
+            continue;
+        }
+        let rgba = match svg::fill(attribute("fill"), attribute("fill-opacity"))? {
+            Some(rgba) => rgba,
+            None => continue,
+        };
+        let outlines = match name {
+            "polygon" => vec![svg::points(attribute("points").unwrap_or_default())?],
+            _ => svg::path_outlines(attribute("d").unwrap_or_default())?,
+        };
+        let transform = svg::transform(attribute("transform"))?;
+        for mut points in outlines {
+            // The outline closes itself, so a repeated first point would be a zero-length edge.
+            if points.len() > 1 && points.first() == points.last() {
+                points.pop();
+            }
+            if points.len() < 3 {
+                continue;
+            }
+            let points = points.into_iter().map(|p| Vec2::new(p.x, -p.y)).collect();
+            shapes.push((Polygon::new(points, rgba), transform, rgba));
+        }
+    }
+    Ok(shapes)
+}
+
+/// The straight-line subset of SVG that `import_svg` reads.
+mod svg;
+
+/// The render-world half: extraction, pipelines and draw commands. Behind the default
+/// `render` feature; without it only the main-world types and systems above are built.
+#[cfg(feature = "render")]
+pub mod render;
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use bevy::app::{Events, ManualEventReader};
+    use bevy::asset::AssetPlugin;
+    use bevy::core::CorePlugin;
+    use bevy::ecs::system::CommandQueue;
+    use bevy::transform::TransformPlugin;
+
+    use super::*;
+
+    /// A windowless app with `TrianglePlugin` and what its systems read.
+    pub(crate) fn app() -> App {
+        let mut app = App::new();
+        app.add_plugin(CorePlugin)
+            .add_plugin(TransformPlugin)
+            .add_plugin(AssetPlugin)
+            .add_asset::<Mesh>()
+            .add_asset::<Image>()
+            .init_resource::<Windows>()
+            .init_resource::<Input<MouseButton>>()
+            .add_plugin(TrianglePlugin);
+        app
+    }
+
+    fn assert_near(actual: Vec2, expected: Vec2) {
+        assert!(
+            actual.abs_diff_eq(expected, 1e-3),
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    /// The `xy` of every vertex of `mesh`.
+    pub(crate) fn mesh_positions(mesh: &Mesh) -> Vec<Vec2> {
+        match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+            Some(VertexAttributeValues::Float32x3(positions)) => {
+                positions.iter().map(|p| Vec2::new(p[0], p[1])).collect()
+            }
+            _ => panic!("no positions"),
+        }
+    }
+
+    #[test]
+    fn ribbon_corners_keep_their_width() {
+        let ribbon = Ribbon::new(
+            vec![Vec2::ZERO, Vec2::new(10.0, 0.0), Vec2::new(10.0, 10.0)],
+            vec![2.0, 4.0, 2.0],
+            [1.0; 4],
+        );
+        let positions = mesh_positions(&ribbon.mesh());
+        assert_eq!(positions.len(), 6);
+        assert_near(positions[0], Vec2::new(0.0, 1.0));
+        assert_near(positions[1], Vec2::new(0.0, -1.0));
+        // Mitered: 2 away from both the horizontal and the vertical segment.
+        assert_near(positions[2], Vec2::new(8.0, 2.0));
+        assert_near(positions[3], Vec2::new(12.0, -2.0));
+        assert_near(positions[4], Vec2::new(9.0, 10.0));
+        assert_near(positions[5], Vec2::new(11.0, 10.0));
+
+        // Doubling back keeps the width instead of collapsing to the centerline.
+        let ribbon = Ribbon::new(
+            vec![Vec2::ZERO, Vec2::new(10.0, 0.0), Vec2::ZERO],
+            vec![2.0; 3],
+            [1.0; 4],
+        );
+        let positions = mesh_positions(&ribbon.mesh());
+        assert_near(positions[2], Vec2::new(10.0, 1.0));
+        assert_near(positions[3], Vec2::new(10.0, -1.0));
+
+        // A hairpin is cut off at the miter limit.
+        let ribbon = Ribbon::new(
+            vec![Vec2::ZERO, Vec2::new(10.0, 0.0), Vec2::new(0.0, 0.1)],
+            vec![2.0; 3],
+            [1.0; 4],
+        );
+        let positions = mesh_positions(&ribbon.mesh());
+        assert!(positions[2].distance(Vec2::new(10.0, 0.0)) <= Ribbon::MITER_LIMIT + 1e-3);
+    }
+
+    /// The signed areas of `mesh`'s triangles, positive for counter-clockwise ones.
+    pub(crate) fn windings(mesh: &Mesh) -> Vec<f32> {
+        let positions = mesh_positions(mesh);
+        let indices = match mesh.indices() {
+            Some(Indices::U16(indices)) => indices.iter().map(|&i| i as usize).collect_vec(),
+            Some(Indices::U32(indices)) => indices.iter().map(|&i| i as usize).collect_vec(),
+            None => (0..positions.len()).collect_vec(),
+        };
+        let triangles = match mesh.primitive_topology() {
+            wgpu::PrimitiveTopology::TriangleList => indices
+                .chunks_exact(3)
+                .map(|t| [t[0], t[1], t[2]])
+                .collect_vec(),
+            wgpu::PrimitiveTopology::TriangleStrip => indices
+                .windows(3)
+                .enumerate()
+                // Odd triangles of a strip are read with their first two vertices swapped.
+                .map(|(i, t)| match i % 2 {
+                    0 => [t[0], t[1], t[2]],
+                    _ => [t[1], t[0], t[2]],
+                })
+                .collect_vec(),
+            topology => panic!("{:?}", topology),
+        };
+        triangles
+            .iter()
+            .map(|t| {
+                let [a, b, c] = t.map(|i| positions[i]);
+                (b - a).perp_dot(c - a) / 2.0
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ear_clipping_covers_concave_outlines() {
+        let area = |polygon: &Polygon, indices: &[u32]| {
+            indices
+                .chunks_exact(3)
+                .map(|t| {
+                    let [a, b, c] = [t[0], t[1], t[2]].map(|i| polygon.points[i as usize]);
+                    let area = (b - a).perp_dot(c - a) / 2.0;
+                    assert!(area > 0.0, "{:?} is clockwise or empty", t);
+                    area
+                })
+                .sum::<f32>()
+        };
+        // Pointing right, clockwise, with the notch at the back concave.
+        let arrow = Polygon::new(
+            [
+                (0.0, 1.0),
+                (3.0, 1.0),
+                (3.0, 2.0),
+                (5.0, 0.0),
+                (3.0, -2.0),
+                (3.0, -1.0),
+                (0.0, -1.0),
+                (1.0, 0.0),
+            ]
+            .iter()
+            .map(|&p| Vec2::from(p))
+            .collect(),
+            [1.0; 4],
+        );
+        let indices = arrow.triangulate().unwrap();
+        assert_eq!(indices.len(), (arrow.points.len() - 2) * 3);
+        // The shaft less the notch, and the head.
+        assert!((area(&arrow, &indices) - (6.0 - 1.0 + 4.0)).abs() < 1e-4);
+
+        let hexagon = Polygon::new(
+            (0..6)
+                .map(|i| Vec2::from(f32::sin_cos(i as f32 * std::f32::consts::TAU / 6.0)))
+                .collect(),
+            [1.0; 4],
+        );
+        let clipped = hexagon.triangulate().unwrap();
+        let fan = hexagon
+            .clone()
+            .with_fill(PolygonFill::Convex)
+            .triangulate()
+            .unwrap();
+        assert_eq!(clipped.len(), fan.len());
+        assert!((area(&hexagon, &clipped) - area(&hexagon, &fan)).abs() < 1e-4);
+
+        let bowtie = Polygon::new(
+            vec![
+                Vec2::ZERO,
+                Vec2::new(2.0, 2.0),
+                Vec2::new(2.0, 0.0),
+                Vec2::new(0.0, 2.0),
+            ],
+            [1.0; 4],
+        );
+        assert_eq!(bowtie.triangulate(), Err(PolygonError::SelfIntersecting));
+
+        // Taken clockwise, as the arrow is given, only the notch looks like an ear, and
+        // once it's cut off there's no other with the rest of the area left over.
+        let order = (0..arrow.points.len() as u32).collect_vec();
+        assert_eq!(arrow.clip_ears(order), Err(PolygonError::Degenerate));
+    }
+
+    #[test]
+    fn built_in_shapes_wind_the_configured_front_face() {
+        let side = Triangle::side(10.0);
+        assert!(windings(&side.mesh())[0] > 0.0);
+        assert!(windings(&side.clone().facing(wgpu::FrontFace::Cw).mesh())[0] < 0.0);
+        assert_eq!(side.clone().facing(wgpu::FrontFace::Ccw), side);
+
+        let mut app = app();
+        app.insert_resource(TriangleFrontFace(wgpu::FrontFace::Cw));
+        let square = [(0.0, 0.0), (0.0, 10.0), (10.0, 10.0), (10.0, 0.0)];
+        let polygon = app
+            .world
+            .spawn()
+            .insert(Polygon::new(
+                square.iter().map(|&p| Vec2::from(p)).collect(),
+                [1.0; 4],
+            ))
+            .id();
+        let ribbon = app
+            .world
+            .spawn()
+            .insert(Ribbon::new(
+                vec![Vec2::ZERO, Vec2::new(10.0, 0.0), Vec2::new(10.0, 10.0)],
+                vec![2.0; 3],
+                [1.0; 4],
+            ))
+            .id();
+        app.update();
+        for entity in [polygon, ribbon] {
+            let handle = &app.world.get::<TriangleMeshHandle>(entity).unwrap().0;
+            let meshes = app.world.get_resource::<Assets<Mesh>>().unwrap();
+            let windings = windings(meshes.get(handle).unwrap());
+            assert!(!windings.is_empty());
+            assert!(windings.iter().all(|&area| area < 0.0), "{:?}", windings);
+        }
+    }
+
+    #[test]
+    fn mesh_indices_use_u16_up_to_65535_vertices() {
+        let indices = mesh_indices(65535, vec![0, 1, 65534]);
+        assert_eq!(wgpu::IndexFormat::from(&indices), wgpu::IndexFormat::Uint16);
+        assert!(matches!(indices, Indices::U16(ref i) if i == &[0, 1, 65534]));
+
+        let indices = mesh_indices(65536, vec![0, 1, 65535]);
+        assert_eq!(wgpu::IndexFormat::from(&indices), wgpu::IndexFormat::Uint32);
+        assert!(matches!(indices, Indices::U32(ref i) if i == &[0, 1, 65535]));
+    }
+
+    #[test]
+    fn equilateral_and_right_triangles_measure_up() {
+        let equilateral = Triangle::side(1.0);
+        for length in equilateral.edge_lengths() {
+            assert!(
+                (length - 1.0).abs() < 1e-5,
+                "{:?}",
+                equilateral.edge_lengths()
+            );
+        }
+        for angle in equilateral.angles() {
+            assert!((angle - std::f32::consts::FRAC_PI_3).abs() < 1e-5);
+        }
+        assert!(equilateral.is_acute() && !equilateral.is_right() && !equilateral.is_obtuse());
+
+        let right = Triangle {
+            a: Vec2::ZERO,
+            b: Vec2::new(3.0, 0.0),
+            c: Vec2::new(0.0, 4.0),
+            rgba: [1.0; 4],
+        };
+        assert_eq!((right.b - right.a).dot(right.c - right.a), 0.0);
+        assert_eq!(right.edge_lengths(), [5.0, 4.0, 3.0]);
+        assert_eq!(right.area(), 6.0);
+        assert!((right.angles()[0] - std::f32::consts::FRAC_PI_2).abs() < 1e-6);
+        assert!(right.is_right() && !right.is_acute() && !right.is_obtuse());
+    }
+
+    #[test]
+    fn rounded_corners_add_an_arc_per_corner() {
+        let triangle = Triangle::side(10.0);
+        let sharp = triangle.rounded_mesh(0.0, 8);
+        assert_eq!(sharp.count_vertices(), 3);
+        assert_eq!(mesh_positions(&sharp), mesh_positions(&triangle.mesh()));
+
+        let rounded = triangle.rounded_mesh(1.0, 4);
+        // The incenter the fan starts from, then five points along each corner's arc.
+        assert_eq!(rounded.count_vertices(), 1 + 3 * 5);
+        assert_eq!(rounded.indices().unwrap().len(), 15 * 3);
+        let inside = mesh_positions(&rounded)
+            .into_iter()
+            .all(|p| triangle.contains(p + (triangle.incenter() - p) * 1e-4));
+        assert!(inside);
+
+        // Past the inradius the arcs would overlap, so it's the inscribed circle.
+        let circle = mesh_positions(&triangle.rounded_mesh(100.0, 4));
+        let inradius = triangle.inradius();
+        for p in &circle[1..] {
+            assert!((p.distance(triangle.incenter()) - inradius).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn extruded_prisms_face_outward() {
+        // Clockwise, which `extrude` rewinds.
+        let triangle = Triangle::side(10.0).facing(wgpu::FrontFace::Cw);
+        let prism = triangle.extrude(2.0);
+        assert_eq!(
+            prism.primitive_topology(),
+            wgpu::PrimitiveTopology::TriangleList
+        );
+        assert_eq!(prism.count_vertices(), 18);
+        let indices = match prism.indices() {
+            Some(Indices::U16(indices)) => indices.iter().map(|&i| i as usize).collect_vec(),
+            indices => panic!("{:?}", indices),
+        };
+        assert_eq!(indices.len(), 24);
+        let positions = match prism.attribute(Mesh::ATTRIBUTE_POSITION) {
+            Some(VertexAttributeValues::Float32x3(positions)) => positions.clone(),
+            _ => panic!("no positions"),
+        };
+        let normals = match prism.attribute(Mesh::ATTRIBUTE_NORMAL) {
+            Some(VertexAttributeValues::Float32x3(normals)) => normals.clone(),
+            _ => panic!("no normals"),
+        };
+        let centroid = triangle.centroid().extend(0.0);
+        for face in indices.chunks_exact(3) {
+            let [a, b, c] = [face[0], face[1], face[2]].map(|i| Vec3::from(positions[i]));
+            let normal = Vec3::from(normals[face[0]]);
+            // Caps point straight along z, at the depth they sit at.
+            if normal.z != 0.0 {
+                assert_eq!(normal, Vec3::new(0.0, 0.0, a.z.signum()));
+            }
+            // Wound counter-clockwise seen from outside, and pointing away from the middle.
+            assert!((b - a).cross(c - a).dot(normal) > 0.0, "{:?}", face);
+            assert!(normal.dot((a + b + c) / 3.0 - centroid) > 0.0, "{:?}", face);
+        }
+    }
+
+    #[test]
+    fn constant_displacement_lifts_every_vertex_alike() {
+        let positions = |mesh: &Mesh| match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+            Some(VertexAttributeValues::Float32x3(positions)) => positions.clone(),
+            _ => panic!("no positions"),
+        };
+        let triangle = Triangle::side(10.0);
+        let (flat, lifted) = (triangle.displace(2, |_| 0.0), triangle.displace(2, |_| 3.0));
+        // Two levels split each edge in 4: 15 vertices and 16 triangles.
+        assert_eq!(flat.count_vertices(), 15);
+        assert_eq!(lifted.indices().unwrap().len(), 48);
+        for (flat, lifted) in positions(&flat).iter().zip(positions(&lifted)) {
+            assert_eq!(flat[..2], lifted[..2]);
+            assert_eq!(lifted[2] - flat[2], 3.0);
+        }
+    }
+
+    #[test]
+    fn morphing_a_triangle_into_a_square_resamples_both() {
+        // Perimeters of 12, so four points land 3 apart along each.
+        let triangle = [Vec2::ZERO, Vec2::new(3.0, 0.0), Vec2::new(0.0, 4.0)];
+        let square = [
+            Vec2::ZERO,
+            Vec2::new(3.0, 0.0),
+            Vec2::new(3.0, 3.0),
+            Vec2::new(0.0, 3.0),
+        ];
+        let halfway = morph_outline(&triangle, &square, 0.5);
+        assert_eq!(halfway.len(), 4);
+        for (point, expected) in halfway.into_iter().zip([
+            Vec2::ZERO,
+            Vec2::new(3.0, 0.0),
+            // (1.2, 2.4) on the hypotenuse, halfway to the square's corner.
+            Vec2::new(2.1, 2.7),
+            Vec2::new(0.0, 3.0),
+        ]) {
+            assert_near(point, expected);
+        }
+        for (point, expected) in morph_outline(&triangle, &square, 1.0)
+            .into_iter()
+            .zip(square)
+        {
+            assert_near(point, expected);
+        }
+    }
+
+    #[test]
+    fn shared_edges_weld_into_one_vertex_each() {
+        let triangle = |a: (f32, f32), b: (f32, f32), c: (f32, f32), rgba| Triangle {
+            a: a.into(),
+            b: b.into(),
+            c: c.into(),
+            rgba,
+        };
+        let white = [1.0; 4];
+        let mut builder = TriangleMeshBuilder::default();
+        builder
+            .push(&triangle((0.0, 0.0), (1.0, 0.0), (0.0, 1.0), white))
+            // Off by less than the epsilon along the shared edge.
+            .push(&triangle((1.0, 0.0), (1.0, 1.0), (0.0, 1.00005), white));
+        assert_eq!(builder.vertex_count(), 4);
+        let mesh = builder.mesh();
+        assert_eq!(mesh.count_vertices(), 4);
+        assert!(matches!(mesh.indices(), Some(Indices::U16(i)) if i == &[0, 1, 2, 1, 3, 2]));
+
+        // Another color keeps its own vertices.
+        builder.push(&triangle(
+            (0.0, 0.0),
+            (1.0, 0.0),
+            (0.0, -1.0),
+            [0.0, 0.0, 0.0, 1.0],
+        ));
+        assert_eq!(builder.vertex_count(), 7);
+    }
+
+    #[test]
+    fn with_color_stores_linear_rgba() {
+        assert_eq!(
+            Triangle::side(1.0).with_color(Color::RED).rgba,
+            [1.0, 0.0, 0.0, 1.0]
+        );
+        // sRGB 0.5 is about 0.214 in linear, and alpha is left alone.
+        let [r, g, b, a] = Triangle::side(1.0)
+            .with_color(Color::rgba(0.5, 0.5, 0.5, 0.5))
+            .rgba;
+        for channel in [r, g, b] {
+            assert!((channel - 0.2140).abs() < 1e-3, "{}", channel);
+        }
+        assert_eq!(a, 0.5);
+    }
+
+    #[test]
+    fn colors_round_trip_through_linear() {
+        let assert_close = |a: Color, b: Color| {
+            let (a, b) = (Vec4::from(a.as_rgba_f32()), Vec4::from(b.as_rgba_f32()));
+            assert!(a.abs_diff_eq(b, 1e-5), "{:?} != {:?}", a, b);
+        };
+        for color in [
+            Color::rgba(0.2, 0.5, 0.9, 0.75),
+            Color::hsl(30.0, 0.5, 0.5),
+            Color::rgba_linear(0.1, 0.0, 1.0, 1.0),
+        ] {
+            let mut triangle = Triangle::side(1.0).with_color(color);
+            assert_eq!(triangle.rgba, color.as_linear_rgba_f32());
+            assert_close(triangle.color(), color);
+            triangle.set_color(Color::WHITE);
+            triangle.set_color(color);
+            assert_close(triangle.color(), color);
+        }
+    }
+
+    #[test]
+    fn scaling_about_the_centroid_keeps_it_in_place() {
+        let original = Triangle {
+            a: Vec2::new(1.0, 1.0),
+            b: Vec2::new(7.0, 2.0),
+            c: Vec2::new(3.0, 6.0),
+            rgba: [1.0; 4],
+        };
+        let mut triangle = original.clone();
+        triangle.scale_about_centroid(2.0);
+        assert_near(triangle.centroid(), original.centroid());
+        for (scaled, length) in triangle.edge_lengths().iter().zip(original.edge_lengths()) {
+            assert!(
+                (scaled - 2.0 * length).abs() < 1e-4,
+                "{} != 2 * {}",
+                scaled,
+                length
+            );
+        }
+    }
+
+    #[test]
+    fn sampler_configs_build_their_descriptors() {
+        let nearest = TriangleSamplerConfig::default().descriptor();
+        assert_eq!(nearest.mag_filter, wgpu::FilterMode::Nearest);
+        assert_eq!(nearest.min_filter, wgpu::FilterMode::Nearest);
+
+        let linear = TriangleSamplerConfig::linear()
+            .with_address_mode(wgpu::AddressMode::Repeat)
+            .descriptor();
+        assert_ne!(linear, nearest);
+        assert_eq!(linear.mag_filter, wgpu::FilterMode::Linear);
+        assert_eq!(linear.min_filter, wgpu::FilterMode::Linear);
+        for mode in [
+            linear.address_mode_u,
+            linear.address_mode_v,
+            linear.address_mode_w,
+        ] {
+            assert_eq!(mode, wgpu::AddressMode::Repeat);
+        }
+    }
+
+    #[test]
+    fn bundles_take_the_transform_they_are_given() {
+        let transform = Transform::from_xyz(3.0, -2.0, 1.0)
+            .with_rotation(Quat::from_rotation_z(0.5))
+            .with_scale(Vec3::splat(2.0));
+        let translation = Vec3::new(-4.0, 5.0, 2.0);
+        let bundles = [
+            (
+                TriangleBundle::new(Triangle::side(1.0)),
+                Transform::default(),
+            ),
+            (
+                TriangleBundle::at(Triangle::side(1.0), translation),
+                Transform::from_translation(translation),
+            ),
+            (
+                TriangleBundle::with_transform(Triangle::side(1.0), transform),
+                transform,
+            ),
+        ];
+
+        let mut app = app();
+        for (bundle, expected) in bundles {
+            assert_eq!(bundle.transform, expected);
+            // Right from the start, before transform propagation has run.
+            assert_eq!(bundle.global_transform, GlobalTransform::from(expected));
+            let entity = app.world.spawn().insert_bundle(bundle).id();
+            app.update();
+            assert_eq!(
+                *app.world.get::<GlobalTransform>(entity).unwrap(),
+                GlobalTransform::from(expected)
+            );
+        }
+    }
+
+    #[test]
+    fn world_vertices_are_rotated_then_translated() {
+        let triangle = Triangle {
+            a: Vec2::new(2.0, 0.0),
+            b: Vec2::new(0.0, 1.0),
+            c: Vec2::new(-1.0, -1.0),
+            rgba: [1.0; 4],
+        };
+        // A quarter turn takes (x, y) to (-y, x); the z offset is projected away.
+        let transform = GlobalTransform::from_xyz(10.0, 5.0, 3.0)
+            .with_rotation(Quat::from_rotation_z(std::f32::consts::FRAC_PI_2));
+        let [a, b, c] = triangle.world_vertices(&transform);
+        assert_near(a, Vec2::new(10.0, 7.0));
+        assert_near(b, Vec2::new(9.0, 5.0));
+        assert_near(c, Vec2::new(11.0, 4.0));
+    }
+
+    #[test]
+    fn child_triangles_follow_their_parent() {
+        let mut app = app();
+        let triangle = Triangle {
+            a: Vec2::new(0.0, 1.0),
+            b: Vec2::new(-1.0, 0.0),
+            c: Vec2::new(1.0, 0.0),
+            rgba: [1.0; 4],
+        };
+        let parent = app
+            .world
+            .spawn()
+            .insert_bundle((
+                Transform::from_xyz(100.0, 50.0, 0.0),
+                GlobalTransform::default(),
+            ))
+            .id();
+        let child = app
+            .world
+            .spawn()
+            .insert_bundle(TriangleBundle::at(
+                triangle.clone(),
+                Vec3::new(10.0, 0.0, 1.0),
+            ))
+            .id();
+        app.world.entity_mut(parent).push_children(&[child]);
+        app.update();
+
+        let global = app.world.get::<GlobalTransform>(child).unwrap();
+        let [a, b, c] = triangle.world_vertices(global);
+        assert_near(a, Vec2::new(110.0, 51.0));
+        assert_near(b, Vec2::new(109.0, 50.0));
+        assert_near(c, Vec2::new(111.0, 50.0));
+
+        // A quarter turn of the parent swings the child around the parent's origin.
+        app.world.get_mut::<Transform>(parent).unwrap().rotation =
+            Quat::from_rotation_z(std::f32::consts::FRAC_PI_2);
+        app.update();
+
+        let global = app.world.get::<GlobalTransform>(child).unwrap();
+        let [a, b, c] = triangle.world_vertices(global);
+        assert_near(a, Vec2::new(99.0, 60.0));
+        assert_near(b, Vec2::new(100.0, 59.0));
+        assert_near(c, Vec2::new(100.0, 61.0));
+    }
+
+    /// The number of `Created` and `Modified` mesh events sent since `reader` last read.
+    fn mesh_writes(app: &App, reader: &mut ManualEventReader<AssetEvent<Mesh>>) -> usize {
+        let events = app
+            .world
+            .get_resource::<Events<AssetEvent<Mesh>>>()
+            .unwrap();
+        reader
+            .iter(events)
+            .filter(|event| !matches!(event, AssetEvent::Removed { .. }))
+            .count()
+    }
+
+    #[test]
+    fn changes_within_a_frame_remesh_once() {
+        let mut app = app();
+        let mut reader = ManualEventReader::default();
+        let entity = app
+            .world
+            .spawn()
+            .insert_bundle(TriangleBundle::new(Triangle::side(10.0)))
+            .id();
+        app.update();
+        assert_eq!(mesh_writes(&app, &mut reader), 1);
+
+        // Two edits, and a `RemeshTriangle` on top.
+        app.world
+            .get_mut::<Triangle>(entity)
+            .unwrap()
+            .set_color(Color::RED);
+        app.world
+            .get_mut::<Triangle>(entity)
+            .unwrap()
+            .set_color(Color::BLUE);
+        RemeshTriangle(entity).write(&mut app.world);
+        app.update();
+        assert_eq!(mesh_writes(&app, &mut reader), 1);
+        assert!(app.world.get::<MeshDirty>(entity).is_none());
+
+        app.update();
+        assert_eq!(mesh_writes(&app, &mut reader), 0);
+    }
+
+    #[test]
+    fn buttons_follow_hover_and_presses_and_click_on_release() {
+        let mut app = app();
+        let button = TriangleButton {
+            normal: [0.1; 4],
+            hovered: [0.5; 4],
+            pressed: [0.9; 4],
+        };
+        let [a, b] = [0.0, 20.0].map(|x| {
+            app.world
+                .spawn()
+                .insert_bundle(TriangleBundle::at(
+                    Triangle::side(10.0),
+                    Vec3::new(x, 0.0, 0.0),
+                ))
+                .insert(button)
+                .id()
+        });
+        let mut reader = ManualEventReader::<TriangleClicked>::default();
+        // Picking runs after the buttons and finds nothing without a window, so the hover
+        // is set again before every frame, like picking would have the frame before.
+        let mut frame = |hovered, press: Option<bool>| {
+            app.world.get_resource_mut::<HoveredTriangle>().unwrap().0 = hovered;
+            let mut mouse = app.world.get_resource_mut::<Input<MouseButton>>().unwrap();
+            match press {
+                Some(true) => mouse.press(MouseButton::Left),
+                Some(false) => mouse.release(MouseButton::Left),
+                None => {}
+            }
+            app.update();
+            app.world
+                .get_resource_mut::<Input<MouseButton>>()
+                .unwrap()
+                .clear();
+            let events = app.world.get_resource::<Events<TriangleClicked>>().unwrap();
+            let clicked = reader.iter(events).map(|clicked| clicked.0).collect_vec();
+            let rgba = |entity| app.world.get::<Triangle>(entity).unwrap().rgba;
+            ([rgba(a), rgba(b)], clicked)
+        };
+
+        assert_eq!(
+            frame(Some(a), None),
+            ([button.hovered, button.normal], vec![])
+        );
+        assert_eq!(
+            frame(Some(a), Some(true)),
+            ([button.pressed, button.normal], vec![])
+        );
+        // Dragged off while held: nothing lights up, and releasing elsewhere doesn't click.
+        assert_eq!(
+            frame(Some(b), None),
+            ([button.normal, button.normal], vec![])
+        );
+        assert_eq!(
+            frame(Some(b), Some(false)),
+            ([button.normal, button.hovered], vec![])
+        );
+        frame(Some(a), Some(true));
+        assert_eq!(
+            frame(Some(a), Some(false)),
+            ([button.hovered, button.normal], vec![a])
+        );
+        assert_eq!(frame(None, None), ([button.normal, button.normal], vec![]));
+    }
+
+    #[test]
+    fn remeshing_rebuilds_an_untouched_triangle() {
+        let mut app = app();
+        let mut reader = ManualEventReader::default();
+        let triangle = Triangle::side(10.0);
+        let entity = app
+            .world
+            .spawn()
+            .insert_bundle(TriangleBundle::new(triangle.clone()))
+            .id();
+        app.update();
+        let handle = app
+            .world
+            .get::<TriangleMeshHandle>(entity)
+            .unwrap()
+            .0
+            .clone();
+        // Something outside the mesh system clobbers the mesh; the `Triangle` is unchanged.
+        app.world
+            .get_resource_mut::<Assets<Mesh>>()
+            .unwrap()
+            .set_untracked(&handle, Mesh::new(wgpu::PrimitiveTopology::TriangleList));
+        app.update();
+        mesh_writes(&app, &mut reader);
+
+        RemeshTriangle(entity).write(&mut app.world);
+        app.update();
+        assert_eq!(mesh_writes(&app, &mut reader), 1);
+        let meshes = app.world.get_resource::<Assets<Mesh>>().unwrap();
+        let positions = mesh_positions(meshes.get(&handle).unwrap());
+        assert_eq!(positions, [triangle.a, triangle.b, triangle.c]);
+
+        // A despawned entity is skipped.
+        app.world.despawn(entity);
+        RemeshTriangle(entity).write(&mut app.world);
+        app.update();
+    }
+
+    #[test]
+    fn the_spatial_index_picks_like_brute_force_from_fewer_candidates() {
+        let mut app = app();
+        // A 50 by 50 grid of triangles, 20 units apart, spread over many cells.
+        for (x, y) in (0..50).cartesian_product(0..50) {
+            app.world.spawn().insert_bundle(TriangleBundle::at(
+                Triangle::side(16.0),
+                Vec3::new(x as f32 * 20.0, y as f32 * 20.0, 0.0),
+            ));
+        }
+        // New triangles' bounds are inserted by commands, so they're indexed a frame later.
+        app.update();
+        app.update();
+        let triangles: HashMap<_, _> = app
+            .world
+            .query::<(Entity, &Triangle, &GlobalTransform)>()
+            .iter(&app.world)
+            .map(|(entity, triangle, tform)| (entity, (triangle.clone(), tform.translation)))
+            .collect();
+        let index = app.world.get_resource::<TriangleSpatialIndex>().unwrap();
+        assert_eq!(index.len(), 2500);
+
+        let hits = |entities: &mut dyn Iterator<Item = &Entity>, point: Vec2| {
+            let mut hits = entities
+                .filter(|entity| {
+                    let (triangle, translation) = &triangles[entity];
+                    triangle.contains(point - translation.truncate())
+                })
+                .copied()
+                .collect_vec();
+            hits.sort();
+            hits
+        };
+        let mut most_candidates = 0;
+        for point in [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(500.0, 500.0),
+            Vec2::new(510.0, 510.0),
+            Vec2::new(983.0, 120.0),
+            Vec2::new(-50.0, -50.0),
+        ] {
+            let brute_force = hits(&mut triangles.keys(), point);
+            let candidates = index.candidates(point);
+            most_candidates = most_candidates.max(candidates.len());
+            assert_eq!(
+                hits(&mut candidates.iter(), point),
+                brute_force,
+                "at {:?}",
+                point
+            );
+        }
+        assert!(most_candidates < 100, "{} candidates", most_candidates);
+    }
+
+    #[test]
+    fn anchors_shift_the_mesh_vertices() {
+        let triangle = Triangle {
+            a: Vec2::new(2.0, 1.0),
+            b: Vec2::new(6.0, 1.0),
+            c: Vec2::new(2.0, 4.0),
+            rgba: [1.0; 4],
+        };
+        let mut app = app();
+        for (anchor, a) in [
+            (TriangleAnchor::Center, Vec2::new(-2.0, -1.5)),
+            (TriangleAnchor::BottomLeft, Vec2::ZERO),
+            (
+                TriangleAnchor::Custom(Vec2::new(6.0, 1.0)),
+                Vec2::new(-4.0, 0.0),
+            ),
+        ] {
+            let entity = app
+                .world
+                .spawn()
+                .insert_bundle(TriangleBundle::new(triangle.clone()))
+                .insert(anchor)
+                .id();
+            app.update();
+            let handle = app.world.get::<TriangleMeshHandle>(entity).unwrap();
+            let meshes = app.world.get_resource::<Assets<Mesh>>().unwrap();
+            let positions = mesh_positions(meshes.get(&handle.0).unwrap());
+            assert_eq!(positions.len(), 3);
+            // Every vertex moves by the same amount, keeping the shape.
+            let offset = a - triangle.a;
+            for (p, original) in positions.iter().zip([triangle.a, triangle.b, triangle.c]) {
+                assert_near(*p, original + offset);
+            }
+        }
+    }
+
+    #[test]
+    fn scalar_colors_map_through_the_colormap() {
+        let mut app = app();
+        let mut mesh_colors = |values| {
+            let entity = app
+                .world
+                .spawn()
+                .insert_bundle(TriangleBundle::new(Triangle::side(10.0)))
+                .insert(TriangleScalarColor {
+                    values,
+                    colormap: Colormap::Viridis,
+                })
+                .id();
+            app.update();
+            let handle = app.world.get::<TriangleMeshHandle>(entity).unwrap();
+            let meshes = app.world.get_resource::<Assets<Mesh>>().unwrap();
+            match meshes
+                .get(&handle.0)
+                .unwrap()
+                .attribute(Mesh::ATTRIBUTE_COLOR)
+            {
+                Some(VertexAttributeValues::Float32x4(colors)) => colors.clone(),
+                _ => panic!("no colors"),
+            }
+        };
+
+        let equal = mesh_colors([0.3; 3]);
+        assert_eq!(equal.len(), 3);
+        assert!(equal.iter().all(|color| *color == equal[0]));
+
+        let (first, last) = (0x44, 0xfd);
+        let red = |hex: u8| Color::rgb_u8(hex, 0, 0).as_linear_rgba_f32()[0];
+        // Out-of-range values clamp to the endpoints too.
+        let ends = mesh_colors([0.0, 1.0, 2.0]);
+        for (color, expected) in ends.iter().zip([first, last, last]) {
+            assert!((color[0] - red(expected)).abs() < 1e-5, "{:?}", color);
+            assert_eq!(color[3], 1.0);
+        }
+        assert_eq!(ends[0], Colormap::Viridis.sample(0.0));
+        assert_eq!(ends[1], Colormap::Viridis.sample(1.0));
+    }
+
+    #[test]
+    fn uv_modes_give_the_vertices_their_uvs() {
+        let mut app = app();
+        let triangle = Triangle {
+            a: Vec2::new(0.0, 0.0),
+            b: Vec2::new(4.0, 0.0),
+            c: Vec2::new(1.0, 2.0),
+            rgba: [1.0; 4],
+        };
+        let mut mesh_uvs = |mode: Option<UvMode>| {
+            let mut entity = app.world.spawn();
+            entity.insert_bundle(TriangleBundle::new(triangle.clone()));
+            if let Some(mode) = mode {
+                entity.insert(mode);
+            }
+            let entity = entity.id();
+            app.update();
+            let handle = app.world.get::<TriangleMeshHandle>(entity).unwrap();
+            let meshes = app.world.get_resource::<Assets<Mesh>>().unwrap();
+            match meshes
+                .get(&handle.0)
+                .unwrap()
+                .attribute(Mesh::ATTRIBUTE_UV_0)
+            {
+                Some(VertexAttributeValues::Float32x2(uvs)) => uvs.clone(),
+                _ => panic!("no uvs"),
+            }
+        };
+
+        // By default the bounding box maps onto the unit square, v pointing down, so each
+        // vertex gets a UV of its own.
+        assert_eq!(mesh_uvs(None), [[0.0, 1.0], [1.0, 1.0], [0.25, 0.0]]);
+
+        let explicit = [
+            Vec2::new(0.1, 0.2),
+            Vec2::new(0.3, 0.4),
+            Vec2::new(0.5, 0.6),
+        ];
+        assert_eq!(
+            mesh_uvs(Some(UvMode::Explicit(explicit))),
+            explicit.map(|uv| uv.to_array())
+        );
+        assert_eq!(mesh_uvs(Some(UvMode::None)), [[0.0; 2]; 3]);
+    }
+
+    #[test]
+    fn non_finite_triangles_get_no_mesh() {
+        let mut app = app();
+        let mut nan = Triangle::side(10.0);
+        nan.a.x = f32::NAN;
+        let entity = app
+            .world
+            .spawn()
+            .insert_bundle(TriangleBundle::new(nan))
+            .id();
+        app.update();
+        assert!(app.world.get::<TriangleMeshHandle>(entity).is_none());
+        assert_eq!(app.world.get_resource::<Assets<Mesh>>().unwrap().len(), 0);
+
+        // Fixing it brings the mesh back, and breaking it again drops the stale one.
+        *app.world.get_mut::<Triangle>(entity).unwrap() = Triangle::side(10.0);
+        app.update();
+        assert!(app.world.get::<TriangleMeshHandle>(entity).is_some());
+        app.world.get_mut::<Triangle>(entity).unwrap().rgba[0] = f32::INFINITY;
+        app.update();
+        assert!(app.world.get::<TriangleMeshHandle>(entity).is_none());
+    }
+
+    #[test]
+    fn mesh_stats_match_the_built_mesh() {
+        let mut app = app();
+        let triangle = app
+            .world
+            .spawn()
+            .insert_bundle(TriangleBundle::new(Triangle::side(10.0)))
+            .insert(TriangleMeshStats::default())
+            .id();
+        let points = (0..5)
+            .map(|i| Vec2::from(f32::sin_cos(i as f32 / 5.0 * std::f32::consts::TAU)) * 10.0)
+            .collect_vec();
+        let polygon = app
+            .world
+            .spawn()
+            .insert(Polygon::new(points, [1.0; 4]))
+            .insert(TriangleMeshStats::default())
+            .id();
+        app.update();
+
+        for entity in [triangle, polygon] {
+            let handle = &app.world.get::<TriangleMeshHandle>(entity).unwrap().0;
+            let meshes = app.world.get_resource::<Assets<Mesh>>().unwrap();
+            let mesh = meshes.get(handle).unwrap();
+            assert_eq!(
+                *app.world.get::<TriangleMeshStats>(entity).unwrap(),
+                TriangleMeshStats {
+                    vertex_count: mesh.count_vertices(),
+                    index_count: mesh.indices().map(|indices| indices.len()),
+                }
+            );
+        }
+        assert_eq!(
+            app.world
+                .get::<TriangleMeshStats>(triangle)
+                .unwrap()
+                .vertex_count,
+            3
+        );
+
+        // Without a mesh, it reports an empty one.
+        app.world.get_mut::<Triangle>(triangle).unwrap().a.x = f32::NAN;
+        app.update();
+        assert_eq!(
+            *app.world.get::<TriangleMeshStats>(triangle).unwrap(),
+            TriangleMeshStats::default()
+        );
+    }
+
+    #[test]
+    fn tangents_lie_in_the_face_and_go_when_removed() {
+        let skewed = Triangle {
+            a: Vec2::new(1.0, 5.0),
+            b: Vec2::new(-3.0, -2.0),
+            c: Vec2::new(4.0, 0.5),
+            rgba: [1.0; 4],
+        };
+        for triangle in [
+            Triangle::side(10.0),
+            skewed.clone(),
+            skewed.facing(wgpu::FrontFace::Cw),
+        ] {
+            let mesh = triangle.mesh_with_tangents();
+            let tangents = match mesh.attribute(Mesh::ATTRIBUTE_TANGENT) {
+                Some(VertexAttributeValues::Float32x4(tangents)) => tangents,
+                _ => panic!("no tangents"),
+            };
+            for &[x, y, z, w] in tangents {
+                assert_eq!(z, 0.0, "not orthogonal to +Z for {:?}", triangle);
+                assert!((Vec2::new(x, y).length() - 1.0).abs() < 1e-5);
+                assert_eq!(w.abs(), 1.0);
+            }
+        }
+
+        let mut app = app();
+        let mut reader = ManualEventReader::default();
+        let entity = app
+            .world
+            .spawn()
+            .insert_bundle(TriangleBundle::new(Triangle::side(10.0)))
+            .insert(TriangleTangents)
+            .id();
+        app.update();
+        assert_eq!(mesh_writes(&app, &mut reader), 1);
+        let mesh_has_tangents = |app: &App| {
+            let handle = app.world.get::<TriangleMeshHandle>(entity).unwrap();
+            let meshes = app.world.get_resource::<Assets<Mesh>>().unwrap();
+            let mesh = meshes.get(&handle.0).unwrap();
+            mesh.attribute(Mesh::ATTRIBUTE_TANGENT).is_some()
+        };
+        assert!(mesh_has_tangents(&app));
+
+        app.world.entity_mut(entity).remove::<TriangleTangents>();
+        app.update();
+        assert_eq!(mesh_writes(&app, &mut reader), 1);
+        assert!(!mesh_has_tangents(&app));
+    }
+
+    #[test]
+    fn animations_step_through_their_keyframes() {
+        let from = Triangle::side(10.0).with_rgba([1.0, 0.0, 0.0, 1.0]);
+        let to = Triangle::side(20.0).with_rgba([0.0, 0.0, 1.0, 1.0]);
+        let animation = TriangleAnimation::new(vec![(0.0, from.clone()), (2.0, to.clone())]);
+        assert_eq!(animation.sample(0.0), Some(from.clone()));
+        let between = animation.sample(0.5).unwrap();
+        assert_near(
+            between.c,
+            Vec2::new(6.25, from.c.y + (to.c.y - from.c.y) / 4.0),
+        );
+        assert_eq!(between.rgba, [0.75, 0.0, 0.25, 1.0]);
+        assert_eq!(animation.sample(3.0), Some(to.clone()));
+        assert_eq!(
+            animation.clone().looping().sample(2.5),
+            animation.sample(0.5)
+        );
+
+        // Past the end the triangle stops changing, and so stops being remeshed.
+        let mut app = app();
+        let mut reader = ManualEventReader::default();
+        let entity = app
+            .world
+            .spawn()
+            .insert_bundle(TriangleBundle::new(from))
+            .insert(animation)
+            .id();
+        app.update();
+        mesh_writes(&app, &mut reader);
+        app.world.get_mut::<TriangleAnimation>(entity).unwrap().time = 3.0;
+        app.update();
+        assert_eq!(app.world.get::<Triangle>(entity), Some(&to));
+        assert_eq!(mesh_writes(&app, &mut reader), 1);
+        app.update();
+        app.update();
+        assert_eq!(mesh_writes(&app, &mut reader), 0);
+    }
+
+    #[test]
+    fn only_strong_mesh_handles_keep_the_mesh() {
+        let mut app = app();
+        let mut mesh = || {
+            let handle = app
+                .world
+                .get_resource_mut::<Assets<Mesh>>()
+                .unwrap()
+                .add(Triangle::side(10.0).mesh());
+            (handle.id, handle)
+        };
+        let (weak_id, weak) = mesh();
+        let (strong_id, strong) = mesh();
+        app.world
+            .spawn()
+            .insert(TriangleMeshHandle::new_weak(&weak));
+        let holder = app
+            .world
+            .spawn()
+            .insert(TriangleMeshHandle::new_strong(&strong))
+            .id();
+        drop((weak, strong));
+        let alive = |app: &App, id| {
+            app.world
+                .get_resource::<Assets<Mesh>>()
+                .unwrap()
+                .get(id)
+                .is_some()
+        };
+        // Bevy counts the drop in one frame, frees the asset in the next and removes it in
+        // the one after.
+        for _ in 0..3 {
+            app.update();
+        }
+        assert!(!alive(&app, weak_id));
+        assert!(alive(&app, strong_id));
+
+        app.world.despawn(holder);
+        for _ in 0..3 {
+            app.update();
+        }
+        assert!(!alive(&app, strong_id));
+    }
+
+    #[test]
+    fn a_grid_is_one_mesh_drawn_per_instance() {
+        let mut app = app();
+        let mut queue = CommandQueue::default();
+        let root = app
+            .world
+            .resource_scope(|world, mut meshes: Mut<Assets<Mesh>>| {
+                let mut commands = Commands::new(&mut queue, world);
+                spawn_triangle_grid(
+                    &mut commands,
+                    &mut meshes,
+                    10,
+                    10,
+                    5.0,
+                    &Triangle::side(4.0),
+                )
+            });
+        queue.apply(&mut app.world);
+        app.update();
+
+        assert_eq!(app.world.get_resource::<Assets<Mesh>>().unwrap().len(), 1);
+        assert!(app.world.get::<TriangleMeshHandle>(root).is_some());
+        let mut instances = app.world.query::<(
+            &TriangleInstance,
+            &GlobalTransform,
+            Option<&TriangleMeshHandle>,
+        )>();
+        let translations = instances
+            .iter(&app.world)
+            .map(|(instance, tform, mesh)| {
+                assert_eq!(instance.0, root);
+                assert!(mesh.is_none());
+                tform.translation.truncate()
+            })
+            .collect_vec();
+        assert_eq!(translations.len(), 100);
+        let min = translations.iter().copied().reduce(Vec2::min).unwrap();
+        let max = translations.iter().copied().reduce(Vec2::max).unwrap();
+        assert_eq!((min, max), (Vec2::splat(-22.5), Vec2::splat(22.5)));
+    }
+
+    #[test]
+    fn obj_exports_every_vertex_once_per_entity() {
+        let mut app = app();
+        let triangle = |x| TriangleBundle::at(Triangle::side(10.0), Vec3::new(x, 0.0, 0.0));
+        let first = app.world.spawn().insert_bundle(triangle(0.0)).id();
+        app.world.spawn().insert_bundle(triangle(20.0));
+        let square = [(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)];
+        app.world.spawn().insert_bundle((
+            Polygon::new(square.iter().map(|&p| Vec2::from(p)).collect(), [1.0; 4]),
+            GlobalTransform::default(),
+        ));
+        app.update();
+        // A third entity drawing the first one's mesh.
+        let shared =
+            TriangleMeshHandle::new_strong(&app.world.get::<TriangleMeshHandle>(first).unwrap().0);
+        app.world
+            .spawn()
+            .insert_bundle((shared, GlobalTransform::from_xyz(0.0, 40.0, 0.0)));
+
+        let dir = std::env::temp_dir().join(format!("triangle_obj_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("scene.obj");
+        export_obj(&app.world, &path).unwrap();
+        let obj = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let groups = obj.split("g ").skip(1).collect_vec();
+        assert_eq!(groups.len(), 4);
+        let mut vertices = Vec::new();
+        let mut sizes = Vec::new();
+        for group in groups {
+            let lines = group.lines().skip(1).collect_vec();
+            let group_vertices = lines
+                .iter()
+                .filter_map(|line| line.strip_prefix("v "))
+                .map(|v| {
+                    v.split(' ')
+                        .map(|x| x.parse::<f32>().unwrap())
+                        .collect_vec()
+                })
+                .collect_vec();
+            let faces = lines
+                .iter()
+                .filter_map(|line| line.strip_prefix("f "))
+                .map(|f| {
+                    f.split(' ')
+                        .map(|i| i.parse::<usize>().unwrap())
+                        .collect_vec()
+                })
+                .collect_vec();
+            // Faces only point at their own group's vertices.
+            let own = vertices.len() + 1..=vertices.len() + group_vertices.len();
+            assert!(faces.iter().flatten().all(|i| own.contains(i)), "{}", group);
+            sizes.push((group_vertices.len(), faces.len()));
+            vertices.extend(group_vertices);
+        }
+        sizes.sort_unstable();
+        // The indexed square keeps its four corners rather than one per face corner.
+        assert_eq!(sizes, [(3, 1), (3, 1), (3, 1), (4, 2)]);
+        // The shared mesh is written again, moved by its own entity's transform.
+        let highest = vertices.iter().map(|v| v[1]).fold(f32::MIN, f32::max);
+        assert!(highest > 40.0, "{}", obj);
+        let unique = vertices
+            .iter()
+            .map(|v| v.iter().map(|x| x.to_bits()).collect_vec())
+            .unique()
+            .count();
+        assert_eq!(unique, vertices.len());
+    }
+
+    #[test]
+    fn tris_files_load_and_spawn_their_triangles() {
+        use bevy::asset::LoadState;
+
+        let dir = std::env::temp_dir().join(format!("triangle_tris_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("level.tris"),
+            r#"{"triangles": [
+                {"triangle": {"a": [0, 0], "b": [1, 0], "c": [0, 1], "rgba": [1, 0, 0, 1]},
+                 "translation": [5, 0, 0]},
+                {"triangle": {"a": [0, 0], "b": [2, 0], "c": [0, 2], "rgba": [0, 0, 1, 1]}}
+            ]}"#,
+        )
+        .unwrap();
+        std::fs::write(dir.join("broken.tris"), r#"{"triangles": [{"#).unwrap();
+
+        let mut app = app();
+        // Absolute paths replace the asset folder.
+        let asset_server = app.world.get_resource::<AssetServer>().unwrap().clone();
+        let level: Handle<TriangleScene> = asset_server.load(dir.join("level.tris").as_path());
+        let broken: Handle<TriangleScene> = asset_server.load(dir.join("broken.tris").as_path());
+        let root = app
+            .world
+            .spawn()
+            .insert_bundle((
+                level.clone(),
+                Transform::from_xyz(0.0, 10.0, 0.0),
+                GlobalTransform::default(),
+            ))
+            .id();
+        // Files load on the IO task pool.
+        for _ in 0..200 {
+            app.update();
+            let done = [&level, &broken].map(|handle| asset_server.get_load_state(handle));
+            if done == [LoadState::Loaded, LoadState::Failed]
+                && app.world.get::<Children>(root).is_some()
+            {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(asset_server.get_load_state(&broken), LoadState::Failed);
+        let children = app
+            .world
+            .get::<Children>(root)
+            .unwrap()
+            .iter()
+            .copied()
+            .collect_vec();
+        let mut triangles = children
+            .iter()
+            .map(|&child| {
+                let triangle = app.world.get::<Triangle>(child).unwrap();
+                let tform = app.world.get::<GlobalTransform>(child).unwrap();
+                (triangle.b.x, triangle.rgba, tform.translation)
+            })
+            .collect_vec();
+        triangles.sort_by(|a, b| a.0.total_cmp(&b.0));
+        assert_eq!(
+            triangles,
+            [
+                (1.0, [1.0, 0.0, 0.0, 1.0], Vec3::new(5.0, 10.0, 0.0)),
+                (2.0, [0.0, 0.0, 1.0, 1.0], Vec3::new(0.0, 10.0, 0.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn svg_shapes_import_as_polygon_entities() {
+        let dir = std::env::temp_dir().join(format!("triangle_svg_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("shapes.svg");
+        std::fs::write(
+            &path,
+            r##"<svg xmlns="http://www.w3.org/2000/svg">
+                <polygon points="0,0 10,0 10,10 0,10" fill="#ff0000" transform="translate(5 2)"/>
+                <path d="M0 0 L6 0 L3 -4 Z" fill="#00f" fill-opacity="0.5"/>
+                <polygon points="0,0 1,0 1,1" fill="none"/>
+                <!-- <polygon points="0,0 1,0 1,1"/> a < b -->
+                <style><![CDATA[ path { fill: red } a<b ]]></style>
+            </svg>"##,
+        )
+        .unwrap();
+        let shapes = import_svg(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let mut app = app();
+        for (polygon, transform, _) in shapes {
+            app.world
+                .spawn()
+                .insert_bundle((polygon, transform, GlobalTransform::from(transform)));
+        }
+        app.update();
+        let mut polygons = app
+            .world
+            .query::<(&Polygon, &Transform, &TriangleMeshHandle)>();
+        let mut polygons = polygons
+            .iter(&app.world)
+            .map(|(polygon, transform, _)| {
+                (polygon.points.len(), polygon.rgba, transform.translation)
+            })
+            .collect_vec();
+        polygons.sort_by_key(|(len, ..)| *len);
+        assert_eq!(
+            polygons,
+            [
+                (3, [0.0, 0.0, 1.0, 0.5], Vec3::ZERO),
+                (4, [1.0, 0.0, 0.0, 1.0], Vec3::new(5.0, -2.0, 0.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn large_polygons_are_meshed_without_blocking_the_frame() {
+        let points = (0..4000)
+            .map(|i| Vec2::from(f32::sin_cos(i as f32 / 4000.0 * std::f32::consts::TAU)) * 100.0)
+            .collect_vec();
+        let polygon = Polygon::new(points, [1.0; 4]);
+        assert!(polygon.points.len() > Polygon::ASYNC_POINTS);
+
+        let mut app = app();
+        let entity = app.world.spawn().insert(polygon).id();
+        // The first frame hands the polygon to a task instead of triangulating it.
+        app.update();
+        assert!(app.world.get::<PendingPolygonMesh>(entity).is_some());
+        assert!(app.world.get::<TriangleMeshHandle>(entity).is_none());
+        // The mesh shows up in a later frame, once the task has finished, and the task
+        // stays on the entity until then.
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(30);
+        while app.world.get::<TriangleMeshHandle>(entity).is_none() {
+            assert!(app.world.get::<PendingPolygonMesh>(entity).is_some());
+            assert!(
+                std::time::Instant::now() < deadline,
+                "the polygon was never meshed"
+            );
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            app.update();
+        }
+
+        assert!(app.world.get::<PendingPolygonMesh>(entity).is_none());
+        let handle = &app.world.get::<TriangleMeshHandle>(entity).unwrap().0;
+        let mesh = app
+            .world
+            .get_resource::<Assets<Mesh>>()
+            .unwrap()
+            .get(handle);
+        assert_eq!(mesh.unwrap().count_vertices(), 4000);
+    }
+}
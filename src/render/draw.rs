@@ -0,0 +1,560 @@
+use bevy::core_pipeline::Transparent2d;
+use bevy::ecs::system::{lifetimeless::*, SystemParamItem};
+use bevy::log::Level;
+use bevy::prelude::*;
+use bevy::render::mesh::GpuBufferInfo;
+use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_component::DynamicUniformIndex;
+use bevy::render::render_phase::{
+    EntityRenderCommand, RenderCommand, RenderCommandResult, SetItemPipeline, TrackedRenderPass,
+};
+use bevy::render::render_resource::{BindGroup, Buffer};
+use bevy::render::view::{ExtractedView, ViewUniformOffset};
+use bevy::utils::{HashMap, HashSet};
+use std::marker::PhantomData;
+use std::sync::Mutex;
+
+use crate::{TriangleMeshHandle, TriangleScissor};
+
+use super::pipeline::MeshBindings;
+use super::{TriangleEdgeUniform, TriangleFillUniform, TriangleUniform};
+
+pub type DrawTriangle = (
+    SetItemPipeline,
+    SetViewBindGroup<0>,
+    SetMeshBindGroup<1>,
+    SetTextureBindGroup<2>,
+    Scissored<DrawTriangleMesh>,
+);
+
+/// Which problems the draw commands have logged, so a broken phase item doesn't flood the
+/// log every frame. A render-world resource, logging at `TriangleRenderConfig::log_level`.
+pub struct DrawLog {
+    level: Option<Level>,
+    logged: Mutex<HashSet<&'static str>>,
+}
+
+impl DrawLog {
+    pub fn new(level: Option<Level>) -> Self {
+        Self {
+            level,
+            logged: Default::default(),
+        }
+    }
+
+    /// Logs `message` the first time it's passed, at the configured level.
+    pub fn once(&self, message: &'static str) {
+        self.once_at(self.level, message);
+    }
+
+    /// Logs `message` the first time it's passed, at `level`.
+    pub fn once_at(&self, level: Option<Level>, message: &'static str) {
+        if !self.logged.lock().unwrap().insert(message) {
+            return;
+        }
+        match level {
+            None => {}
+            Some(Level::ERROR) => error!("{}", message),
+            Some(Level::WARN) => warn!("{}", message),
+            Some(Level::INFO) => info!("{}", message),
+            Some(Level::DEBUG) => debug!("{}", message),
+            Some(Level::TRACE) => trace!("{}", message),
+        }
+    }
+
+    /// Whether `message` has come up, even if it wasn't printed.
+    pub fn logged(&self, message: &str) -> bool {
+        self.logged.lock().unwrap().contains(message)
+    }
+}
+
+#[derive(Clone, Debug, Component)]
+pub struct ViewBindGroup(pub BindGroup);
+
+/// This frame's mesh bind groups by the uniforms they bind besides `TriangleUniform`.
+/// Combinations whose buffers don't exist this frame, because no triangle uses them, are
+/// left out.
+#[derive(Clone, Debug, Default)]
+pub struct MeshBindGroups(pub HashMap<MeshBindings, BindGroup>);
+
+#[derive(Clone, Debug, Component)]
+pub struct TextureBindGroup(pub BindGroup);
+
+pub struct SetViewBindGroup<const I: usize>;
+impl<const I: usize> EntityRenderCommand for SetViewBindGroup<I> {
+    type Param = (
+        SRes<DrawLog>,
+        SQuery<(Read<ViewUniformOffset>, Read<ViewBindGroup>)>,
+    );
+    #[inline]
+    fn render<'w>(
+        view: Entity,
+        _item: Entity,
+        (log, view_query): SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let (view_uniform, view_bind_group) = match view_query.get(view) {
+            Ok(x) => x,
+            Err(_) => {
+                log.once("view is missing its triangle view bind group");
+                return RenderCommandResult::Failure;
+            }
+        };
+        pass.set_bind_group(I, &view_bind_group.0, &[view_uniform.offset]);
+
+        RenderCommandResult::Success
+    }
+}
+
+/// Binds the mesh bind group with the item's `TriangleUniform`, plus its
+/// `TriangleEdgeUniform` and `TriangleFillUniform` if it has them.
+pub struct SetMeshBindGroup<const I: usize>;
+impl<const I: usize> EntityRenderCommand for SetMeshBindGroup<I> {
+    type Param = (
+        SRes<DrawLog>,
+        Option<SRes<MeshBindGroups>>,
+        SQuery<(
+            Read<DynamicUniformIndex<TriangleUniform>>,
+            Option<Read<DynamicUniformIndex<TriangleEdgeUniform>>>,
+            Option<Read<DynamicUniformIndex<TriangleFillUniform>>>,
+        )>,
+    );
+    #[inline]
+    fn render<'w>(
+        _view: Entity,
+        item: Entity,
+        (log, mesh_bind_groups, mesh_query): SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let (mesh_index, edge_index, fill_index) = match mesh_query.get(item) {
+            Ok(x) => x,
+            Err(_) => {
+                log.once("triangle is missing its uniform index");
+                return RenderCommandResult::Failure;
+            }
+        };
+        // Dynamic offsets go in binding order.
+        let mut bindings = MeshBindings::NONE;
+        let mut offsets = [mesh_index.index(), 0, 0];
+        let mut len = 1;
+        if let Some(edge_index) = edge_index {
+            bindings |= MeshBindings::EDGE;
+            offsets[len] = edge_index.index();
+            len += 1;
+        }
+        if let Some(fill_index) = fill_index {
+            bindings |= MeshBindings::FILL;
+            offsets[len] = fill_index.index();
+            len += 1;
+        }
+        // `queue_mesh_bind_groups` only leaves one out in frames without its uniforms.
+        let mesh_bind_group = match mesh_bind_groups
+            .and_then(|bind_groups| bind_groups.into_inner().0.get(&bindings))
+        {
+            Some(x) => x,
+            None => {
+                log.once("the triangle mesh bind group is missing");
+                return RenderCommandResult::Failure;
+            }
+        };
+        pass.set_bind_group(I, mesh_bind_group, &offsets[..len]);
+        RenderCommandResult::Success
+    }
+}
+
+/// Binds the item's texture if it has one; untextured pipelines have no group `I`.
+pub struct SetTextureBindGroup<const I: usize>;
+impl<const I: usize> EntityRenderCommand for SetTextureBindGroup<I> {
+    type Param = SQuery<Read<TextureBindGroup>>;
+    #[inline]
+    fn render<'w>(
+        _view: Entity,
+        item: Entity,
+        texture_query: SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        if let Ok(texture_bind_group) = texture_query.get(item) {
+            pass.set_bind_group(I, &texture_bind_group.0, &[]);
+        }
+        RenderCommandResult::Success
+    }
+}
+
+/// Projects a world-space rect into the view's pixels as `(x, y, width, height)`,
+/// clamped to the viewport. `None` if nothing of it is on screen.
+///
+/// The rect is projected at `z = 0` with the view's own projection, so this holds for
+/// perspective views too as long as the rect is in front of the camera.
+pub fn scissor_pixels(rect: &Rect<f32>, view: &ExtractedView) -> Option<[u32; 4]> {
+    let view_proj = view.projection * view.transform.compute_matrix().inverse();
+    let size = Vec2::new(view.width as f32, view.height as f32);
+    let (min, max) = [
+        Vec2::new(rect.left, rect.top),
+        Vec2::new(rect.right, rect.top),
+        Vec2::new(rect.left, rect.bottom),
+        Vec2::new(rect.right, rect.bottom),
+    ]
+    .into_iter()
+    .map(|corner| {
+        let ndc = view_proj.project_point3(corner.extend(0.0));
+        Vec2::new(ndc.x + 1.0, 1.0 - ndc.y) / 2.0 * size
+    })
+    .fold((size, Vec2::ZERO), |(min, max), p| (min.min(p), max.max(p)));
+    let min = min.max(Vec2::ZERO).round();
+    let max = max.min(size).round();
+    if max.x <= min.x || max.y <= min.y {
+        return None;
+    }
+    let extent = max - min;
+    Some([min.x as u32, min.y as u32, extent.x as u32, extent.y as u32])
+}
+
+/// Draws with `C` inside the item's `TriangleScissor`, if it has one, and resets the
+/// scissor to the full viewport afterwards, even if `C` failed, so nothing the pass draws
+/// next is clipped by it. Fully clipped items aren't drawn at all.
+pub struct Scissored<C>(PhantomData<C>);
+impl<C: EntityRenderCommand> EntityRenderCommand for Scissored<C> {
+    type Param = (
+        SQuery<Read<ExtractedView>>,
+        SQuery<Read<TriangleScissor>>,
+        C::Param,
+    );
+    #[inline]
+    fn render<'w>(
+        view: Entity,
+        item: Entity,
+        (view_query, scissor_query, param): SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let scissor = match scissor_query.get(item) {
+            Ok(scissor) => scissor,
+            Err(_) => return C::render(view, item, param, pass),
+        };
+        let (width, height, rect) = match view_query.get(view) {
+            Ok(extracted) if extracted.width > 0 && extracted.height > 0 => (
+                extracted.width,
+                extracted.height,
+                scissor_pixels(&scissor.0, extracted),
+            ),
+            _ => return RenderCommandResult::Failure,
+        };
+        let [x, y, w, h] = match rect {
+            Some(rect) => rect,
+            None => return RenderCommandResult::Failure,
+        };
+        pass.set_scissor_rect(x, y, w, h);
+        let result = C::render(view, item, param, pass);
+        pass.set_scissor_rect(0, 0, width, height);
+        result
+    }
+}
+
+/// The render-world buffer of a `StreamingTriangle`, see `prepare_streaming_triangles`.
+#[derive(Component)]
+pub struct StreamingVertexBuffer {
+    pub buffer: Buffer,
+    pub vertex_count: u32,
+}
+
+pub type DrawStreamingTriangle = (
+    SetItemPipeline,
+    SetViewBindGroup<0>,
+    SetMeshBindGroup<1>,
+    Scissored<DrawStreamingMesh>,
+);
+
+pub struct DrawStreamingMesh;
+impl EntityRenderCommand for DrawStreamingMesh {
+    type Param = (SRes<DrawLog>, SQuery<Read<StreamingVertexBuffer>>);
+    #[inline]
+    fn render<'w>(
+        _view: Entity,
+        item: Entity,
+        (log, buffer_query): SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let streaming = match buffer_query.get(item) {
+            Ok(x) => x,
+            Err(_) => {
+                log.once("streaming triangle is missing its vertex buffer");
+                return RenderCommandResult::Failure;
+            }
+        };
+        pass.set_vertex_buffer(0, streaming.buffer.slice(..));
+        pass.draw(0..streaming.vertex_count, 0..1);
+        RenderCommandResult::Success
+    }
+}
+
+/// The vertex buffer of a `TriangleBatch`, see `prepare_triangle_batches`.
+#[derive(Component)]
+pub struct TriangleBatchBuffer(pub Buffer);
+
+/// Batched triangles aren't scissored: a batch mixes triangles with and without scissors.
+pub type DrawBatchedTriangles = (
+    SetItemPipeline,
+    SetViewBindGroup<0>,
+    SetMeshBindGroup<1>,
+    DrawBatchedMesh,
+);
+
+/// Draws the vertices of a `TriangleBatch` in the item's `batch_range`, which Bevy's
+/// `batch_phase_system` has widened to cover its neighbors in the same batch.
+pub struct DrawBatchedMesh;
+impl RenderCommand<Transparent2d> for DrawBatchedMesh {
+    type Param = (SRes<DrawLog>, SQuery<Read<TriangleBatchBuffer>>);
+    #[inline]
+    fn render<'w>(
+        _view: Entity,
+        item: &Transparent2d,
+        (log, buffer_query): SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let (buffer, range) = match (buffer_query.get(item.entity), &item.batch_range) {
+            (Ok(buffer), Some(range)) => (buffer, range),
+            _ => {
+                log.once("batched triangles are missing their vertex buffer or range");
+                return RenderCommandResult::Failure;
+            }
+        };
+        pass.set_vertex_buffer(0, buffer.0.slice(..));
+        pass.draw(range.clone(), 0..1);
+        RenderCommandResult::Success
+    }
+}
+
+/// The render-world instance buffer of a `TriangleInstances` entity, see
+/// `prepare_triangle_instances`.
+#[derive(Component)]
+pub struct TriangleInstanceBuffer {
+    pub buffer: Buffer,
+    pub instance_count: u32,
+}
+
+/// Draws the item's mesh, once per instance in its `TriangleInstanceBuffer` if it has one.
+pub struct DrawTriangleMesh;
+impl EntityRenderCommand for DrawTriangleMesh {
+    type Param = (
+        SRes<DrawLog>,
+        SRes<RenderAssets<Mesh>>,
+        SQuery<Read<TriangleMeshHandle>>,
+        SQuery<Read<TriangleInstanceBuffer>>,
+    );
+    #[inline]
+    fn render<'w>(
+        _view: Entity,
+        item: Entity,
+        (log, meshes, mesh_query, instance_query): SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let mesh_handle = match mesh_query.get(item) {
+            Ok(x) => &x.0,
+            Err(_) => {
+                log.once("triangle is missing its mesh handle");
+                return RenderCommandResult::Failure;
+            }
+        };
+        let gpu_mesh = match meshes.into_inner().get(mesh_handle) {
+            Some(x) => x,
+            None => {
+                // `queue_triangles` skips unprepared meshes, so this is at most a
+                // transient race, not worth more than a debug line.
+                log.once_at(
+                    Some(Level::DEBUG),
+                    "triangle's mesh isn't prepared yet, skipping it",
+                );
+                return RenderCommandResult::Failure;
+            }
+        };
+        pass.set_vertex_buffer(0, gpu_mesh.vertex_buffer.slice(..));
+        let instances = match instance_query.get(item) {
+            Ok(instances) => {
+                pass.set_vertex_buffer(1, instances.buffer.slice(..));
+                0..instances.instance_count
+            }
+            Err(_) => 0..1,
+        };
+        match &gpu_mesh.buffer_info {
+            GpuBufferInfo::Indexed {
+                buffer,
+                index_format,
+                count,
+            } => {
+                pass.set_index_buffer(buffer.slice(..), 0, *index_format);
+                pass.draw_indexed(0..*count, 0, instances);
+            }
+            GpuBufferInfo::NonIndexed { vertex_count } => {
+                pass.draw(0..*vertex_count, instances);
+            }
+        }
+        RenderCommandResult::Success
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::plugin::TriangleRenderPlugin;
+    use crate::render::testing;
+    use crate::{Triangle, TriangleBundle};
+    use bevy::render::{RenderApp, RenderStage};
+    use bevy::utils::tracing::{span, subscriber, Event, Metadata, Subscriber};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn spawn(app: &mut App, rgba: [f32; 4], side: f32, at: Vec3) -> Entity {
+        app.world
+            .spawn()
+            .insert_bundle(TriangleBundle::at(Triangle::side(side).with_rgba(rgba), at))
+            .id()
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn scissor_clips_only_its_own_triangle() {
+        let mut app = testing::gpu_app(TriangleRenderPlugin::default());
+        // Covers the whole view, clipped to its left half.
+        let clipped = spawn(
+            &mut app,
+            [1.0, 0.0, 0.0, 1.0],
+            200.0,
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+        app.world.entity_mut(clipped).insert(TriangleScissor(Rect {
+            left: -32.0,
+            right: 0.0,
+            top: 32.0,
+            bottom: -32.0,
+        }));
+        // Drawn next, without a scissor.
+        spawn(
+            &mut app,
+            [0.0, 1.0, 0.0, 1.0],
+            20.0,
+            Vec3::new(16.0, 0.0, 2.0),
+        );
+        // Scissored to a rect that's off screen.
+        let hidden = spawn(
+            &mut app,
+            [0.0, 0.0, 1.0, 1.0],
+            20.0,
+            Vec3::new(-16.0, -20.0, 3.0),
+        );
+        app.world.entity_mut(hidden).insert(TriangleScissor(Rect {
+            left: 100.0,
+            right: 120.0,
+            top: 120.0,
+            bottom: 100.0,
+        }));
+
+        let pixels = testing::render(&mut app);
+        assert_eq!(
+            testing::pixel_at(&pixels, Vec2::new(-16.0, 0.0)),
+            [255, 0, 0, 255]
+        );
+        assert_eq!(
+            testing::pixel_at(&pixels, Vec2::new(16.0, 10.0)),
+            [0, 0, 0, 0]
+        );
+        assert_eq!(
+            testing::pixel_at(&pixels, Vec2::new(16.0, 0.0)),
+            [0, 255, 0, 255]
+        );
+        assert_eq!(
+            testing::pixel_at(&pixels, Vec2::new(-16.0, -20.0)),
+            [255, 0, 0, 255]
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn a_missing_uniform_index_skips_the_draw() {
+        let mut app = testing::gpu_app(TriangleRenderPlugin::default());
+        let broken = spawn(
+            &mut app,
+            [1.0, 0.0, 0.0, 1.0],
+            20.0,
+            Vec3::new(-16.0, 0.0, 1.0),
+        );
+        spawn(
+            &mut app,
+            [0.0, 1.0, 0.0, 1.0],
+            20.0,
+            Vec3::new(16.0, 0.0, 1.0),
+        );
+        // After queueing, as if the index went missing in between.
+        app.sub_app_mut(RenderApp).add_system_to_stage(
+            RenderStage::PhaseSort,
+            move |mut commands: Commands| {
+                commands
+                    .entity(broken)
+                    .remove::<DynamicUniformIndex<TriangleUniform>>();
+            },
+        );
+
+        let pixels = testing::render(&mut app);
+        assert_eq!(
+            testing::pixel_at(&pixels, Vec2::new(-16.0, 0.0)),
+            [0, 0, 0, 0]
+        );
+        assert_eq!(
+            testing::pixel_at(&pixels, Vec2::new(16.0, 0.0)),
+            [0, 255, 0, 255]
+        );
+        let log = app
+            .sub_app_mut(RenderApp)
+            .world
+            .get_resource::<DrawLog>()
+            .unwrap();
+        assert!(log.logged("triangle is missing its uniform index"));
+    }
+
+    static ERRORS: AtomicUsize = AtomicUsize::new(0);
+
+    /// Counts `ERROR` events from this crate, on any thread. The tests don't install a
+    /// subscriber otherwise, so it can be the global one.
+    struct ErrorCounter;
+
+    impl Subscriber for ErrorCounter {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            *metadata.level() == Level::ERROR && metadata.target().starts_with("bevy_experiments")
+        }
+
+        fn new_span(&self, _span: &span::Attributes) -> span::Id {
+            span::Id::from_u64(1)
+        }
+
+        fn record(&self, _span: &span::Id, _values: &span::Record) {}
+
+        fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+        fn event(&self, _event: &Event) {
+            ERRORS.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn enter(&self, _span: &span::Id) {}
+
+        fn exit(&self, _span: &span::Id) {}
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn unprepared_meshes_log_no_errors_in_the_first_frames() {
+        let _ = subscriber::set_global_default(ErrorCounter);
+        // Even with the draw log turned up to errors, meshes that aren't ready yet are
+        // only worth a debug line.
+        let mut app =
+            testing::gpu_app(TriangleRenderPlugin::default().with_log_level(Some(Level::ERROR)));
+        for i in 0..100 {
+            let at = Vec3::new(
+                (i % 10) as f32 * 6.0 - 30.0,
+                (i / 10) as f32 * 6.0 - 30.0,
+                0.0,
+            );
+            spawn(&mut app, [1.0; 4], 5.0, at);
+        }
+        app.update();
+        assert_eq!(ERRORS.load(Ordering::Relaxed), 0);
+        testing::render(&mut app);
+        assert_eq!(ERRORS.load(Ordering::Relaxed), 0);
+    }
+}
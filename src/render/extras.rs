@@ -0,0 +1,255 @@
+//! Per-triangle uniforms for custom shaders, on top of `TriangleUniform`.
+//!
+//! Define the data as a component that can go into a std140 uniform:
+//!
+//! ```ignore
+//! #[derive(Clone, Component, AsStd140)]
+//! struct Wobble {
+//!     amount: f32,
+//! }
+//! ```
+//!
+//! Add `TriangleExtras::<Wobble>::default()` after `TriangleRenderPlugin`, and every
+//! triangle with a `Wobble` gets it uploaded to `ComponentUniforms<Wobble>`. Drawing it
+//! takes a pipeline whose layout ends with `TriangleExtrasLayout::<Wobble>`, e.g. one that
+//! wraps `TrianglePipeline::specialize` and pushes the layout (group 2 for untextured
+//! keys, 3 for textured ones), and a draw function with the bind group at that index:
+//!
+//! ```ignore
+//! type DrawWobblyTriangle = (
+//!     SetItemPipeline,
+//!     SetViewBindGroup<0>,
+//!     SetMeshBindGroup<1>,
+//!     SetExtrasBindGroup<Wobble, 2>,
+//!     DrawTriangleMesh,
+//! );
+//! ```
+//!
+//! The shader then declares the struct at that group:
+//!
+//! ```wgsl
+//! struct Wobble {
+//!     amount: f32;
+//! };
+//! [[group(2), binding(0)]]
+//! var<uniform> wobble: Wobble;
+//! ```
+
+use bevy::ecs::system::{lifetimeless::*, SystemParamItem};
+use bevy::prelude::*;
+use bevy::render::render_component::{
+    ComponentUniforms, DynamicUniformIndex, UniformComponentPlugin,
+};
+use bevy::render::render_phase::{EntityRenderCommand, RenderCommandResult, TrackedRenderPass};
+use bevy::render::render_resource::std140::AsStd140;
+use bevy::render::render_resource::{BindGroup, BindGroupLayout};
+use bevy::render::renderer::RenderDevice;
+use bevy::render::{RenderApp, RenderStage};
+use std::marker::PhantomData;
+
+use crate::TriangleMeshHandle;
+
+use super::draw::DrawLog;
+use super::wgpu;
+
+/// Uploads every triangle's `T` into `ComponentUniforms<T>`. See the module docs.
+pub struct TriangleExtras<T>(PhantomData<T>);
+
+impl<T> Default for TriangleExtras<T> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T: AsStd140 + Component + Clone> Plugin for TriangleExtras<T> {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(UniformComponentPlugin::<T>::default());
+        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app
+                .init_resource::<TriangleExtrasLayout<T>>()
+                .add_system_to_stage(RenderStage::Extract, extract_extras::<T>)
+                .add_system_to_stage(RenderStage::Queue, queue_extras_bind_group::<T>);
+        }
+    }
+}
+
+/// The layout of the bind group holding `T`, for custom pipelines to append.
+pub struct TriangleExtrasLayout<T> {
+    pub layout: BindGroupLayout,
+    marker: PhantomData<T>,
+}
+
+impl<T: AsStd140> FromWorld for TriangleExtrasLayout<T> {
+    fn from_world(world: &mut World) -> Self {
+        let device = world.get_resource::<RenderDevice>().unwrap();
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(T::std140_size_static() as u64),
+                },
+                count: None,
+            }],
+            label: Some("triangle extras layout"),
+        });
+        Self {
+            layout,
+            marker: PhantomData,
+        }
+    }
+}
+
+pub struct TriangleExtrasBindGroup<T> {
+    pub bind_group: BindGroup,
+    marker: PhantomData<T>,
+}
+
+/// Extracts the `T` of visible triangles only, so other entities that happen to have one
+/// don't take a slot in `ComponentUniforms<T>`.
+fn extract_extras<T: Component + Clone>(
+    mut commands: Commands,
+    extras_q: Query<(Entity, &T, &ComputedVisibility), With<TriangleMeshHandle>>,
+) {
+    let components = extras_q
+        .iter()
+        .filter(|(_, _, vis)| vis.is_visible)
+        .map(|(entity, extras, _)| (entity, (extras.clone(),)))
+        .collect::<Vec<_>>();
+    commands.insert_or_spawn_batch(components);
+}
+
+fn queue_extras_bind_group<T: AsStd140 + Component>(
+    mut commands: Commands,
+    layout: Res<TriangleExtrasLayout<T>>,
+    render_device: Res<RenderDevice>,
+    uniforms: Res<ComponentUniforms<T>>,
+) {
+    let binding = match uniforms.uniforms().binding() {
+        Some(binding) => binding,
+        None => {
+            commands.remove_resource::<TriangleExtrasBindGroup<T>>();
+            return;
+        }
+    };
+    commands.insert_resource(TriangleExtrasBindGroup::<T> {
+        bind_group: render_device.create_bind_group(&wgpu::BindGroupDescriptor {
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: binding,
+            }],
+            label: Some("triangle extras bind group"),
+            layout: &layout.layout,
+        }),
+        marker: PhantomData,
+    });
+}
+
+/// Binds the item's `T` at group `I`.
+pub struct SetExtrasBindGroup<T, const I: usize>(PhantomData<T>);
+impl<T: AsStd140 + Component, const I: usize> EntityRenderCommand for SetExtrasBindGroup<T, I> {
+    type Param = (
+        SRes<DrawLog>,
+        Option<SRes<TriangleExtrasBindGroup<T>>>,
+        SQuery<Read<DynamicUniformIndex<T>>>,
+    );
+    #[inline]
+    fn render<'w>(
+        _view: Entity,
+        item: Entity,
+        (log, bind_group, index_query): SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let index = match index_query.get(item) {
+            Ok(x) => x,
+            Err(_) => {
+                log.once("triangle is missing its extras uniform index");
+                return RenderCommandResult::Failure;
+            }
+        };
+        let bind_group = match bind_group {
+            Some(x) => x.into_inner(),
+            None => {
+                log.once("the triangle extras bind group is missing");
+                return RenderCommandResult::Failure;
+            }
+        };
+        pass.set_bind_group(I, &bind_group.bind_group, &[index.index()]);
+        RenderCommandResult::Success
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::render::render_resource::std140::AsStd140;
+
+    use crate::render::plugin::TriangleRenderPlugin;
+    use crate::render::testing;
+    use crate::{Triangle, TriangleBundle};
+
+    #[derive(Clone, Component, AsStd140)]
+    struct Wobble {
+        amount: f32,
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn extras_are_uploaded_for_triangles_that_have_them() {
+        let mut app = testing::gpu_app(TriangleRenderPlugin::default());
+        app.add_plugin(TriangleExtras::<Wobble>::default());
+        let wobbly = [0.5, 2.0].map(|amount| {
+            app.world
+                .spawn()
+                .insert_bundle(TriangleBundle::new(Triangle::side(10.0)))
+                .insert(Wobble { amount })
+                .id()
+        });
+        // And one without, and a `Wobble` on something that isn't a triangle, which get
+        // no slot.
+        app.world
+            .spawn()
+            .insert_bundle(TriangleBundle::new(Triangle::side(10.0)));
+        app.world
+            .spawn()
+            .insert_bundle((Transform::default(), GlobalTransform::default()))
+            .insert(Wobble { amount: 1.0 });
+        // Render-world entities are cleared at the end of the frame, so note the offsets
+        // while they're there.
+        #[derive(Default)]
+        struct Offsets(Vec<(Entity, u32)>);
+        app.sub_app_mut(RenderApp)
+            .init_resource::<Offsets>()
+            .add_system_to_stage(
+                RenderStage::Queue,
+                |mut offsets: ResMut<Offsets>,
+                 index_q: Query<(Entity, &DynamicUniformIndex<Wobble>)>| {
+                    offsets.0 = index_q
+                        .iter()
+                        .map(|(entity, index)| (entity, index.index()))
+                        .collect();
+                },
+            );
+        app.update();
+
+        let world = &app.sub_app_mut(RenderApp).world;
+        let uniforms = world.get_resource::<ComponentUniforms<Wobble>>().unwrap();
+        assert_eq!(uniforms.uniforms().len(), 2);
+        assert!(uniforms.uniforms().uniform_buffer().is_some());
+        let mut offsets = world.get_resource::<Offsets>().unwrap().0.clone();
+        offsets.sort();
+        assert_eq!(
+            offsets
+                .iter()
+                .map(|(entity, _)| *entity)
+                .collect::<Vec<_>>(),
+            wobbly
+        );
+        assert_ne!(offsets[0].1, offsets[1].1);
+        assert!(world
+            .get_resource::<TriangleExtrasBindGroup<Wobble>>()
+            .is_some());
+    }
+}
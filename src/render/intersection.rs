@@ -0,0 +1,530 @@
+//! Tints the pixels covered by two or more triangles, see
+//! `TriangleIntersectionHighlightPlugin`.
+
+use bevy::core_pipeline::{draw_2d_graph, Transparent2d};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::render::camera::{ActiveCameras, CameraPlugin};
+use bevy::render::render_graph::{
+    Node, NodeRunError, RenderGraph, RenderGraphContext, SlotInfo, SlotType,
+};
+use bevy::render::render_phase::{
+    AddRenderCommand, CachedPipelinePhaseItem, DrawFunctionId, DrawFunctions, EntityPhaseItem,
+    PhaseItem, RenderPhase, TrackedRenderPass,
+};
+use bevy::render::render_resource::{
+    BindGroup, BindGroupLayout, Buffer, CachedPipelineId, FragmentState, RenderPipelineCache,
+    RenderPipelineDescriptor, SpecializedPipeline, SpecializedPipelines, VertexState,
+};
+use bevy::render::renderer::{RenderContext, RenderDevice, RenderQueue};
+use bevy::render::texture::{BevyDefault, CachedTexture, TextureCache};
+use bevy::render::view::{ExtractedView, ViewTarget};
+use bevy::render::{RenderApp, RenderStage};
+
+use super::draw::DrawTriangle;
+use super::pipeline::{TrianglePipeline, TrianglePipelineKey, TrianglePipelineSpecializations};
+use super::plugin::{first_build, TriangleRenderSystem};
+use super::{wgpu, HaloSource};
+
+/// `triangle_intersection.wgsl`, the fullscreen tint.
+pub const SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0x5d3a9e61b2c07f48);
+
+const NODE: &str = "triangle_intersection";
+const STENCIL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24PlusStencil8;
+/// Stencil counts at or above this are tinted.
+const OVERLAP: u32 = 2;
+
+/// How `TriangleIntersectionHighlightPlugin` tints overlaps.
+#[derive(Clone, Copy, Debug)]
+pub struct TriangleIntersectionHighlight {
+    /// Blended over every pixel covered by two or more triangles.
+    pub color: Color,
+}
+
+impl Default for TriangleIntersectionHighlight {
+    fn default() -> Self {
+        Self {
+            color: Color::rgba(1.0, 1.0, 1.0, 0.5),
+        }
+    }
+}
+
+/// Blends `TriangleIntersectionHighlight::color` over the pixels covered by two or
+/// more triangles. Needs `TriangleDrawPlugin` added first.
+///
+/// After the 2d main pass, every triangle in `Transparent2d` is drawn again with its
+/// own shader but no color writes, incrementing a stencil attachment for each fragment
+/// it keeps. One fullscreen triangle then tints where the count reached 2. That costs
+/// a `Depth24PlusStencil8` attachment per 2d view at the `Msaa` sample count (4 bytes
+/// a sample, about 33 MB at 1920x1080 with 4x MSAA), a second draw call per triangle
+/// and a fullscreen pass, all skipped for views with fewer than two triangles.
+///
+/// Glows, shadows and streaming triangles aren't counted. Transparent parts the shader
+/// doesn't discard, like pattern gaps, are. The tint goes over everything in the main
+/// pass, sprites included.
+#[derive(Default)]
+pub struct TriangleIntersectionHighlightPlugin;
+
+impl Plugin for TriangleIntersectionHighlightPlugin {
+    fn build(&self, app: &mut App) {
+        if !first_build::<Self>(app) {
+            return;
+        }
+        app.init_resource::<TriangleIntersectionHighlight>();
+        if app.get_sub_app_mut(RenderApp).is_err() {
+            return;
+        }
+        let mut shaders = app.world.get_resource_mut::<Assets<Shader>>().unwrap();
+        shaders.set_untracked(
+            SHADER_HANDLE,
+            Shader::from_wgsl(include_str!("triangle_intersection.wgsl")),
+        );
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app
+            .init_resource::<DrawFunctions<IntersectionStencil>>()
+            .init_resource::<IntersectionStencilPipeline>()
+            .init_resource::<SpecializedPipelines<IntersectionStencilPipeline>>()
+            .init_resource::<IntersectionTintPipeline>()
+            .init_resource::<SpecializedPipelines<IntersectionTintPipeline>>()
+            .add_render_command::<IntersectionStencil, DrawTriangle>()
+            .add_system_to_stage(RenderStage::Extract, extract_intersection_highlight)
+            .add_system_to_stage(RenderStage::Prepare, prepare_intersection_stencils)
+            .add_system_to_stage(
+                RenderStage::Queue,
+                queue_intersection_stencil.after(TriangleRenderSystem::QueueTriangles),
+            );
+
+        let node = IntersectionNode::new(&mut render_app.world);
+        let mut graph = render_app.world.get_resource_mut::<RenderGraph>().unwrap();
+        let draw_2d = graph.get_sub_graph_mut(draw_2d_graph::NAME).unwrap();
+        draw_2d.add_node(NODE, node);
+        draw_2d
+            .add_slot_edge(
+                RenderGraph::INPUT_NODE_NAME,
+                draw_2d_graph::input::VIEW_ENTITY,
+                NODE,
+                IntersectionNode::IN_VIEW,
+            )
+            .unwrap();
+        draw_2d
+            .add_node_edge(draw_2d_graph::node::MAIN_PASS, NODE)
+            .unwrap();
+    }
+}
+
+/// A triangle drawn into the view's stencil to count the triangles covering each pixel.
+pub struct IntersectionStencil {
+    pub entity: Entity,
+    pub pipeline: CachedPipelineId,
+    pub draw_function: DrawFunctionId,
+}
+
+impl PhaseItem for IntersectionStencil {
+    /// Counting doesn't depend on the order, so the phase is never sorted.
+    type SortKey = ();
+
+    fn sort_key(&self) -> Self::SortKey {}
+
+    fn draw_function(&self) -> DrawFunctionId {
+        self.draw_function
+    }
+}
+
+impl EntityPhaseItem for IntersectionStencil {
+    fn entity(&self) -> Entity {
+        self.entity
+    }
+}
+
+impl CachedPipelinePhaseItem for IntersectionStencil {
+    fn cached_pipeline(&self) -> CachedPipelineId {
+        self.pipeline
+    }
+}
+
+/// The stencil attachment of a view and the tint pipeline for its sample count.
+#[derive(Component)]
+pub struct ViewIntersectionStencil {
+    pub texture: CachedTexture,
+    pub tint_pipeline: CachedPipelineId,
+}
+
+fn stencil_state(face: wgpu::StencilFaceState) -> wgpu::DepthStencilState {
+    wgpu::DepthStencilState {
+        format: STENCIL_FORMAT,
+        depth_write_enabled: false,
+        depth_compare: wgpu::CompareFunction::Always,
+        stencil: wgpu::StencilState {
+            front: face,
+            back: face,
+            read_mask: !0,
+            write_mask: !0,
+        },
+        bias: wgpu::DepthBiasState::default(),
+    }
+}
+
+/// `TrianglePipeline` with its color writes masked off, incrementing the stencil for
+/// every fragment that isn't discarded.
+pub struct IntersectionStencilPipeline {
+    triangle: TrianglePipeline,
+}
+
+impl FromWorld for IntersectionStencilPipeline {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            triangle: world.get_resource::<TrianglePipeline>().unwrap().clone(),
+        }
+    }
+}
+
+impl SpecializedPipeline for IntersectionStencilPipeline {
+    type Key = TrianglePipelineKey;
+
+    fn specialize(&self, key: Self::Key) -> RenderPipelineDescriptor {
+        let mut descriptor = self.triangle.specialize(key);
+        if let Some(fragment) = &mut descriptor.fragment {
+            for target in &mut fragment.targets {
+                target.blend = None;
+                target.write_mask = wgpu::ColorWrites::empty();
+            }
+        }
+        descriptor.depth_stencil = Some(stencil_state(wgpu::StencilFaceState {
+            compare: wgpu::CompareFunction::Always,
+            fail_op: wgpu::StencilOperation::Keep,
+            depth_fail_op: wgpu::StencilOperation::Keep,
+            pass_op: wgpu::StencilOperation::IncrementClamp,
+        }));
+        descriptor.label = Some("triangle intersection stencil pipeline".into());
+        descriptor
+    }
+}
+
+/// The fullscreen tint, keyed by MSAA sample count, and the uniform buffer holding
+/// `TriangleIntersectionHighlight::color`.
+pub struct IntersectionTintPipeline {
+    layout: BindGroupLayout,
+    color_buffer: Buffer,
+    bind_group: BindGroup,
+}
+
+impl FromWorld for IntersectionTintPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let device = world.get_resource::<RenderDevice>().unwrap();
+        let color_size = std::mem::size_of::<Vec4>() as u64;
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(color_size),
+                },
+                count: None,
+            }],
+            label: Some("triangle intersection tint layout"),
+        });
+        let color_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("triangle intersection tint buffer"),
+            size: color_size,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: color_buffer.as_entire_binding(),
+            }],
+            label: Some("triangle intersection tint bind group"),
+            layout: &layout,
+        });
+        Self {
+            layout,
+            color_buffer,
+            bind_group,
+        }
+    }
+}
+
+impl SpecializedPipeline for IntersectionTintPipeline {
+    type Key = u32;
+
+    fn specialize(&self, msaa_samples: Self::Key) -> RenderPipelineDescriptor {
+        RenderPipelineDescriptor {
+            vertex: VertexState {
+                shader: SHADER_HANDLE.typed::<Shader>(),
+                entry_point: "vertex".into(),
+                shader_defs: Vec::new(),
+                buffers: Vec::new(),
+            },
+            fragment: Some(FragmentState {
+                shader: SHADER_HANDLE.typed::<Shader>(),
+                shader_defs: Vec::new(),
+                entry_point: "fragment".into(),
+                targets: vec![wgpu::ColorTargetState {
+                    format: wgpu::TextureFormat::bevy_default(),
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            layout: Some(vec![self.layout.clone()]),
+            primitive: wgpu::PrimitiveState::default(),
+            // Passes where `OVERLAP`, the reference, is at most the count.
+            depth_stencil: Some(stencil_state(wgpu::StencilFaceState {
+                compare: wgpu::CompareFunction::LessEqual,
+                fail_op: wgpu::StencilOperation::Keep,
+                depth_fail_op: wgpu::StencilOperation::Keep,
+                pass_op: wgpu::StencilOperation::Keep,
+            })),
+            multisample: wgpu::MultisampleState {
+                count: msaa_samples,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            label: Some("triangle intersection tint pipeline".into()),
+        }
+    }
+}
+
+/// Gives the 2d camera its `RenderPhase<IntersectionStencil>` and copies the
+/// highlight settings over.
+pub fn extract_intersection_highlight(
+    mut commands: Commands,
+    active_cameras: Res<ActiveCameras>,
+    highlight: Res<TriangleIntersectionHighlight>,
+) {
+    commands.insert_resource(*highlight);
+    let camera_2d = active_cameras.get(CameraPlugin::CAMERA_2D);
+    if let Some(entity) = camera_2d.and_then(|camera| camera.entity) {
+        commands
+            .get_or_spawn(entity)
+            .insert(RenderPhase::<IntersectionStencil>::default());
+    }
+}
+
+/// Uploads the highlight color and gets each view a stencil attachment.
+#[allow(clippy::too_many_arguments)]
+pub fn prepare_intersection_stencils(
+    mut commands: Commands,
+    mut texture_cache: ResMut<TextureCache>,
+    mut pipelines: ResMut<SpecializedPipelines<IntersectionTintPipeline>>,
+    mut pipeline_cache: ResMut<RenderPipelineCache>,
+    tint_pipeline: Res<IntersectionTintPipeline>,
+    highlight: Res<TriangleIntersectionHighlight>,
+    msaa: Res<Msaa>,
+    device: Res<RenderDevice>,
+    queue: Res<RenderQueue>,
+    views: Query<(Entity, &ExtractedView), With<RenderPhase<IntersectionStencil>>>,
+) {
+    let color = Vec4::from(highlight.color).to_array();
+    queue.write_buffer(
+        &tint_pipeline.color_buffer,
+        0,
+        bevy::core::cast_slice(&color),
+    );
+    let tint_pipeline = pipelines.specialize(&mut pipeline_cache, &tint_pipeline, msaa.samples);
+    for (entity, view) in views.iter() {
+        let texture = texture_cache.get(
+            &device,
+            wgpu::TextureDescriptor {
+                label: Some("triangle intersection stencil"),
+                size: wgpu::Extent3d {
+                    width: view.width,
+                    height: view.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: msaa.samples,
+                dimension: wgpu::TextureDimension::D2,
+                format: STENCIL_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            },
+        );
+        commands.entity(entity).insert(ViewIntersectionStencil {
+            texture,
+            tint_pipeline,
+        });
+    }
+}
+
+/// Queues a stencil copy of every triangle `queue_triangles` put in a view's
+/// `Transparent2d`, leaving out glows and shadows.
+#[allow(clippy::too_many_arguments)]
+pub fn queue_intersection_stencil(
+    mut view_q: Query<(
+        &RenderPhase<Transparent2d>,
+        &mut RenderPhase<IntersectionStencil>,
+    )>,
+    transparent_draw_functions: Res<DrawFunctions<Transparent2d>>,
+    draw_functions: Res<DrawFunctions<IntersectionStencil>>,
+    mut pipelines: ResMut<SpecializedPipelines<IntersectionStencilPipeline>>,
+    mut pipeline_cache: ResMut<RenderPipelineCache>,
+    pipeline: Res<IntersectionStencilPipeline>,
+    specializations: Res<TrianglePipelineSpecializations>,
+    halo_q: Query<(), With<HaloSource>>,
+) {
+    let triangle_draw_function = transparent_draw_functions
+        .read()
+        .get_id::<DrawTriangle>()
+        .unwrap();
+    let draw_function = draw_functions.read().get_id::<DrawTriangle>().unwrap();
+    for (transparent_phase, mut phase) in view_q.iter_mut() {
+        for item in &transparent_phase.items {
+            if item.draw_function != triangle_draw_function || halo_q.get(item.entity).is_ok() {
+                continue;
+            }
+            let key = match specializations.key(item.pipeline) {
+                Some(key) => key,
+                None => continue,
+            };
+            let pipeline_id = pipelines.specialize(&mut pipeline_cache, &pipeline, key);
+            if pipeline_cache.get(pipeline_id).is_none() {
+                continue;
+            }
+            phase.add(IntersectionStencil {
+                entity: item.entity,
+                pipeline: pipeline_id,
+                draw_function,
+            });
+        }
+    }
+}
+
+/// Counts coverage into the stencil, then tints the overlaps, in one pass after the
+/// 2d main pass.
+struct IntersectionNode {
+    query: QueryState<
+        (
+            &'static RenderPhase<IntersectionStencil>,
+            &'static ViewTarget,
+            &'static ViewIntersectionStencil,
+        ),
+        With<ExtractedView>,
+    >,
+}
+
+impl IntersectionNode {
+    const IN_VIEW: &'static str = "view";
+
+    fn new(world: &mut World) -> Self {
+        Self {
+            query: QueryState::new(world),
+        }
+    }
+}
+
+impl Node for IntersectionNode {
+    fn input(&self) -> Vec<SlotInfo> {
+        vec![SlotInfo::new(Self::IN_VIEW, SlotType::Entity)]
+    }
+
+    fn update(&mut self, world: &mut World) {
+        self.query.update_archetypes(world);
+    }
+
+    fn run(
+        &self,
+        graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let view_entity = graph.get_input_entity(Self::IN_VIEW)?;
+        let (phase, target, stencil) = match self.query.get_manual(world, view_entity) {
+            Ok(x) => x,
+            Err(_) => return Ok(()),
+        };
+        if phase.items.len() < OVERLAP as usize {
+            return Ok(());
+        }
+        let pipeline_cache = world.get_resource::<RenderPipelineCache>().unwrap();
+        let tint_pipeline = match pipeline_cache.get(stencil.tint_pipeline) {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+        let pass_descriptor = wgpu::RenderPassDescriptor {
+            label: Some("triangle_intersection"),
+            color_attachments: &[target.get_color_attachment(wgpu::Operations {
+                load: wgpu::LoadOp::Load,
+                store: true,
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &stencil.texture.default_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(0.0),
+                    store: false,
+                }),
+                stencil_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(0),
+                    store: false,
+                }),
+            }),
+        };
+        let draw_functions = world
+            .get_resource::<DrawFunctions<IntersectionStencil>>()
+            .unwrap();
+        let render_pass = render_context
+            .command_encoder
+            .begin_render_pass(&pass_descriptor);
+        let mut draw_functions = draw_functions.write();
+        let mut tracked_pass = TrackedRenderPass::new(render_pass);
+        for item in phase.items.iter() {
+            let draw_function = draw_functions.get_mut(item.draw_function).unwrap();
+            draw_function.draw(world, &mut tracked_pass, view_entity, item);
+        }
+        tracked_pass.set_render_pipeline(tint_pipeline);
+        let tint = world.get_resource::<IntersectionTintPipeline>().unwrap();
+        tracked_pass.set_bind_group(0, &tint.bind_group, &[]);
+        tracked_pass.set_stencil_reference(OVERLAP);
+        tracked_pass.draw(0..3, 0..1);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::plugin::TriangleRenderPlugin;
+    use crate::render::testing;
+    use crate::{Triangle, TriangleBundle};
+
+    /// Gives `gpu_app`'s view a stencil phase: it isn't the active 2d camera.
+    fn add_stencil_phase(mut commands: Commands, view_q: Query<Entity, With<Camera>>) {
+        for entity in view_q.iter() {
+            commands
+                .get_or_spawn(entity)
+                .insert(RenderPhase::<IntersectionStencil>::default());
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn only_the_overlap_is_highlighted() {
+        let mut app = testing::gpu_app(TriangleRenderPlugin::default());
+        app.add_plugin(TriangleIntersectionHighlightPlugin)
+            .insert_resource(ClearColor(Color::NONE))
+            .insert_resource(TriangleIntersectionHighlight {
+                color: Color::rgb(0.0, 1.0, 0.0),
+            });
+        testing::draw_2d_graph(&mut app);
+        app.sub_app_mut(RenderApp)
+            .add_system_to_stage(RenderStage::Extract, add_stencil_phase);
+        let red = [1.0, 0.0, 0.0, 1.0];
+        let blue = [0.0, 0.0, 1.0, 1.0];
+        for (x, rgba) in [(-6.0, red), (6.0, blue)] {
+            app.world.spawn().insert_bundle(TriangleBundle::at(
+                Triangle::side(24.0).with_rgba(rgba),
+                Vec3::new(x, 0.0, 1.0 + x / 6.0),
+            ));
+        }
+
+        let pixels = testing::render(&mut app);
+        let at = |x, y| testing::pixel_at(&pixels, Vec2::new(x, y));
+        assert_eq!(at(-10.0, -4.0), [255, 0, 0, 255]);
+        assert_eq!(at(10.0, -4.0), [0, 0, 255, 255]);
+        assert_eq!(at(0.0, -4.0), [0, 255, 0, 255]);
+        // Between the tips, where neither triangle reaches.
+        assert_eq!(at(0.0, 9.0), [0; 4]);
+    }
+}
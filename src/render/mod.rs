@@ -0,0 +1,326 @@
+//! The render-world half: extraction, pipelines and draw commands.
+
+use bevy::asset::HandleId;
+use bevy::prelude::*;
+use bevy::render::render_resource::std140::AsStd140;
+use bevy::render::render_resource::{BindGroup, Buffer, UniformVec};
+use bevy::utils::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    wgpu, Billboard, MinPixelSize, PremultipliedVertexColor, SmoothEdges, StreamingTriangle,
+    Triangle, TriangleAlphaCutoff, TriangleAnchor, TriangleBackColor, TriangleDissolve,
+    TriangleEdgeColor, TriangleEdgeColors, TriangleGlow, TriangleGroup, TriangleGroupEffects,
+    TriangleOpacity, TrianglePattern, TriangleReady, TriangleReveal, TriangleSamplerConfig,
+    TriangleShadow, TriangleSplit, TriangleUvScale, TriangleUvScroll, TriangleUvTransform,
+};
+
+pub mod draw;
+pub mod extras;
+pub mod intersection;
+pub mod pipeline;
+pub mod plugin;
+pub mod system;
+#[cfg(test)]
+mod testing;
+#[cfg(feature = "gpu-timing")]
+pub mod timing;
+
+/// What every triangle's shader reads from group 1, binding 0. Effect parameters are in
+/// `TriangleEdgeUniform` and `TriangleFillUniform`, which only triangles using them carry.
+#[derive(Clone, Component, AsStd140)]
+pub struct TriangleUniform {
+    pub transform: Mat4,
+    /// From the triangle's `TriangleGroupEffect`.
+    pub tint: Vec4,
+    /// See `TriangleOpacity`.
+    pub opacity: f32,
+    /// See `TriangleUvTransform` and `TriangleUvScale`; only read by `TEXTURED` pipelines.
+    /// Kept here since whether a triangle is drawn textured is only known once its image
+    /// has loaded.
+    pub uv_transform: Mat3,
+    /// See `TriangleUvScroll`; only read by `TEXTURED` pipelines.
+    pub uv_scroll: Vec2,
+}
+
+/// Parameters of the effects that measure the distance to the triangle's edges, bound at
+/// group 1, binding 1 for pipelines with any of `MeshBindings::EDGE`'s flags.
+#[derive(Clone, Default, Component, AsStd140)]
+pub struct TriangleEdgeUniform {
+    /// The heights above each edge in `xyz`, and the width of the `FEATHER` fade in `w`.
+    pub feather: Vec4,
+    /// Fill color of `FEATHER` pipelines, replacing the vertex colors.
+    pub glow: Vec4,
+    /// See `TriangleEdgeColor`; only read by `EDGE_COLOR` pipelines.
+    pub edge_color: Vec4,
+    pub edge_thickness: f32,
+    /// See `TriangleEdgeColors`; only read by `EDGE_COLORS` pipelines.
+    pub edge_color_a: Vec4,
+    pub edge_color_b: Vec4,
+    pub edge_color_c: Vec4,
+}
+
+/// Parameters of the effects that change how the inside of the triangle is filled, bound
+/// at group 1, binding 2 for pipelines with any of `MeshBindings::FILL`'s flags.
+#[derive(Clone, Default, Component, AsStd140)]
+pub struct TriangleFillUniform {
+    /// See `TrianglePattern::params`.
+    pub pattern: Vec4,
+    /// See `TriangleAlphaCutoff`; only read by `ALPHA_MASK` pipelines.
+    pub alpha_cutoff: f32,
+    /// See `TriangleSplit::params`; this and the split colors are only read by `SPLIT`
+    /// pipelines.
+    pub split: Vec4,
+    pub split_color_a: Vec4,
+    pub split_color_b: Vec4,
+    /// See `TriangleReveal::params`; only read by `REVEAL` pipelines.
+    pub reveal: Vec4,
+    /// See `TriangleBackColor`; only read by `BACK_COLOR` pipelines.
+    pub back_color: Vec4,
+    /// See `TriangleDissolve`; only read by `DISSOLVE` pipelines.
+    pub dissolve: Vec4,
+    pub dissolve_color: Vec4,
+}
+
+/// Marks a render-world-only entity drawing the glow or shadow of a main-world triangle.
+/// The triangle decides its visibility, and it sorts `depth` steps behind it.
+#[derive(Clone, Copy, Component)]
+pub struct HaloSource {
+    pub entity: Entity,
+    pub depth: f32,
+}
+
+/// Pipeline key bits that follow from the triangle's own components, worked out during
+/// extraction.
+#[derive(Clone, Copy, Component)]
+pub struct TriangleFeatures(pub pipeline::TrianglePipelineKey);
+
+/// One `TriangleInstance` as the vertex shader reads it from vertex buffer 1, see
+/// `pipeline::instance_attributes`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub struct TriangleInstanceData {
+    /// The columns of the instance's transform relative to its `TriangleInstances` entity's.
+    pub transform: [[f32; 4]; 4],
+    /// Its `TriangleInstanceUv`, `offset` in `xy` and `scale` in `zw`.
+    pub uv: [f32; 4],
+}
+
+// SAFETY: `repr(C)` and made only of `f32`s, so there's no padding and any bit pattern is valid.
+unsafe impl bevy::core::Zeroable for TriangleInstanceData {}
+unsafe impl bevy::core::Pod for TriangleInstanceData {}
+
+/// The visible instances of a `TriangleInstances` entity, extracted onto it. Empty when none
+/// are, so `queue_triangles` knows to leave it out.
+#[derive(Clone, Debug, Default, Component)]
+pub struct ExtractedTriangleInstances(pub Vec<TriangleInstanceData>);
+
+/// A render-world-only entity holding the vertices of `TriangleBatched` triangles, moved by
+/// their transforms, and the range of them each triangle takes up. A triangle can be split
+/// across batches, so it may show up in two.
+#[derive(Clone, Debug, Default, Component)]
+pub struct TriangleBatch {
+    pub vertices: Vec<crate::StreamingVertex>,
+    /// In the order of their `TriangleOrder::sort_z`.
+    pub triangles: Vec<BatchedTriangle>,
+}
+
+#[derive(Clone, Debug)]
+pub struct BatchedTriangle {
+    /// The main-world triangle, whose visibility decides whether the range is drawn.
+    pub entity: Entity,
+    pub sort_z: f32,
+    pub range: std::ops::Range<u32>,
+}
+
+/// Per-frame values shared by every triangle, bound next to the view uniform as
+/// `globals` so shaders can animate without CPU work. `time` is seconds since startup,
+/// `tint` is the `TriangleGlobalTint`, `alpha_clamp` the `AlphaClamp` as `(min, max)` and
+/// `fog_color` and `fog_range` the `Fog` color and `(start, end)`.
+#[derive(Clone, Copy, Debug, Default, AsStd140)]
+pub struct TriangleGlobals {
+    pub time: f32,
+    pub delta: f32,
+    pub tint: Vec4,
+    /// The view-projection `CAMERA_RELATIVE` pipelines use instead of the view's, with the
+    /// translation subtracted from triangles taken out. See
+    /// `TriangleRenderConfig::camera_relative`.
+    pub relative_view_proj: Mat4,
+    pub alpha_clamp: Vec2,
+    pub fog_color: Vec4,
+    /// The plane a world position is dotted with (`w` added) for its depth in front of
+    /// the camera. Positions are camera-relative in `CAMERA_RELATIVE` pipelines, and so is
+    /// the plane.
+    pub fog_plane: Vec4,
+    pub fog_range: Vec2,
+}
+
+#[derive(Default)]
+pub struct GlobalsBuffer(pub UniformVec<TriangleGlobals>);
+
+/// Texture bind groups by image and sampler, reused across frames until the image is
+/// modified or removed.
+#[derive(Default)]
+pub struct TextureBindGroupCache(pub HashMap<(HandleId, TriangleSamplerConfig), BindGroup>);
+
+/// Persistent vertex buffers of `StreamingTriangle`s by entity with their size in bytes,
+/// grown as needed and dropped once their entity stops being extracted.
+#[derive(Default)]
+pub struct StreamingBuffers(pub HashMap<Entity, (Buffer, u64)>);
+
+/// What the prepare systems last wrote to the GPU, kept in the render world in debug
+/// builds so tests can check the packing without reading buffers back. Mesh vertices and
+/// `TriangleUniform`s are uploaded by Bevy and can be inspected through
+/// `RenderAssets<Mesh>` and `ComponentUniforms<TriangleUniform>` instead.
+#[cfg(debug_assertions)]
+#[derive(Clone, Debug, Default)]
+pub struct LastUploadedTriangleData {
+    /// Each `StreamingTriangle`'s vertices as written to its buffer, at offset 0.
+    pub streaming: HashMap<Entity, Vec<crate::StreamingVertex>>,
+    /// Each `TriangleBatch` as written to its buffer, so with the triangles' ranges of it.
+    pub batches: Vec<TriangleBatch>,
+    /// Each `TriangleInstances` entity's instances as written to its buffer.
+    pub instances: HashMap<Entity, Vec<TriangleInstanceData>>,
+    pub globals: Option<TriangleGlobals>,
+}
+
+/// The shader `TrianglePipeline` specializes, copied into the render world in debug builds
+/// whenever `Assets<Shader>` changes, for `check_shader_uniform_sizes`.
+#[cfg(debug_assertions)]
+pub struct ExtractedTriangleShader(pub Shader);
+
+/// Images modified or removed in the main world since the last extract.
+#[derive(Default)]
+pub struct ChangedImages(pub Vec<HandleId>);
+
+/// Entities whose meshes the render world has prepared, handed back to the main world so
+/// it can mark them `TriangleReady`. The same instance lives in both worlds.
+#[derive(Clone, Default)]
+pub struct PreparedTriangles(pub Arc<Mutex<Vec<Entity>>>);
+
+/// Render statistics for the main world. The same instance lives in both worlds.
+#[derive(Clone, Default)]
+pub struct TriangleRenderDiagnostics {
+    main_pass_gpu_time: Arc<Mutex<Option<std::time::Duration>>>,
+}
+
+impl TriangleRenderDiagnostics {
+    /// GPU time of the 2d main pass, the whole pass and not only its triangles: sprites
+    /// and anything else in `Transparent2d` are included. With several 2d cameras it's the
+    /// pass of whichever ran last. Only measured with the `gpu-timing` feature on a device
+    /// with `TIMESTAMP_QUERY`; `None` otherwise. Lags a frame or two behind.
+    pub fn main_pass_gpu_time(&self) -> Option<std::time::Duration> {
+        *self.main_pass_gpu_time.lock().unwrap()
+    }
+}
+
+/// Render-world copy of the mesh handle of a triangle that isn't `TriangleReady` yet.
+#[derive(Component)]
+pub struct AwaitingReady(pub Handle<Mesh>);
+
+pub fn mark_ready_triangles(
+    mut commands: Commands,
+    prepared: Res<PreparedTriangles>,
+    awaiting_q: Query<Entity, Without<TriangleReady>>,
+) {
+    let mut prepared = prepared.0.lock().unwrap();
+    for entity in prepared.drain(..) {
+        if awaiting_q.get(entity).is_ok() {
+            commands.entity(entity).insert(TriangleReady);
+        }
+    }
+}
+
+impl TriangleUniform {
+    pub fn translation(&self) -> Vec3 {
+        self.transform.w_axis.truncate()
+    }
+
+    pub fn scale(&self) -> Vec3 {
+        self.transform.to_scale_rotation_translation().0
+    }
+
+    /// A plain triangle's uniform, drawn with `transform`.
+    pub fn new(transform: Mat4) -> Self {
+        Self {
+            transform,
+            tint: Vec4::ONE,
+            opacity: 1.0,
+            uv_transform: Mat3::IDENTITY,
+            uv_scroll: Vec2::ZERO,
+        }
+    }
+
+    /// The uniforms for a `FEATHER` copy of `triangle`, scaled about its incenter so every
+    /// edge moves out by `radius` and fading out over that distance. `None` for degenerate
+    /// triangles.
+    pub fn feathered(
+        transform: Mat4,
+        triangle: &Triangle,
+        color: [f32; 4],
+        radius: f32,
+    ) -> Option<(Self, TriangleEdgeUniform)> {
+        let inradius = triangle.inradius();
+        if inradius <= f32::EPSILON {
+            return None;
+        }
+        let radius = radius.max(0.0);
+        let scale = (inradius + radius) / inradius;
+        let pivot = triangle.incenter().extend(0.0);
+        let [h_a, h_b, h_c] = triangle.heights();
+        let uniform = Self::new(
+            transform
+                * Mat4::from_translation(pivot)
+                * Mat4::from_scale(Vec3::new(scale, scale, 1.0))
+                * Mat4::from_translation(-pivot),
+        );
+        let edge = TriangleEdgeUniform {
+            glow: Vec4::from(color),
+            // A zero-width fade would divide by zero in `smoothStep`.
+            feather: Vec3::new(h_a, h_b, h_c).extend(0.0) * scale
+                + Vec4::W * radius.max(f32::EPSILON),
+            ..Default::default()
+        };
+        Some((uniform, edge))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::plugin::TriangleRenderPlugin;
+    use crate::TriangleBundle;
+
+    #[test]
+    fn uniforms_decompose_their_transform() {
+        let uniform = TriangleUniform::new(Mat4::from_scale_rotation_translation(
+            Vec3::new(2.0, 3.0, 1.0),
+            Quat::from_rotation_z(0.5),
+            Vec3::new(10.0, -20.0, 5.0),
+        ));
+        assert_eq!(uniform.translation(), Vec3::new(10.0, -20.0, 5.0));
+        assert!(
+            uniform.scale().abs_diff_eq(Vec3::new(2.0, 3.0, 1.0), 1e-5),
+            "{:?}",
+            uniform.scale()
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn triangles_are_marked_ready_within_a_few_frames() {
+        let mut app = testing::gpu_app(TriangleRenderPlugin::default());
+        let entity = app
+            .world
+            .spawn()
+            .insert_bundle(TriangleBundle::new(Triangle::side(10.0)))
+            .id();
+        let frames = (1..=3).find(|_| {
+            app.update();
+            app.world.get::<TriangleReady>(entity).is_some()
+        });
+        assert!(frames.is_some(), "not ready after 3 frames");
+        // And only once its mesh was prepared, not in the frame it was spawned.
+        assert!(frames > Some(1), "ready after {:?} frames", frames);
+    }
+}
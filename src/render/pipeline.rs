@@ -0,0 +1,1425 @@
+use std::any::type_name;
+
+use bevy::prelude::*;
+use bevy::render::render_resource::std140::AsStd140;
+use bevy::render::render_resource::{
+    BindGroupLayout, CachedPipelineId, FragmentState, RenderPipelineCache,
+    RenderPipelineDescriptor, SpecializedPipeline, SpecializedPipelines, VertexBufferLayout,
+    VertexState,
+};
+use bevy::render::renderer::RenderDevice;
+use bevy::render::texture::BevyDefault;
+use bevy::render::view::ViewUniform;
+use bevy::utils::HashMap;
+
+use super::*;
+use plugin::{TriangleRenderConfig, SHADER_HANDLE};
+
+#[derive(Clone)]
+pub struct TrianglePipeline {
+    pub view_layout: BindGroupLayout,
+    /// A mesh layout per combination of `MeshBindings`, indexed by its bits.
+    pub mesh_layouts: Vec<BindGroupLayout>,
+    pub texture_layout: BindGroupLayout,
+    /// The `min_binding_size`s the view and mesh layouts were created with, checked
+    /// against the shader's uniform structs by `check_shader_uniform_sizes`.
+    pub view_binding_size: u64,
+    pub globals_binding_size: u64,
+    pub mesh_binding_size: u64,
+    pub edge_binding_size: u64,
+    pub fill_binding_size: u64,
+    pub config: TriangleRenderConfig,
+}
+
+impl FromWorld for TrianglePipeline {
+    fn from_world(world: &mut World) -> Self {
+        let config = world
+            .get_resource::<TriangleRenderConfig>()
+            .cloned()
+            .unwrap_or_default();
+        let device = world.get_resource::<RenderDevice>().unwrap();
+        Self::new(device, &config)
+    }
+}
+
+impl TrianglePipeline {
+    /// Builds the layouts on `device` without a render app. A `RenderDevice` can be made
+    /// from an `Arc<wgpu::Device>` with `RenderDevice::from`. What `specialize` produces
+    /// for a key, short of the layouts, comes from `TriangleRenderConfig::descriptor`,
+    /// which needs no device.
+    pub fn new(device: &RenderDevice, config: &TriangleRenderConfig) -> Self {
+        let view_binding_size = ViewUniform::std140_size_static() as u64;
+        let globals_binding_size = TriangleGlobals::std140_size_static() as u64;
+        let mesh_binding_size = TriangleUniform::std140_size_static() as u64;
+        let edge_binding_size = TriangleEdgeUniform::std140_size_static() as u64;
+        let fill_binding_size = TriangleFillUniform::std140_size_static() as u64;
+        let view_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                // View
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(view_binding_size),
+                    },
+                    count: None,
+                },
+                // Globals
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(globals_binding_size),
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("triangle view layout"),
+        });
+
+        let mesh_layouts = (0..=MeshBindings::all().bits())
+            .map(|bits| {
+                let bindings = MeshBindings::from_bits_truncate(bits);
+                let entries = [
+                    (0, mesh_binding_size),
+                    (1, edge_binding_size),
+                    (2, fill_binding_size),
+                ]
+                .into_iter()
+                .filter(|(binding, _)| match binding {
+                    1 => bindings.contains(MeshBindings::EDGE),
+                    2 => bindings.contains(MeshBindings::FILL),
+                    _ => true,
+                })
+                .map(|(binding, size)| wgpu::BindGroupLayoutEntry {
+                    binding,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(size),
+                    },
+                    count: None,
+                })
+                .collect::<Vec<_>>();
+                device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    entries: &entries,
+                    label: Some("triangle mesh layout"),
+                })
+            })
+            .collect();
+
+        let texture_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("triangle texture layout"),
+        });
+        Self {
+            view_layout,
+            mesh_layouts,
+            texture_layout,
+            view_binding_size,
+            globals_binding_size,
+            mesh_binding_size,
+            edge_binding_size,
+            fill_binding_size,
+            config: config.clone(),
+        }
+    }
+}
+
+impl TrianglePipeline {
+    /// The mesh layout of pipelines binding `bindings` next to `TriangleUniform`.
+    pub fn mesh_layout(&self, bindings: MeshBindings) -> &BindGroupLayout {
+        &self.mesh_layouts[bindings.bits() as usize]
+    }
+
+    /// The uniforms a pipeline for `key` binds, as their `(group, binding)`, the Rust
+    /// type written to them and the `min_binding_size` their layout entry was created
+    /// with, which the shader's struct at that binding has to match.
+    pub fn uniform_bindings(
+        &self,
+        key: TrianglePipelineKey,
+    ) -> Vec<((u32, u32), &'static str, u64)> {
+        let mut uniforms = vec![
+            ((0, 0), type_name::<ViewUniform>(), self.view_binding_size),
+            (
+                (0, 1),
+                type_name::<TriangleGlobals>(),
+                self.globals_binding_size,
+            ),
+            (
+                (1, 0),
+                type_name::<TriangleUniform>(),
+                self.mesh_binding_size,
+            ),
+        ];
+        let bindings = key.mesh_bindings();
+        if bindings.contains(MeshBindings::EDGE) {
+            uniforms.push((
+                (1, 1),
+                type_name::<TriangleEdgeUniform>(),
+                self.edge_binding_size,
+            ));
+        }
+        if bindings.contains(MeshBindings::FILL) {
+            uniforms.push((
+                (1, 2),
+                type_name::<TriangleFillUniform>(),
+                self.fill_binding_size,
+            ));
+        }
+        uniforms
+    }
+}
+
+/// The size naga lays out for the uniform struct at `group` and `binding`, if `module`
+/// declares one there.
+pub fn uniform_size(module: &naga::Module, group: u32, binding: u32) -> Option<u64> {
+    let binding = naga::ResourceBinding { group, binding };
+    let (_, var) = module
+        .global_variables
+        .iter()
+        .find(|(_, var)| var.binding.as_ref() == Some(&binding))?;
+    match module.types[var.ty].inner {
+        naga::TypeInner::Struct { span, .. } => Some(span as u64),
+        _ => None,
+    }
+}
+
+bitflags::bitflags! {
+    /// The uniforms in a mesh bind group besides `TriangleUniform`: `EDGE` for a
+    /// `TriangleEdgeUniform` at binding 1, `FILL` for a `TriangleFillUniform` at binding 2.
+    pub struct MeshBindings: u8 {
+        const NONE = 0;
+        const EDGE = (1 << 0);
+        const FILL = (1 << 1);
+    }
+}
+
+bitflags::bitflags! {
+    #[repr(transparent)]
+    // See bevy_sprite::render::SpritePipelineKey
+
+    pub struct TrianglePipelineKey: u32 {
+        const NONE               = 0;
+        const COLORED            = (1 << 0);
+        const TRIANGLE_STRIP     = (1 << 1);
+        const TEXTURED           = (1 << 2);
+        const VERTEX_TANGENTS    = (1 << 3);
+        const STRIP_INDEX_U16    = (1 << 4);
+        const STRIP_INDEX_U32    = (1 << 5);
+        const PATTERN_STRIPES    = (1 << 6);
+        const PATTERN_CHECKER    = (1 << 7);
+        const PATTERN_DOTS       = (1 << 8);
+        const FEATHER            = (1 << 9);
+        const BLEND_ADD          = (1 << 10);
+        const ALPHA_MASK         = (1 << 11);
+        const EDGE_COLOR         = (1 << 12);
+        const SPLIT              = (1 << 13);
+        const PREMULTIPLIED_ALPHA = (1 << 14);
+        const REVEAL             = (1 << 15);
+        const BACK_COLOR         = (1 << 16);
+        const TEXTURE_PREMULTIPLIED = (1 << 17);
+        const TEXTURE_OPAQUE     = (1 << 18);
+        const DISSOLVE           = (1 << 19);
+        const SMOOTH_EDGES       = (1 << 20);
+        const EDGE_COLORS        = (1 << 21);
+        /// Write depth without testing it, e.g. for a prepass that later geometry is
+        /// clipped against. Needs a pass with a `TriangleRenderConfig::DEPTH_FORMAT`
+        /// attachment: Bevy's 2d main pass has none, so these pipelines are for passes of
+        /// your own. Triangles still draw in sort order and blend over each other
+        /// whatever their depth; only what's drawn later with a depth test sees it.
+        const DEPTH_WRITE        = (1 << 22);
+        /// Read a `TriangleInstanceData` per instance from vertex buffer 1, for
+        /// `TriangleInstances`.
+        const INSTANCED          = (1 << 23);
+        /// Draw into a `TriangleRenderConfig::HDR_FORMAT` target instead of
+        /// `bevy_default()`, for views of `TriangleHdr` cameras.
+        const HDR                = (1 << 24);
+        const MSAA_RESERVED_BITS = TrianglePipelineKey::MSAA_MASK_BITS << TrianglePipelineKey::MSAA_SHIFT_BITS;
+    }
+}
+
+impl TrianglePipelineKey {
+    const MSAA_MASK_BITS: u32 = 0b111111;
+    const MSAA_SHIFT_BITS: u32 = 32 - 6;
+
+    /// Flags whose shaders read `TriangleEdgeUniform`. They all measure the distance to
+    /// the edges, so they also share the barycentric interpolant.
+    pub const EDGE_UNIFORM_FLAGS: Self = Self {
+        bits: Self::FEATHER.bits
+            | Self::EDGE_COLOR.bits
+            | Self::SMOOTH_EDGES.bits
+            | Self::EDGE_COLORS.bits,
+    };
+    /// Flags whose shaders read `TriangleFillUniform`.
+    pub const FILL_UNIFORM_FLAGS: Self = Self {
+        bits: Self::PATTERN_STRIPES.bits
+            | Self::PATTERN_CHECKER.bits
+            | Self::PATTERN_DOTS.bits
+            | Self::ALPHA_MASK.bits
+            | Self::SPLIT.bits
+            | Self::REVEAL.bits
+            | Self::BACK_COLOR.bits
+            | Self::DISSOLVE.bits,
+    };
+
+    /// The uniforms pipelines for this key bind next to `TriangleUniform`. Extraction gives
+    /// a triangle exactly the uniforms its `TriangleFeatures` call for, so the two agree.
+    pub fn mesh_bindings(&self) -> MeshBindings {
+        let mut bindings = MeshBindings::NONE;
+        if self.intersects(Self::EDGE_UNIFORM_FLAGS) {
+            bindings |= MeshBindings::EDGE;
+        }
+        if self.intersects(Self::FILL_UNIFORM_FLAGS) {
+            bindings |= MeshBindings::FILL;
+        }
+        bindings
+    }
+
+    pub fn from_msaa_samples(msaa_samples: u32) -> Self {
+        let msaa_bits = ((msaa_samples - 1) & Self::MSAA_MASK_BITS) << Self::MSAA_SHIFT_BITS;
+        TrianglePipelineKey::from_bits(msaa_bits).unwrap()
+    }
+
+    pub fn msaa_samples(&self) -> u32 {
+        ((self.bits >> Self::MSAA_SHIFT_BITS) & Self::MSAA_MASK_BITS) + 1
+    }
+
+    /// Only triangle lists and strips are supported; anything else is drawn as a list.
+    pub fn from_primitive_topology(topology: wgpu::PrimitiveTopology) -> Self {
+        match topology {
+            wgpu::PrimitiveTopology::TriangleStrip => Self::TRIANGLE_STRIP,
+            _ => Self::NONE,
+        }
+    }
+
+    pub fn from_pattern(pattern: TrianglePattern) -> Self {
+        match pattern {
+            TrianglePattern::Solid => Self::NONE,
+            TrianglePattern::Stripes { .. } => Self::PATTERN_STRIPES,
+            TrianglePattern::Checker { .. } => Self::PATTERN_CHECKER,
+            TrianglePattern::Dots { .. } => Self::PATTERN_DOTS,
+        }
+    }
+
+    /// Indexed strips need their index format baked into the pipeline; lists don't, so
+    /// this is empty for them to avoid extra specializations.
+    pub fn from_strip_index_format(
+        topology: wgpu::PrimitiveTopology,
+        index_format: Option<wgpu::IndexFormat>,
+    ) -> Self {
+        match (topology, index_format) {
+            (wgpu::PrimitiveTopology::TriangleStrip, Some(wgpu::IndexFormat::Uint16)) => {
+                Self::STRIP_INDEX_U16
+            }
+            (wgpu::PrimitiveTopology::TriangleStrip, Some(wgpu::IndexFormat::Uint32)) => {
+                Self::STRIP_INDEX_U32
+            }
+            _ => Self::NONE,
+        }
+    }
+
+    pub fn strip_index_format(&self) -> Option<wgpu::IndexFormat> {
+        if self.contains(Self::STRIP_INDEX_U16) {
+            Some(wgpu::IndexFormat::Uint16)
+        } else if self.contains(Self::STRIP_INDEX_U32) {
+            Some(wgpu::IndexFormat::Uint32)
+        } else {
+            None
+        }
+    }
+
+    pub fn primitive_topology(&self) -> wgpu::PrimitiveTopology {
+        match self.contains(Self::TRIANGLE_STRIP) {
+            true => wgpu::PrimitiveTopology::TriangleStrip,
+            false => wgpu::PrimitiveTopology::TriangleList,
+        }
+    }
+}
+
+/// Every key `queue_triangles` has specialized so far, for diagnosing first-frame
+/// hitches when a new key shows up and its pipeline still has to compile.
+#[derive(Default)]
+pub struct TrianglePipelineSpecializations {
+    ids: HashMap<TrianglePipelineKey, CachedPipelineId>,
+}
+
+impl TrianglePipelineSpecializations {
+    pub fn record(&mut self, key: TrianglePipelineKey, id: CachedPipelineId) {
+        self.ids.insert(key, id);
+    }
+
+    /// The pipeline for `key`, specialized the first time any view asks for it and looked
+    /// up after that, so views with the same key bits share it and a new camera only
+    /// builds pipelines for keys no view has used yet.
+    pub fn specialize(
+        &mut self,
+        pipelines: &mut SpecializedPipelines<TrianglePipeline>,
+        cache: &mut RenderPipelineCache,
+        pipeline: &TrianglePipeline,
+        key: TrianglePipelineKey,
+    ) -> CachedPipelineId {
+        *self
+            .ids
+            .entry(key)
+            .or_insert_with(|| pipelines.specialize(cache, pipeline, key))
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = TrianglePipelineKey> + '_ {
+        self.ids.keys().copied()
+    }
+
+    /// The key the pipeline `id` was specialized from, if it's one of these.
+    pub fn key(&self, id: CachedPipelineId) -> Option<TrianglePipelineKey> {
+        self.ids
+            .iter()
+            .find(|(_, x)| **x == id)
+            .map(|(key, _)| *key)
+    }
+
+    /// Each specialized key and whether its pipeline has finished compiling.
+    pub fn readiness<'a>(
+        &'a self,
+        cache: &'a RenderPipelineCache,
+    ) -> impl Iterator<Item = (TrianglePipelineKey, bool)> + 'a {
+        self.ids
+            .iter()
+            .map(|(key, id)| (*key, cache.get(*id).is_some()))
+    }
+}
+
+/// The vertex attributes of the meshes drawn with `key`, laid out the way
+/// `Mesh::get_vertex_buffer_data` interleaves them: tightly packed in attribute name
+/// order, so color comes before position.
+pub fn vertex_attributes(key: TrianglePipelineKey) -> Vec<wgpu::VertexAttribute> {
+    let mut attributes = vec![
+        (Mesh::ATTRIBUTE_POSITION, wgpu::VertexFormat::Float32x3, 0),
+        (Mesh::ATTRIBUTE_COLOR, wgpu::VertexFormat::Float32x4, 1),
+        (Mesh::ATTRIBUTE_UV_0, wgpu::VertexFormat::Float32x2, 2),
+    ];
+    if key.contains(TrianglePipelineKey::VERTEX_TANGENTS) {
+        attributes.push((Mesh::ATTRIBUTE_TANGENT, wgpu::VertexFormat::Float32x4, 3));
+    }
+    attributes.sort_by_key(|(name, ..)| *name);
+    let mut offset = 0;
+    attributes
+        .into_iter()
+        .map(|(_, format, shader_location)| {
+            let attribute = wgpu::VertexAttribute {
+                format,
+                offset,
+                shader_location,
+            };
+            offset += format.size();
+            attribute
+        })
+        .collect()
+}
+
+/// The attributes of a `TriangleInstanceData` in vertex buffer 1 of `INSTANCED` pipelines:
+/// the columns of its transform at locations 4 to 7, then its UV offset and scale at 8.
+pub fn instance_attributes() -> Vec<wgpu::VertexAttribute> {
+    let column = wgpu::VertexFormat::Float32x4;
+    (0..5)
+        .map(|i| wgpu::VertexAttribute {
+            format: column,
+            offset: i * column.size(),
+            shader_location: 4 + i as u32,
+        })
+        .collect()
+}
+
+impl TriangleRenderConfig {
+    /// The depth attachment format `DEPTH_WRITE` pipelines expect, the one Bevy's 3d
+    /// passes use.
+    pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+    /// The color target format of `HDR` pipelines.
+    pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+    /// The pipeline `TrianglePipeline::specialize` builds for `key` with this config, but
+    /// with `layout` left `None` since bind group layouts need a device. The layouts it
+    /// fills in are the view, `key.mesh_bindings()`'s mesh and, for `TEXTURED` keys, the
+    /// texture layout.
+    pub fn descriptor(&self, key: TrianglePipelineKey) -> RenderPipelineDescriptor {
+        let shader = self
+            .shader
+            .clone()
+            .unwrap_or_else(|| SHADER_HANDLE.typed::<Shader>());
+        let mut shader_defs = self.shader_defs.clone();
+        if self.srgb_input {
+            shader_defs.push("SRGB_INPUT".to_string());
+        }
+        if self.camera_relative {
+            shader_defs.push("CAMERA_RELATIVE".to_string());
+        }
+        for (flag, def) in [
+            (TrianglePipelineKey::PATTERN_STRIPES, "PATTERN_STRIPES"),
+            (TrianglePipelineKey::PATTERN_CHECKER, "PATTERN_CHECKER"),
+            (TrianglePipelineKey::PATTERN_DOTS, "PATTERN_DOTS"),
+            (TrianglePipelineKey::FEATHER, "FEATHER"),
+            (TrianglePipelineKey::ALPHA_MASK, "ALPHA_MASK"),
+            (TrianglePipelineKey::EDGE_COLOR, "EDGE_COLOR"),
+            (TrianglePipelineKey::SPLIT, "SPLIT"),
+            (TrianglePipelineKey::REVEAL, "REVEAL"),
+            (TrianglePipelineKey::BACK_COLOR, "BACK_COLOR"),
+            (
+                TrianglePipelineKey::TEXTURE_PREMULTIPLIED,
+                "TEXTURE_PREMULTIPLIED",
+            ),
+            (TrianglePipelineKey::TEXTURE_OPAQUE, "TEXTURE_OPAQUE"),
+            (TrianglePipelineKey::DISSOLVE, "DISSOLVE"),
+            (TrianglePipelineKey::SMOOTH_EDGES, "SMOOTH_EDGES"),
+            (TrianglePipelineKey::EDGE_COLORS, "EDGE_COLORS"),
+            (TrianglePipelineKey::BLEND_ADD, "BLEND_ADD"),
+            (
+                TrianglePipelineKey::PREMULTIPLIED_ALPHA,
+                "PREMULTIPLIED_ALPHA",
+            ),
+        ] {
+            if key.contains(flag) {
+                shader_defs.push(def.to_string());
+            }
+        }
+        // The preprocessor has no `||`, so flags reading the distance to the edges share
+        // one define for the barycentric interpolant, and flags reading a uniform one for
+        // its binding.
+        let bindings = key.mesh_bindings();
+        if bindings.contains(MeshBindings::EDGE) {
+            shader_defs.push("BARYCENTRIC".to_string());
+            shader_defs.push("EDGE_UNIFORM".to_string());
+        }
+        if bindings.contains(MeshBindings::FILL) {
+            shader_defs.push("FILL_UNIFORM".to_string());
+        }
+        if key.contains(TrianglePipelineKey::VERTEX_TANGENTS) {
+            shader_defs.push("VERTEX_TANGENTS".to_string());
+        }
+        let vertex_attributes = vertex_attributes(key);
+        let mut buffers = vec![VertexBufferLayout {
+            array_stride: vertex_attributes.iter().map(|x| x.format.size()).sum(),
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: vertex_attributes,
+        }];
+        if key.contains(TrianglePipelineKey::INSTANCED) {
+            shader_defs.push("INSTANCED".to_string());
+            buffers.push(VertexBufferLayout {
+                array_stride: std::mem::size_of::<TriangleInstanceData>() as u64,
+                step_mode: wgpu::VertexStepMode::Instance,
+                attributes: instance_attributes(),
+            });
+        }
+        let blend = if key.contains(TrianglePipelineKey::BLEND_ADD) {
+            wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Zero,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+            }
+        } else if key.contains(TrianglePipelineKey::ALPHA_MASK) {
+            // Surviving fragments are opaque. Cutouts still go through
+            // `Transparent2d`, the only 2d phase, so they sort by z like everything
+            // else.
+            wgpu::BlendState::REPLACE
+        } else if key.contains(TrianglePipelineKey::PREMULTIPLIED_ALPHA) {
+            wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING
+        } else {
+            wgpu::BlendState::ALPHA_BLENDING
+        };
+        if key.contains(TrianglePipelineKey::TEXTURED) {
+            shader_defs.push("TEXTURED".to_string());
+        }
+        RenderPipelineDescriptor {
+            vertex: VertexState {
+                shader: shader.clone(),
+                entry_point: "vertex".into(),
+                shader_defs: shader_defs.clone(),
+                buffers,
+            },
+            fragment: Some(FragmentState {
+                shader,
+                shader_defs,
+                entry_point: "fragment".into(),
+                targets: vec![wgpu::ColorTargetState {
+                    format: if key.contains(TrianglePipelineKey::HDR) {
+                        Self::HDR_FORMAT
+                    } else {
+                        wgpu::TextureFormat::bevy_default()
+                    },
+                    blend: Some(blend),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            layout: None,
+            primitive: wgpu::PrimitiveState {
+                front_face: self.front_face,
+                // Back colors need the back faces.
+                cull_mode: if key.contains(TrianglePipelineKey::BACK_COLOR) {
+                    None
+                } else {
+                    self.cull_mode
+                },
+                unclipped_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+                topology: key.primitive_topology(),
+                strip_index_format: key.strip_index_format(),
+            },
+            depth_stencil: key.contains(TrianglePipelineKey::DEPTH_WRITE).then(|| {
+                wgpu::DepthStencilState {
+                    format: Self::DEPTH_FORMAT,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::Always,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }
+            }),
+            multisample: wgpu::MultisampleState {
+                count: key.msaa_samples(),
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            label: Some("triangle pipeline".into()),
+        }
+    }
+}
+
+impl SpecializedPipeline for TrianglePipeline {
+    type Key = TrianglePipelineKey;
+
+    fn specialize(&self, key: Self::Key) -> RenderPipelineDescriptor {
+        let mut layout = vec![
+            self.view_layout.clone(),
+            self.mesh_layout(key.mesh_bindings()).clone(),
+        ];
+        if key.contains(TrianglePipelineKey::TEXTURED) {
+            layout.push(self.texture_layout.clone());
+        }
+        RenderPipelineDescriptor {
+            layout: Some(layout),
+            ..self.config.descriptor(key)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::mesh::VertexAttributeValues;
+    use bevy::render::render_resource::ShaderProcessor;
+    use bevy::render::{RenderApp, RenderStage};
+
+    use super::*;
+    use crate::render::plugin::TriangleRenderPlugin;
+    use crate::render::testing;
+    use crate::{
+        PremultipliedVertexColor, TextureAlphaMode, Triangle, TriangleAlphaCutoff,
+        TriangleBackColor, TriangleBundle, TriangleEdgeColor, TrianglePattern,
+        TriangleSamplerConfig, TriangleSplit, TriangleTangents, TriangleTexture,
+    };
+
+    /// The built-in shader preprocessed for `key`, parsed and validated by naga.
+    fn reflect(key: TrianglePipelineKey) -> naga::Module {
+        let descriptor = TriangleRenderConfig::default().descriptor(key);
+        let shader = Shader::from_wgsl(include_str!("triangle.wgsl"));
+        let processed = ShaderProcessor::default()
+            .process(
+                &shader,
+                &descriptor.vertex.shader_defs,
+                &HashMap::default(),
+                &HashMap::default(),
+            )
+            .unwrap();
+        match processed.reflect() {
+            Ok(reflection) => reflection.module,
+            Err(err) => panic!("the shader for {:?} doesn't validate: {:?}", key, err),
+        }
+    }
+
+    /// Renders what `spawn` adds to the world through `plugin`, and returns the pixels.
+    fn render_with(plugin: TriangleRenderPlugin, spawn: impl FnOnce(&mut World)) -> Vec<[u8; 4]> {
+        let mut app = testing::gpu_app(plugin);
+        spawn(&mut app.world);
+        testing::render(&mut app)
+    }
+
+    #[test]
+    fn shader_matches_the_mesh_layout_for_every_flag() {
+        let config = TriangleRenderConfig::default();
+        let flags = (0..25).map(|bit| TrianglePipelineKey::from_bits_truncate(1 << bit));
+        let everything = TrianglePipelineKey::EDGE_UNIFORM_FLAGS
+            | TrianglePipelineKey::FILL_UNIFORM_FLAGS
+            | TrianglePipelineKey::TEXTURED
+            | TrianglePipelineKey::VERTEX_TANGENTS
+            | TrianglePipelineKey::INSTANCED
+            | TrianglePipelineKey::PREMULTIPLIED_ALPHA;
+        for flags in flags.chain([TrianglePipelineKey::NONE, everything]) {
+            let key = TrianglePipelineKey::from_msaa_samples(4) | flags;
+            let module = reflect(key);
+            let bindings = key.mesh_bindings();
+            assert_eq!(
+                uniform_size(&module, 1, 0),
+                Some(TriangleUniform::std140_size_static() as u64),
+                "{:?}",
+                key
+            );
+            assert_eq!(
+                uniform_size(&module, 1, 1),
+                bindings
+                    .contains(MeshBindings::EDGE)
+                    .then(|| TriangleEdgeUniform::std140_size_static() as u64),
+                "{:?}",
+                key
+            );
+            assert_eq!(
+                uniform_size(&module, 1, 2),
+                bindings
+                    .contains(MeshBindings::FILL)
+                    .then(|| TriangleFillUniform::std140_size_static() as u64),
+                "{:?}",
+                key
+            );
+            assert_eq!(
+                config.descriptor(key).vertex.buffers[0].array_stride,
+                vertex_attributes(key)
+                    .iter()
+                    .map(|attribute| attribute.format.size())
+                    .sum::<u64>(),
+            );
+            assert_eq!(
+                config
+                    .descriptor(key)
+                    .vertex
+                    .buffers
+                    .get(1)
+                    .map(|buffer| buffer.array_stride),
+                key.contains(TrianglePipelineKey::INSTANCED)
+                    .then(|| instance_attributes()
+                        .iter()
+                        .map(|attribute| attribute.format.size())
+                        .sum::<u64>()),
+                "{:?}",
+                key
+            );
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn the_default_key_alpha_blends_and_culls_back_faces() {
+        let pipeline =
+            TrianglePipeline::new(&testing::render_device(), &TriangleRenderConfig::default());
+        let descriptor = pipeline.specialize(TrianglePipelineKey::from_msaa_samples(1));
+        assert_eq!(descriptor.layout.unwrap().len(), 2);
+        assert_eq!(descriptor.primitive.cull_mode, Some(wgpu::Face::Back));
+        let targets = descriptor.fragment.unwrap().targets;
+        assert_eq!(targets.len(), 1);
+        assert_eq!(targets[0].format, wgpu::TextureFormat::bevy_default());
+        assert_eq!(targets[0].blend, Some(wgpu::BlendState::ALPHA_BLENDING));
+    }
+
+    #[test]
+    fn indexed_strips_bake_in_their_index_format() {
+        let config = TriangleRenderConfig::default();
+        for (vertex_count, format) in [
+            (65535, wgpu::IndexFormat::Uint16),
+            (65536, wgpu::IndexFormat::Uint32),
+        ] {
+            let indices = crate::mesh_indices(vertex_count, vec![0, 1, 2]);
+            let strip = wgpu::PrimitiveTopology::TriangleStrip;
+            let key = TrianglePipelineKey::from_primitive_topology(strip)
+                | TrianglePipelineKey::from_strip_index_format(strip, Some((&indices).into()));
+            let primitive = config.descriptor(key).primitive;
+            assert_eq!(primitive.topology, strip);
+            assert_eq!(primitive.strip_index_format, Some(format));
+        }
+
+        let list = wgpu::PrimitiveTopology::TriangleList;
+        let key = TrianglePipelineKey::from_primitive_topology(list)
+            | TrianglePipelineKey::from_strip_index_format(list, Some(wgpu::IndexFormat::Uint16));
+        assert_eq!(config.descriptor(key).primitive.strip_index_format, None);
+    }
+
+    #[test]
+    fn the_configured_front_face_reaches_the_descriptor() {
+        let key = TrianglePipelineKey::from_msaa_samples(1);
+        let primitive = |plugin: TriangleRenderPlugin| plugin.config.descriptor(key).primitive;
+        assert_eq!(
+            primitive(TriangleRenderPlugin::default()).front_face,
+            wgpu::FrontFace::Ccw
+        );
+        let cw = TriangleRenderPlugin::default().with_front_face(wgpu::FrontFace::Cw);
+        assert_eq!(primitive(cw).front_face, wgpu::FrontFace::Cw);
+    }
+
+    #[test]
+    fn vertex_attributes_match_the_mesh_buffer() {
+        let triangle = Triangle::side(10.0).with_rgba([0.1, 0.2, 0.3, 0.4]);
+        for (key, mesh) in [
+            (TrianglePipelineKey::NONE, triangle.mesh()),
+            (
+                TrianglePipelineKey::VERTEX_TANGENTS,
+                triangle.mesh_with_tangents(),
+            ),
+        ] {
+            let attributes = vertex_attributes(key);
+            let stride: u64 = attributes.iter().map(|a| a.format.size()).sum();
+            let data = mesh.get_vertex_buffer_data();
+            assert_eq!(data.len() as u64, stride * mesh.count_vertices() as u64);
+            for attribute in attributes {
+                let name = [
+                    Mesh::ATTRIBUTE_POSITION,
+                    Mesh::ATTRIBUTE_COLOR,
+                    Mesh::ATTRIBUTE_UV_0,
+                    Mesh::ATTRIBUTE_TANGENT,
+                ][attribute.shader_location as usize];
+                let expected = match mesh.attribute(name) {
+                    Some(VertexAttributeValues::Float32x2(values)) => {
+                        values.iter().map(|v| v.to_vec()).collect::<Vec<_>>()
+                    }
+                    Some(VertexAttributeValues::Float32x3(values)) => {
+                        values.iter().map(|v| v.to_vec()).collect()
+                    }
+                    Some(VertexAttributeValues::Float32x4(values)) => {
+                        values.iter().map(|v| v.to_vec()).collect()
+                    }
+                    other => panic!("unexpected {} values {:?}", name, other),
+                };
+                // What the pipeline would read for each vertex at the attribute's offset.
+                let read = (0..mesh.count_vertices() as u64)
+                    .map(|vertex| {
+                        let start = (vertex * stride + attribute.offset) as usize;
+                        data[start..start + attribute.format.size() as usize]
+                            .chunks_exact(4)
+                            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+                            .collect::<Vec<_>>()
+                    })
+                    .collect::<Vec<_>>();
+                assert_eq!(read, expected, "{} with {:?}", name, key);
+            }
+        }
+    }
+
+    #[test]
+    fn plugin_shader_defs_reach_both_stages() {
+        let config = TriangleRenderPlugin::default()
+            .with_shader_def("USE_FANCY_LIGHTING")
+            .config;
+        let descriptor = config.descriptor(TrianglePipelineKey::TEXTURED);
+        let fragment = descriptor.fragment.unwrap();
+        for defs in [&descriptor.vertex.shader_defs, &fragment.shader_defs] {
+            // Alongside the key's own, not instead of them.
+            assert!(
+                defs.contains(&"USE_FANCY_LIGHTING".to_string()),
+                "{:?}",
+                defs
+            );
+            assert!(defs.contains(&"TEXTURED".to_string()), "{:?}", defs);
+        }
+        let plain = TriangleRenderConfig::default().descriptor(TrianglePipelineKey::TEXTURED);
+        assert!(!plain
+            .vertex
+            .shader_defs
+            .contains(&"USE_FANCY_LIGHTING".to_string()));
+    }
+
+    #[test]
+    fn depth_write_writes_depth_without_testing_it() {
+        let config = TriangleRenderConfig::default();
+        let msaa = TrianglePipelineKey::from_msaa_samples(4);
+        assert!(config.descriptor(msaa).depth_stencil.is_none());
+        let depth = config
+            .descriptor(msaa | TrianglePipelineKey::DEPTH_WRITE)
+            .depth_stencil
+            .unwrap();
+        assert_eq!(depth.format, wgpu::TextureFormat::Depth32Float);
+        assert!(depth.depth_write_enabled);
+        assert_eq!(depth.depth_compare, wgpu::CompareFunction::Always);
+        assert_eq!(depth.stencil, wgpu::StencilState::default());
+    }
+
+    /// The lower left half of the view; planar UVs put its corners at (0, 0), (0, 1) and
+    /// (1, 1), so `u` goes from 0 to 1 left to right and `v` from 0 to 1 top to bottom.
+    fn lower_left_half(rgba: [f32; 4]) -> Triangle {
+        Triangle {
+            a: Vec2::new(-30.0, 30.0),
+            b: Vec2::new(-30.0, -30.0),
+            c: Vec2::new(30.0, -30.0),
+            rgba,
+        }
+    }
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const GREEN: [u8; 4] = [0, 255, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+
+    /// A 2x2 image, red and green along the top and blue and white along the bottom.
+    fn quadrants() -> Image {
+        Image::new(
+            wgpu::Extent3d {
+                width: 2,
+                height: 2,
+                depth_or_array_layers: 1,
+            },
+            wgpu::TextureDimension::D2,
+            [RED, GREEN, BLUE, WHITE].concat(),
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+        )
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn textured_triangles_sample_at_their_uvs() {
+        // Tangents go between colors and UVs in the vertex buffer, so try both layouts.
+        for tangents in [false, true] {
+            let mut app = testing::gpu_app(TriangleRenderPlugin::default());
+            let image = app
+                .world
+                .get_resource_mut::<Assets<Image>>()
+                .unwrap()
+                .add(quadrants());
+            let triangle = lower_left_half([1.0; 4]);
+            let mut entity = app.world.spawn();
+            entity
+                .insert_bundle(TriangleBundle::new(triangle))
+                .insert(TriangleTexture(image));
+            if tangents {
+                entity.insert(TriangleTangents);
+            }
+
+            let pixels = testing::render(&mut app);
+            for (p, texel) in [
+                (Vec2::new(-25.0, 20.0), RED),
+                (Vec2::new(-25.0, -20.0), BLUE),
+                (Vec2::new(20.0, -25.0), WHITE),
+            ] {
+                assert_eq!(
+                    testing::pixel_at(&pixels, p),
+                    texel,
+                    "at {:?} with tangents: {}",
+                    p,
+                    tangents
+                );
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn srgb_vertex_colors_draw_like_their_linear_equivalents() {
+        let color = Color::rgb(0.5, 0.25, 0.75);
+        let linear = render_with(TriangleRenderPlugin::default(), |world| {
+            world
+                .spawn()
+                .insert_bundle(TriangleBundle::new(Triangle::side(20.0).with_color(color)));
+        });
+        let srgb = render_with(
+            TriangleRenderPlugin::default().with_srgb_input(true),
+            |world| {
+                let rgba = [color.r(), color.g(), color.b(), 1.0];
+                world
+                    .spawn()
+                    .insert_bundle(TriangleBundle::new(Triangle::side(20.0).with_rgba(rgba)));
+            },
+        );
+        let (linear, srgb) = (
+            testing::pixel_at(&linear, Vec2::ZERO),
+            testing::pixel_at(&srgb, Vec2::ZERO),
+        );
+        assert_eq!(linear[3], 255);
+        for (l, s) in linear.iter().zip(srgb) {
+            assert!(
+                (*l as i32 - s as i32).abs() <= 1,
+                "{:?} != {:?}",
+                linear,
+                srgb
+            );
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn stripes_alternate_with_gaps() {
+        let pixels = render_with(TriangleRenderPlugin::default(), |world| {
+            world
+                .spawn()
+                .insert_bundle(TriangleBundle::new(lower_left_half([1.0, 0.0, 0.0, 1.0])))
+                .insert(TrianglePattern::Stripes {
+                    spacing: 0.25,
+                    angle: 0.0,
+                });
+        });
+        // Vertical stripes filled over the first half of every quarter of `u`, i.e. 7.5
+        // pixels on, 7.5 off.
+        for (i, x) in [-26.25, -18.75, -11.25, -3.75].into_iter().enumerate() {
+            let expected = if i % 2 == 0 { [255, 0, 0, 255] } else { [0; 4] };
+            assert_eq!(
+                testing::pixel_at(&pixels, Vec2::new(x, -25.0)),
+                expected,
+                "at x = {}",
+                x
+            );
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn alpha_cutoff_discards_below_and_draws_opaque_above() {
+        let pixels = render_with(TriangleRenderPlugin::default(), |world| {
+            for (alpha, x) in [(0.3, -15.0), (0.7, 15.0)] {
+                world
+                    .spawn()
+                    .insert_bundle(TriangleBundle::at(
+                        Triangle::side(20.0).with_rgba([1.0, 0.0, 0.0, alpha]),
+                        Vec3::new(x, 0.0, 0.0),
+                    ))
+                    .insert(TriangleAlphaCutoff(0.5));
+            }
+        });
+        assert_eq!(testing::pixel_at(&pixels, Vec2::new(-15.0, 0.0)), [0; 4]);
+        assert_eq!(
+            testing::pixel_at(&pixels, Vec2::new(15.0, 0.0)),
+            [255, 0, 0, 255]
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn edge_colors_paint_a_band_inside_the_edges() {
+        let triangle = Triangle::side(30.0).with_rgba([1.0, 0.0, 0.0, 1.0]);
+        let bottom = triangle.b.y;
+        let pixels = render_with(TriangleRenderPlugin::default(), |world| {
+            world
+                .spawn()
+                .insert_bundle(TriangleBundle::new(triangle))
+                .insert(TriangleEdgeColor {
+                    color: [0.0, 0.0, 1.0, 1.0],
+                    thickness: 2.0,
+                });
+        });
+        assert_eq!(
+            testing::pixel_at(&pixels, Vec2::new(0.0, bottom + 1.0)),
+            [0, 0, 255, 255]
+        );
+        assert_eq!(
+            testing::pixel_at(&pixels, Vec2::new(0.0, bottom + 5.0)),
+            [255, 0, 0, 255]
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn splits_fill_each_side_of_the_line() {
+        let pixels = render_with(TriangleRenderPlugin::default(), |world| {
+            world
+                .spawn()
+                .insert_bundle(TriangleBundle::at(
+                    Triangle::side(20.0),
+                    Vec3::new(5.0, 0.0, 0.0),
+                ))
+                .insert(TriangleSplit {
+                    point: Vec2::ZERO,
+                    normal: Vec2::X,
+                    color_a: [1.0, 0.0, 0.0, 1.0],
+                    color_b: [0.0, 0.0, 1.0, 1.0],
+                    softness: 0.0,
+                });
+        });
+        // The line goes through the triangle's local origin, moved to x = 5.
+        assert_eq!(
+            testing::pixel_at(&pixels, Vec2::new(1.0, -4.0)),
+            [255, 0, 0, 255]
+        );
+        assert_eq!(
+            testing::pixel_at(&pixels, Vec2::new(9.0, -4.0)),
+            [0, 0, 255, 255]
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn premultiplied_and_straight_colors_blend_alike() {
+        let pixels = render_with(TriangleRenderPlugin::default(), |world| {
+            world.spawn().insert_bundle(TriangleBundle::at(
+                Triangle::side(20.0).with_rgba([1.0, 0.0, 0.0, 0.5]),
+                Vec3::new(-15.0, 0.0, 0.0),
+            ));
+            world
+                .spawn()
+                .insert_bundle(TriangleBundle::at(
+                    Triangle::side(20.0).with_rgba([0.5, 0.0, 0.0, 0.5]),
+                    Vec3::new(15.0, 0.0, 0.0),
+                ))
+                .insert(PremultipliedVertexColor);
+        });
+        let straight = testing::pixel_at(&pixels, Vec2::new(-15.0, 0.0));
+        let premultiplied = testing::pixel_at(&pixels, Vec2::new(15.0, 0.0));
+        // Half of linear red over transparent black, encoded as sRGB.
+        assert!((187..=188).contains(&straight[0]), "{:?}", straight);
+        assert_eq!(straight[1..3], [0, 0]);
+        assert_eq!(straight, premultiplied);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn reveals_discard_past_their_progress() {
+        let pixels = render_with(TriangleRenderPlugin::default(), |world| {
+            world
+                .spawn()
+                .insert_bundle(TriangleBundle::new(lower_left_half([1.0, 0.0, 0.0, 1.0])))
+                .insert(TriangleReveal {
+                    progress: 0.5,
+                    axis: Vec2::X,
+                });
+        });
+        // Shown up to halfway along `u`, i.e. the left half of the view.
+        for x in [-25.0, -2.0] {
+            assert_eq!(
+                testing::pixel_at(&pixels, Vec2::new(x, -25.0)),
+                [255, 0, 0, 255]
+            );
+        }
+        for x in [2.0, 25.0] {
+            assert_eq!(testing::pixel_at(&pixels, Vec2::new(x, -25.0)), [0; 4]);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn back_faces_fill_with_the_back_color() {
+        let pixels = render_with(TriangleRenderPlugin::default(), |world| {
+            // Mirroring the right one turns its back to the camera.
+            for (x, scale_x) in [(-15.0, 1.0), (15.0, -1.0)] {
+                world
+                    .spawn()
+                    .insert_bundle(TriangleBundle::with_transform(
+                        Triangle::side(20.0).with_rgba([1.0, 0.0, 0.0, 1.0]),
+                        Transform::from_xyz(x, 0.0, 0.0).with_scale(Vec3::new(scale_x, 1.0, 1.0)),
+                    ))
+                    .insert(TriangleBackColor([0.0, 0.0, 1.0, 1.0]));
+            }
+        });
+        assert_eq!(
+            testing::pixel_at(&pixels, Vec2::new(-15.0, 0.0)),
+            [255, 0, 0, 255]
+        );
+        assert_eq!(
+            testing::pixel_at(&pixels, Vec2::new(15.0, 0.0)),
+            [0, 0, 255, 255]
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn texture_alpha_modes_keep_hard_edges_from_darkening() {
+        // A white texel next to a transparent one, linearly filtered across the middle of
+        // the view, with the transparent texel stored straight or premultiplied.
+        let edge = |transparent: [u8; 4], mode| {
+            render_with(TriangleRenderPlugin::default(), |world| {
+                let image = Image::new(
+                    wgpu::Extent3d {
+                        width: 2,
+                        height: 1,
+                        depth_or_array_layers: 1,
+                    },
+                    wgpu::TextureDimension::D2,
+                    [[255; 4], transparent].concat(),
+                    wgpu::TextureFormat::Rgba8Unorm,
+                );
+                let image = world
+                    .get_resource_mut::<Assets<Image>>()
+                    .unwrap()
+                    .add(image);
+                world
+                    .spawn()
+                    .insert_bundle(TriangleBundle::new(lower_left_half([1.0; 4])))
+                    .insert_bundle((
+                        TriangleTexture(image),
+                        mode,
+                        TriangleSamplerConfig {
+                            mag_filter: wgpu::FilterMode::Linear,
+                            min_filter: wgpu::FilterMode::Linear,
+                            address_mode: wgpu::AddressMode::ClampToEdge,
+                        },
+                    ));
+            })
+        };
+        let at_edge = |pixels: Vec<[u8; 4]>| testing::pixel_at(&pixels, Vec2::new(0.0, -25.0));
+
+        let straight = at_edge(edge([255, 255, 255, 0], TextureAlphaMode::Straight));
+        let premultiplied = at_edge(edge([0; 4], TextureAlphaMode::Premultiplied));
+        assert!(straight[3] > 0 && straight[3] < 255, "{:?}", straight);
+        for (s, p) in straight.iter().zip(premultiplied) {
+            assert!(
+                (*s as i32 - p as i32).abs() <= 1,
+                "{:?} != {:?}",
+                straight,
+                p
+            );
+        }
+        // Reading the premultiplied texture as straight blends in its black.
+        let fringe = at_edge(edge([0; 4], TextureAlphaMode::Straight));
+        assert!(
+            fringe[0] + 20 < straight[0],
+            "{:?} vs {:?}",
+            fringe,
+            straight
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn uv_transforms_rotate_the_sampled_texels() {
+        let pixels = render_with(TriangleRenderPlugin::default(), |world| {
+            let image = world
+                .get_resource_mut::<Assets<Image>>()
+                .unwrap()
+                .add(quadrants());
+            world
+                .spawn()
+                .insert_bundle(TriangleBundle::new(lower_left_half([1.0; 4])))
+                .insert_bundle((
+                    TriangleTexture(image),
+                    TriangleUvTransform {
+                        rotation: std::f32::consts::FRAC_PI_2,
+                        ..Default::default()
+                    },
+                ));
+        });
+        // A quarter turn samples `(1 - v, u)`, each corner the texel clockwise of its own.
+        for (p, texel) in [
+            (Vec2::new(-25.0, 20.0), GREEN),
+            (Vec2::new(-25.0, -20.0), RED),
+            (Vec2::new(20.0, -25.0), BLUE),
+        ] {
+            assert_eq!(testing::pixel_at(&pixels, p), texel, "at {:?}", p);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn uv_scales_tile_repeating_textures() {
+        let pixels = render_with(TriangleRenderPlugin::default(), |world| {
+            let image = world
+                .get_resource_mut::<Assets<Image>>()
+                .unwrap()
+                .add(quadrants());
+            world
+                .spawn()
+                .insert_bundle(TriangleBundle::new(lower_left_half([1.0; 4])))
+                .insert_bundle((
+                    TriangleTexture(image),
+                    TriangleUvScale(Vec2::splat(2.0)),
+                    TriangleSamplerConfig {
+                        address_mode: wgpu::AddressMode::Repeat,
+                        ..Default::default()
+                    },
+                ));
+        });
+        // The bottom row of texels, twice across.
+        for (x, texel) in [(-25.0, BLUE), (-10.0, WHITE), (5.0, BLUE), (20.0, WHITE)] {
+            assert_eq!(
+                testing::pixel_at(&pixels, Vec2::new(x, -25.0)),
+                texel,
+                "at x = {}",
+                x
+            );
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn dissolving_removes_more_as_it_progresses() {
+        let mut app = testing::gpu_app(TriangleRenderPlugin::default());
+        let entity = app
+            .world
+            .spawn()
+            .insert_bundle(TriangleBundle::new(lower_left_half([1.0, 0.0, 0.0, 1.0])))
+            .id();
+        let mut counts = Vec::new();
+        for progress in [0.0, 0.25, 0.5, 0.75, 1.0] {
+            app.world.entity_mut(entity).insert(TriangleDissolve {
+                progress,
+                edge_width: 0.05,
+                edge_color: [0.0, 0.0, 1.0, 1.0],
+            });
+            let pixels = testing::render(&mut app);
+            let count = |color: [u8; 4]| pixels.iter().filter(|&&p| p == color).count();
+            counts.push((count(RED), count(BLUE)));
+        }
+        // No rim before it starts, then a rim around whatever is left.
+        assert_eq!(counts[0].1, 0);
+        assert!(counts[1..4].iter().all(|&(_, rim)| rim > 0), "{:?}", counts);
+        assert_eq!(counts[4], (0, 0));
+        let drawn = counts
+            .iter()
+            .map(|(fill, rim)| fill + rim)
+            .collect::<Vec<_>>();
+        assert!(drawn.windows(2).all(|w| w[0] > w[1]), "{:?}", drawn);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn smooth_edges_antialias_without_msaa() {
+        // `gpu_app` renders with a single sample.
+        let pixels = render_with(TriangleRenderPlugin::default(), |world| {
+            for x in [-15.0, 15.0] {
+                let mut entity = world.spawn();
+                entity.insert_bundle(TriangleBundle::at(
+                    Triangle::side(24.0).with_rgba([1.0, 0.0, 0.0, 1.0]),
+                    Vec3::new(x, 0.0, 0.0),
+                ));
+                if x > 0.0 {
+                    entity.insert(SmoothEdges);
+                }
+            }
+        });
+        let partial = |right: bool| {
+            pixels
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| {
+                    (i % testing::SIZE as usize >= testing::SIZE as usize / 2) == right
+                })
+                .filter(|(_, p)| p[3] > 0 && p[3] < 255)
+                .count()
+        };
+        assert_eq!(partial(false), 0);
+        assert!(
+            partial(true) > 20,
+            "{} partly covered pixels",
+            partial(true)
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn edge_colors_average_at_the_centroid() {
+        let triangle = Triangle::side(40.0);
+        let centroid = (triangle.a + triangle.b + triangle.c) / 3.0;
+        let pixels = render_with(TriangleRenderPlugin::default(), |world| {
+            world
+                .spawn()
+                .insert_bundle(TriangleBundle::new(triangle))
+                .insert(TriangleEdgeColors([
+                    [1.0, 0.0, 0.0, 1.0],
+                    [0.0, 1.0, 0.0, 1.0],
+                    [0.0, 0.0, 1.0, 1.0],
+                ]));
+        });
+        // A third of each in linear, encoded as sRGB.
+        let pixel = testing::pixel_at(&pixels, centroid);
+        for channel in &pixel[..3] {
+            assert!((153..=160).contains(channel), "{:?}", pixel);
+        }
+        assert_eq!(pixel[3], 255);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn uv_scrolls_move_with_the_global_time() {
+        let mut app = testing::gpu_app(TriangleRenderPlugin::default());
+        // Real time would make the offset depend on how fast frames render.
+        let time = Arc::new(Mutex::new(0.0));
+        let now = time.clone();
+        app.sub_app_mut(RenderApp).add_system_to_stage(
+            RenderStage::Prepare,
+            (move |mut globals: ResMut<TriangleGlobals>| globals.time = *now.lock().unwrap())
+                .before(plugin::TriangleRenderSystem::PrepareGlobals),
+        );
+        let image = app
+            .world
+            .get_resource_mut::<Assets<Image>>()
+            .unwrap()
+            .add(quadrants());
+        app.world
+            .spawn()
+            .insert_bundle(TriangleBundle::new(lower_left_half([1.0; 4])))
+            .insert_bundle((
+                TriangleTexture(image),
+                TriangleUvScroll(Vec2::new(0.5, 0.0)),
+                TriangleSamplerConfig {
+                    address_mode: wgpu::AddressMode::Repeat,
+                    ..Default::default()
+                },
+            ));
+
+        // Each second moves the texture half its width, one texel, to the left.
+        for (seconds, top_left, bottom_right) in [(0.0, RED, WHITE), (1.0, GREEN, BLUE)] {
+            *time.lock().unwrap() = seconds;
+            let pixels = testing::render(&mut app);
+            assert_eq!(testing::pixel_at(&pixels, Vec2::new(-25.0, 20.0)), top_left);
+            assert_eq!(
+                testing::pixel_at(&pixels, Vec2::new(20.0, -25.0)),
+                bottom_right
+            );
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn specializing_two_keys_reports_two_entries() {
+        let mut app = testing::gpu_app(TriangleRenderPlugin::default());
+        let world = app.sub_app_mut(RenderApp).world.cell();
+        let pipeline = world.get_resource::<TrianglePipeline>().unwrap();
+        let mut pipelines = world
+            .get_resource_mut::<SpecializedPipelines<TrianglePipeline>>()
+            .unwrap();
+        let mut cache = world.get_resource_mut::<RenderPipelineCache>().unwrap();
+        let mut specializations = TrianglePipelineSpecializations::default();
+        let keys = [
+            TrianglePipelineKey::NONE,
+            TrianglePipelineKey::TEXTURED,
+            TrianglePipelineKey::NONE,
+        ];
+        let ids =
+            keys.map(|key| specializations.specialize(&mut pipelines, &mut cache, &pipeline, key));
+
+        assert_eq!(ids[0], ids[2]);
+        assert_eq!(specializations.len(), 2);
+        let mut specialized = specializations.keys().collect::<Vec<_>>();
+        specialized.sort_by_key(|key| key.bits());
+        assert_eq!(specialized, keys[..2]);
+        assert_eq!(
+            specializations.key(ids[1]),
+            Some(TrianglePipelineKey::TEXTURED)
+        );
+        assert_eq!(specializations.readiness(&cache).count(), 2);
+    }
+}
@@ -0,0 +1,726 @@
+use bevy::core_pipeline::Transparent2d;
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::render::render_component::UniformComponentPlugin;
+use bevy::render::render_phase::AddRenderCommand;
+use bevy::render::render_resource::{RenderPipelineCache, SpecializedPipelines};
+use bevy::render::{RenderApp, RenderStage};
+use std::marker::PhantomData;
+
+use super::pipeline::{TrianglePipeline, TrianglePipelineKey, TrianglePipelineSpecializations};
+use super::*;
+use crate::{
+    AlphaClamp, Fog, TriangleFrontFace, TriangleGlobalTint, TriangleGroupEffects,
+    TriangleSamplerConfig,
+};
+
+/// The built-in `triangle.wgsl`. Weak handles are keyed by type and id alone, so the id
+/// is a random 64-bit number to make a collision with another crate's shader unlikely;
+/// `build` also checks in debug builds that nothing is registered under it yet.
+pub const SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0xc648c90f09f1fe7d);
+
+/// Settings for the triangle pipeline, copied from the plugin into both worlds so
+/// extract can read them too.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TriangleRenderConfig {
+    /// Winding order of front faces. Back faces are culled, so geometry authored with
+    /// clockwise winding needs `FrontFace::Cw`. Also the winding the built-in shapes are
+    /// meshed in, see `TriangleFrontFace`.
+    pub front_face: wgpu::FrontFace,
+    /// Sampler used for textured triangles without their own `TriangleSamplerConfig`.
+    pub sampler: TriangleSamplerConfig,
+    /// Extra defines for every triangle pipeline, e.g. to switch on code in a custom
+    /// shader. They are added alongside the key-driven defines like `TEXTURED`; a define
+    /// can only be switched on, so these can't disable a key-driven one.
+    pub shader_defs: Vec<String>,
+    /// Treat vertex colors as sRGB and convert them to linear in the fragment shader.
+    /// Off by default, matching the linear convention of `Triangle::with_color`.
+    pub srgb_input: bool,
+    /// Subtract the camera's translation from triangle transforms in extract, and draw
+    /// them with a view-projection that adds it back, so triangles far from the origin
+    /// don't jitter from `f32` rounding. Off by default. Relative to the first camera
+    /// with a window; other views draw triangles offset by the difference.
+    pub camera_relative: bool,
+    /// Faces to cull, `None` to draw both sides.
+    pub cull_mode: Option<wgpu::Face>,
+    /// Replaces the built-in shader. It gets the same bindings, vertex layout and
+    /// defines.
+    pub shader: Option<Handle<Shader>>,
+    /// Level the draw commands log broken phase items at, once per kind of problem, see
+    /// `draw::DrawLog`; `None` silences them. `WARN` by default.
+    pub log_level: Option<bevy::log::Level>,
+    /// Full pipeline keys, MSAA bits included, to compile while the plugin is built
+    /// on top of the plain and textured ones for the app's `Msaa`, e.g.
+    /// `TrianglePipelineKey::from_msaa_samples(4) | TrianglePipelineKey::BLEND_ADD`.
+    /// Each costs a shader compile and pipeline creation up front, in exchange for
+    /// its triangles not waiting a frame or more for them the first time they appear.
+    pub prewarm_keys: Vec<TrianglePipelineKey>,
+    /// Most vertices a batch of `TriangleBatched` triangles holds, rounded down to whole
+    /// triangles. Past it they're split into more batches, each with a vertex buffer and
+    /// draw call of its own, so a pathological scene can't make one huge buffer.
+    pub max_batch_vertices: u32,
+}
+
+impl Default for TriangleRenderConfig {
+    fn default() -> Self {
+        Self {
+            front_face: wgpu::FrontFace::Ccw,
+            sampler: TriangleSamplerConfig::default(),
+            shader_defs: Vec::new(),
+            srgb_input: false,
+            camera_relative: false,
+            cull_mode: Some(wgpu::Face::Back),
+            shader: None,
+            log_level: Some(bevy::log::Level::WARN),
+            prewarm_keys: Vec::new(),
+            max_batch_vertices: 1 << 16,
+        }
+    }
+}
+
+/// Renders `Triangle`s and friends. Configure it with the `with_*` methods, e.g.
+/// `TriangleRenderPlugin::default().with_cull_mode(None)`. To replace part of the
+/// rendering, add the sub-plugins it's made of yourself and leave that part out.
+#[derive(Default)]
+pub struct TriangleRenderPlugin {
+    pub config: TriangleRenderConfig,
+}
+
+impl TriangleRenderPlugin {
+    pub fn with_front_face(mut self, front_face: wgpu::FrontFace) -> Self {
+        self.config.front_face = front_face;
+        self
+    }
+
+    pub fn with_cull_mode(mut self, cull_mode: Option<wgpu::Face>) -> Self {
+        self.config.cull_mode = cull_mode;
+        self
+    }
+
+    pub fn with_sampler(mut self, sampler: TriangleSamplerConfig) -> Self {
+        self.config.sampler = sampler;
+        self
+    }
+
+    pub fn with_shader_def(mut self, def: impl Into<String>) -> Self {
+        self.config.shader_defs.push(def.into());
+        self
+    }
+
+    pub fn with_srgb_input(mut self, srgb_input: bool) -> Self {
+        self.config.srgb_input = srgb_input;
+        self
+    }
+
+    pub fn with_camera_relative(mut self, camera_relative: bool) -> Self {
+        self.config.camera_relative = camera_relative;
+        self
+    }
+
+    pub fn with_custom_shader(mut self, shader: Handle<Shader>) -> Self {
+        self.config.shader = Some(shader);
+        self
+    }
+
+    pub fn with_log_level(mut self, log_level: Option<bevy::log::Level>) -> Self {
+        self.config.log_level = log_level;
+        self
+    }
+
+    pub fn with_prewarm_key(mut self, key: TrianglePipelineKey) -> Self {
+        self.config.prewarm_keys.push(key);
+        self
+    }
+
+    pub fn with_max_batch_vertices(mut self, max_batch_vertices: u32) -> Self {
+        self.config.max_batch_vertices = max_batch_vertices;
+        self
+    }
+}
+
+/// Labels for the plugin's render-world systems, so systems of your own can be
+/// ordered `.before()` or `.after()` them within the same `RenderStage`. Each variant
+/// labels the `system` function it's named after, e.g. `ExtractMeshes` is
+/// `extract_triangle_meshes`.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, SystemLabel)]
+pub enum TriangleRenderSystem {
+    // RenderStage::Extract
+    ExtractMeshes,
+    ExtractGlows,
+    ExtractShadows,
+    ExtractTextures,
+    ExtractChangedImages,
+    ExtractStreaming,
+    ExtractInstances,
+    ExtractBatches,
+    ExtractHdrViews,
+    ExtractScissors,
+    ExtractOrders,
+    ExtractAwaitingReady,
+    ExtractGlobals,
+    // RenderStage::Prepare
+    PrepareGlobals,
+    PrepareStreaming,
+    PrepareInstances,
+    PrepareBatches,
+    // RenderStage::Queue
+    QueueViewBindGroups,
+    QueueMeshBindGroups,
+    QueueTextureBindGroups,
+    QueueReadyTriangles,
+    QueueTriangles,
+    QueueStreamingTriangles,
+    QueueBatches,
+}
+
+/// Marks that plugin `P` has been built, since Bevy 0.6 doesn't track added plugins.
+struct PluginAdded<P>(PhantomData<fn() -> P>);
+
+/// Whether this is the first time plugin `P` is built in `app`, marking it built.
+pub(super) fn first_build<P: 'static>(app: &mut App) -> bool {
+    if app.world.contains_resource::<PluginAdded<P>>() {
+        debug!("{} was already added, skipping", std::any::type_name::<P>());
+        return false;
+    }
+    app.insert_resource(PluginAdded::<P>(PhantomData));
+    true
+}
+
+impl Plugin for TriangleRenderPlugin {
+    /// Adds `TriangleExtractPlugin`, `TrianglePipelinePlugin` and `TriangleDrawPlugin`.
+    /// Adding any of them again is a no-op; the first config wins, with a warning if a
+    /// later one differs.
+    fn build(&self, app: &mut App) {
+        app.add_plugin(TriangleExtractPlugin {
+            config: self.config.clone(),
+        })
+        .add_plugin(TrianglePipelinePlugin)
+        .add_plugin(TriangleDrawPlugin);
+    }
+}
+
+/// The data half of `TriangleRenderPlugin`: the config and main-world resources, the
+/// `Extract*` and `Prepare*` systems, and the `TriangleUniform` buffer. Enough to
+/// drive a queue and draw of your own. Without a `RenderApp` (e.g. headless apps
+/// without `RenderPlugin`) only the main-world half is set up, so nothing is drawn.
+#[derive(Default)]
+pub struct TriangleExtractPlugin {
+    pub config: TriangleRenderConfig,
+}
+
+impl Plugin for TriangleExtractPlugin {
+    fn build(&self, app: &mut App) {
+        if !first_build::<Self>(app) {
+            let installed = app.world.get_resource::<TriangleRenderConfig>();
+            if installed.is_some_and(|installed| *installed != self.config) {
+                warn!(
+                    "the triangle render plugin was already added with another config, \
+                     ignoring this one: {:?}",
+                    self.config
+                );
+            }
+            return;
+        }
+        let prepared = PreparedTriangles::default();
+        let diagnostics = TriangleRenderDiagnostics::default();
+        app.add_plugin(UniformComponentPlugin::<TriangleUniform>::default())
+            .add_plugin(UniformComponentPlugin::<TriangleEdgeUniform>::default())
+            .add_plugin(UniformComponentPlugin::<TriangleFillUniform>::default())
+            .insert_resource(self.config.clone())
+            .insert_resource(TriangleFrontFace(self.config.front_face))
+            .insert_resource(prepared.clone())
+            .insert_resource(diagnostics.clone())
+            .init_resource::<TriangleGlobalTint>()
+            .init_resource::<AlphaClamp>()
+            .init_resource::<Fog>()
+            .init_resource::<TriangleGroupEffects>()
+            .add_system(mark_ready_triangles);
+        let render_app = match app.get_sub_app_mut(RenderApp) {
+            Ok(render_app) => render_app,
+            Err(_) => {
+                warn!(
+                    "TriangleExtractPlugin added without a RenderApp, triangles won't be \
+                     drawn"
+                );
+                return;
+            }
+        };
+        render_app
+            .insert_resource(self.config.clone())
+            .insert_resource(prepared)
+            .insert_resource(diagnostics)
+            .insert_resource(draw::DrawLog::new(self.config.log_level))
+            .init_resource::<GlobalsBuffer>()
+            .init_resource::<ChangedImages>()
+            .init_resource::<StreamingBuffers>()
+            .add_system_to_stage(
+                RenderStage::Extract,
+                system::extract_triangle_meshes.label(TriangleRenderSystem::ExtractMeshes),
+            )
+            .add_system_to_stage(
+                RenderStage::Extract,
+                system::extract_triangle_glows.label(TriangleRenderSystem::ExtractGlows),
+            )
+            .add_system_to_stage(
+                RenderStage::Extract,
+                system::extract_triangle_shadows.label(TriangleRenderSystem::ExtractShadows),
+            )
+            .add_system_to_stage(
+                RenderStage::Extract,
+                system::extract_triangle_textures.label(TriangleRenderSystem::ExtractTextures),
+            )
+            .add_system_to_stage(
+                RenderStage::Extract,
+                system::extract_changed_images.label(TriangleRenderSystem::ExtractChangedImages),
+            )
+            .add_system_to_stage(
+                RenderStage::Extract,
+                system::extract_streaming_triangles.label(TriangleRenderSystem::ExtractStreaming),
+            )
+            .add_system_to_stage(
+                RenderStage::Extract,
+                system::extract_triangle_instances.label(TriangleRenderSystem::ExtractInstances),
+            )
+            .add_system_to_stage(
+                RenderStage::Extract,
+                system::extract_triangle_batches.label(TriangleRenderSystem::ExtractBatches),
+            )
+            .add_system_to_stage(
+                RenderStage::Extract,
+                system::extract_hdr_views.label(TriangleRenderSystem::ExtractHdrViews),
+            )
+            .add_system_to_stage(
+                RenderStage::Extract,
+                system::extract_triangle_scissors.label(TriangleRenderSystem::ExtractScissors),
+            )
+            .add_system_to_stage(
+                RenderStage::Extract,
+                system::extract_triangle_orders.label(TriangleRenderSystem::ExtractOrders),
+            )
+            .add_system_to_stage(
+                RenderStage::Extract,
+                system::extract_awaiting_ready.label(TriangleRenderSystem::ExtractAwaitingReady),
+            )
+            .add_system_to_stage(
+                RenderStage::Extract,
+                system::extract_globals.label(TriangleRenderSystem::ExtractGlobals),
+            )
+            .add_system_to_stage(
+                RenderStage::Prepare,
+                system::prepare_globals.label(TriangleRenderSystem::PrepareGlobals),
+            )
+            .add_system_to_stage(
+                RenderStage::Prepare,
+                system::prepare_streaming_triangles.label(TriangleRenderSystem::PrepareStreaming),
+            )
+            .add_system_to_stage(
+                RenderStage::Prepare,
+                system::prepare_triangle_instances.label(TriangleRenderSystem::PrepareInstances),
+            )
+            .add_system_to_stage(
+                RenderStage::Prepare,
+                system::prepare_triangle_batches.label(TriangleRenderSystem::PrepareBatches),
+            )
+            .add_system_to_stage(
+                RenderStage::Queue,
+                system::queue_ready_triangles.label(TriangleRenderSystem::QueueReadyTriangles),
+            );
+        #[cfg(debug_assertions)]
+        render_app.init_resource::<LastUploadedTriangleData>();
+    }
+}
+
+/// The built-in shader, `TrianglePipeline` and the `Queue*BindGroups` systems. Needs
+/// `TriangleExtractPlugin` added first.
+#[derive(Default)]
+pub struct TrianglePipelinePlugin;
+
+impl Plugin for TrianglePipelinePlugin {
+    fn build(&self, app: &mut App) {
+        if !first_build::<Self>(app) || app.get_sub_app_mut(RenderApp).is_err() {
+            return;
+        }
+        let mut shaders = app.world.get_resource_mut::<Assets<Shader>>().unwrap();
+        debug_assert!(
+            shaders.get(SHADER_HANDLE).is_none(),
+            "another shader is already registered under the triangle SHADER_HANDLE"
+        );
+        shaders.set_untracked(
+            SHADER_HANDLE,
+            Shader::from_wgsl(include_str!("triangle.wgsl")),
+        );
+        let msaa_samples = app
+            .world
+            .get_resource::<Msaa>()
+            .map_or(Msaa::default().samples, |msaa| msaa.samples);
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app
+            .init_resource::<TrianglePipeline>()
+            .init_resource::<SpecializedPipelines<TrianglePipeline>>()
+            .init_resource::<TrianglePipelineSpecializations>();
+        prewarm_pipelines(&mut render_app.world, msaa_samples);
+        render_app
+            .init_resource::<TextureBindGroupCache>()
+            .add_system_to_stage(
+                RenderStage::Queue,
+                system::queue_view_bind_groups.label(TriangleRenderSystem::QueueViewBindGroups),
+            )
+            .add_system_to_stage(
+                RenderStage::Queue,
+                system::queue_mesh_bind_groups.label(TriangleRenderSystem::QueueMeshBindGroups),
+            )
+            .add_system_to_stage(
+                RenderStage::Queue,
+                system::queue_texture_bind_groups
+                    .label(TriangleRenderSystem::QueueTextureBindGroups),
+            );
+        #[cfg(debug_assertions)]
+        render_app
+            .add_system_to_stage(RenderStage::Extract, system::extract_triangle_shader)
+            .add_system_to_stage(
+                RenderStage::Queue,
+                system::check_shader_uniform_sizes
+                    .after(TriangleRenderSystem::QueueTriangles)
+                    .after(TriangleRenderSystem::QueueStreamingTriangles)
+                    .after(TriangleRenderSystem::QueueBatches),
+            );
+    }
+}
+
+/// Queues the plain and textured triangle-list pipelines for `msaa_samples` and the
+/// config's `prewarm_keys`, so they compile with the first frame instead of whenever
+/// the first such triangle shows up. Nothing can draw before that first frame anyway,
+/// since the shader only reaches the render world in its extract. Other keys still
+/// compile on first use, and their triangles are left out of the phase until then.
+fn prewarm_pipelines(world: &mut World, msaa_samples: u32) {
+    let world = world.cell();
+    let config = world.get_resource::<TriangleRenderConfig>();
+    let pipeline = world.get_resource::<TrianglePipeline>().unwrap();
+    let mut pipelines = world
+        .get_resource_mut::<SpecializedPipelines<TrianglePipeline>>()
+        .unwrap();
+    let mut specializations = world
+        .get_resource_mut::<TrianglePipelineSpecializations>()
+        .unwrap();
+    let mut cache = match world.get_resource_mut::<RenderPipelineCache>() {
+        Some(cache) => cache,
+        None => return,
+    };
+    let msaa_key = TrianglePipelineKey::from_msaa_samples(msaa_samples);
+    let keys = [TrianglePipelineKey::NONE, TrianglePipelineKey::TEXTURED]
+        .map(|key| msaa_key | key)
+        .into_iter()
+        .chain(config.iter().flat_map(|config| config.prewarm_keys.clone()));
+    for key in keys {
+        specializations.specialize(&mut pipelines, &mut cache, &pipeline, key);
+    }
+}
+
+/// The draw commands and the `QueueTriangles` / `QueueStreamingTriangles` /
+/// `QueueBatches` systems that add triangles to `Transparent2d`. Needs `TrianglePipelinePlugin` added first.
+#[derive(Default)]
+pub struct TriangleDrawPlugin;
+
+impl Plugin for TriangleDrawPlugin {
+    fn build(&self, app: &mut App) {
+        if !first_build::<Self>(app) || app.get_sub_app_mut(RenderApp).is_err() {
+            return;
+        }
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app
+            .add_render_command::<Transparent2d, draw::DrawTriangle>()
+            .add_render_command::<Transparent2d, draw::DrawStreamingTriangle>()
+            .add_render_command::<Transparent2d, draw::DrawBatchedTriangles>()
+            .add_system_to_stage(
+                RenderStage::Queue,
+                system::queue_triangles.label(TriangleRenderSystem::QueueTriangles),
+            )
+            .add_system_to_stage(
+                RenderStage::Queue,
+                system::queue_streaming_triangles
+                    .label(TriangleRenderSystem::QueueStreamingTriangles),
+            )
+            .add_system_to_stage(
+                RenderStage::Queue,
+                system::queue_triangle_batches.label(TriangleRenderSystem::QueueBatches),
+            );
+        #[cfg(feature = "gpu-timing")]
+        timing::add_gpu_timer(render_app);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::schedule::GraphNode;
+    use bevy::render::render_component::ComponentUniforms;
+    use bevy::render::render_phase::DrawFunctions;
+
+    use crate::render::testing;
+    use crate::{Triangle, TriangleBundle, TriangleMeshHandle};
+
+    /// Whether each of `keys` has been specialized and compiled.
+    fn compiled(app: &mut App, keys: &[TrianglePipelineKey]) -> Vec<bool> {
+        let world = &app.sub_app_mut(RenderApp).world;
+        let cache = world.get_resource::<RenderPipelineCache>().unwrap();
+        let readiness: HashMap<_, _> = world
+            .get_resource::<TrianglePipelineSpecializations>()
+            .unwrap()
+            .readiness(cache)
+            .collect();
+        keys.iter()
+            .map(|key| readiness.get(key).copied().unwrap_or(false))
+            .collect()
+    }
+
+    /// The number of systems in each stage of `schedule`.
+    fn system_counts(schedule: &Schedule) -> Vec<(String, usize)> {
+        schedule
+            .iter_stages()
+            .filter_map(|(label, stage)| {
+                let stage = stage.downcast_ref::<SystemStage>()?;
+                let count = stage.parallel_systems().len()
+                    + stage.exclusive_at_start_systems().len()
+                    + stage.exclusive_before_commands_systems().len()
+                    + stage.exclusive_at_end_systems().len();
+                Some((format!("{:?}", label), count))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn the_plugin_builds_without_a_render_app() {
+        let mut app = crate::tests::app();
+        app.add_plugin(TriangleRenderPlugin::default());
+        assert!(app.get_sub_app_mut(RenderApp).is_err());
+        assert!(app.world.get_resource::<TriangleRenderConfig>().is_some());
+
+        // The main-world half still meshes triangles.
+        let entity = app
+            .world
+            .spawn()
+            .insert_bundle(TriangleBundle::new(Triangle::side(10.0)))
+            .id();
+        app.update();
+        assert!(app.world.get::<TriangleMeshHandle>(entity).is_some());
+    }
+
+    #[test]
+    fn adding_the_plugin_twice_adds_it_once() {
+        let mut app = crate::tests::app();
+        app.add_plugin(TriangleRenderPlugin::default());
+        let systems = system_counts(&app.schedule);
+        let resources = app.world.archetypes().resource().components().count();
+        app.add_plugin(TriangleRenderPlugin::default().with_max_batch_vertices(3));
+        assert_eq!(system_counts(&app.schedule), systems);
+        assert_eq!(
+            app.world.archetypes().resource().components().count(),
+            resources
+        );
+        // The first config wins.
+        let config = app.world.get_resource::<TriangleRenderConfig>().unwrap();
+        assert_eq!(config.max_batch_vertices, 1 << 16);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn adding_the_plugin_twice_adds_its_render_half_once() {
+        let mut app = testing::gpu_app(TriangleRenderPlugin::default());
+        let counts = |app: &mut App| {
+            let render_app = app.sub_app_mut(RenderApp);
+            let draw_functions = render_app
+                .world
+                .get_resource::<DrawFunctions<Transparent2d>>()
+                .unwrap()
+                .read()
+                .draw_functions
+                .len();
+            (
+                system_counts(&render_app.schedule),
+                render_app
+                    .world
+                    .archetypes()
+                    .resource()
+                    .components()
+                    .count(),
+                draw_functions,
+            )
+        };
+        let before = counts(&mut app);
+        app.add_plugin(TriangleRenderPlugin::default());
+        assert_eq!(counts(&mut app), before);
+        // And it still draws.
+        app.world.spawn().insert_bundle(TriangleBundle::new(
+            Triangle::side(20.0).with_rgba([1.0, 0.0, 0.0, 1.0]),
+        ));
+        let pixels = testing::render(&mut app);
+        assert_eq!(testing::pixel_at(&pixels, Vec2::ZERO), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn extract_alone_fills_the_uniform_buffer() {
+        let mut app = testing::gpu_app(TriangleExtractPlugin::default());
+        for x in [-15.0, 15.0] {
+            app.world.spawn().insert_bundle(TriangleBundle::at(
+                Triangle::side(10.0),
+                Vec3::new(x, 0.0, 0.0),
+            ));
+        }
+        app.update();
+
+        let world = &app.sub_app_mut(RenderApp).world;
+        let uniforms = world
+            .get_resource::<ComponentUniforms<TriangleUniform>>()
+            .unwrap();
+        assert_eq!(uniforms.uniforms().len(), 2);
+        assert!(uniforms.uniforms().uniform_buffer().is_some());
+        // Nothing to queue or draw them with.
+        assert!(!world.contains_resource::<TrianglePipeline>());
+        let draw_functions = world
+            .get_resource::<DrawFunctions<Transparent2d>>()
+            .unwrap();
+        assert!(draw_functions.read().draw_functions.is_empty());
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn user_systems_order_against_the_labels() {
+        fn before_globals() {}
+        fn after_globals() {}
+        let mut app = testing::gpu_app(TriangleRenderPlugin::default());
+        app.sub_app_mut(RenderApp)
+            .add_system_to_stage(
+                RenderStage::Prepare,
+                after_globals.after(TriangleRenderSystem::PrepareGlobals),
+            )
+            .add_system_to_stage(
+                RenderStage::Prepare,
+                before_globals.before(TriangleRenderSystem::PrepareGlobals),
+            );
+        // The stage sorts its systems the first time it runs.
+        app.update();
+
+        let prepare = app
+            .sub_app_mut(RenderApp)
+            .schedule
+            .get_stage::<SystemStage>(&RenderStage::Prepare)
+            .unwrap();
+        let names: Vec<_> = prepare
+            .parallel_systems()
+            .iter()
+            .map(|system| system.name())
+            .collect();
+        let position = |name: &str| names.iter().position(|n| n.ends_with(name)).unwrap();
+        // Added last, but sorted before it.
+        assert!(position("::before_globals") < position("::prepare_globals"));
+        assert!(position("::prepare_globals") < position("::after_globals"));
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn plugin_instances_keep_their_own_shaders() {
+        let mut app = testing::gpu_app(TriangleRenderPlugin::default());
+        app.add_plugin(TriangleRenderPlugin::default().with_shader_def("WOBBLE"))
+            .add_plugin(crate::render::intersection::TriangleIntersectionHighlightPlugin);
+        let shaders = app.world.get_resource::<Assets<Shader>>().unwrap();
+        // `Shader` has no `PartialEq`, but its `Debug` output includes the source.
+        let registered = |handle| format!("{:?}", shaders.get(handle).unwrap());
+        let wgsl = |source| format!("{:?}", Shader::from_wgsl(source));
+        assert_eq!(
+            registered(SHADER_HANDLE),
+            wgsl(include_str!("triangle.wgsl"))
+        );
+        assert_eq!(
+            registered(crate::render::intersection::SHADER_HANDLE),
+            wgsl(include_str!("triangle_intersection.wgsl"))
+        );
+
+        // The second instance didn't trip the debug assertion, and the shader still draws.
+        app.world.spawn().insert_bundle(TriangleBundle::new(
+            Triangle::side(20.0).with_rgba([1.0, 0.0, 0.0, 1.0]),
+        ));
+        let pixels = testing::render(&mut app);
+        assert_eq!(testing::pixel_at(&pixels, Vec2::ZERO), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn common_pipelines_compile_before_the_first_triangle() {
+        let mut app = testing::gpu_app(TriangleRenderPlugin::default());
+        let msaa = TrianglePipelineKey::from_msaa_samples(1);
+        let common = [msaa, msaa | TrianglePipelineKey::TEXTURED];
+        assert_eq!(compiled(&mut app, &common), [false, false]);
+        app.update();
+        assert_eq!(compiled(&mut app, &common), [true, true]);
+
+        // So a triangle draws in the first frame it's queued in.
+        app.world.spawn().insert_bundle(TriangleBundle::new(
+            Triangle::side(20.0).with_rgba([1.0, 0.0, 0.0, 1.0]),
+        ));
+        app.update();
+        let pixels = testing::read_back(&mut app);
+        assert_eq!(testing::pixel_at(&pixels, Vec2::ZERO), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn prewarm_keys_compile_at_startup() {
+        let msaa = TrianglePipelineKey::from_msaa_samples(1);
+        let keys = [
+            msaa | TrianglePipelineKey::BLEND_ADD,
+            msaa | TrianglePipelineKey::PATTERN_STRIPES | TrianglePipelineKey::EDGE_COLOR,
+        ];
+        let plugin = keys
+            .iter()
+            .fold(TriangleRenderPlugin::default(), |plugin, key| {
+                plugin.with_prewarm_key(*key)
+            });
+        let mut app = testing::gpu_app(plugin);
+        assert_eq!(compiled(&mut app, &keys), [false, false]);
+        app.update();
+        assert_eq!(compiled(&mut app, &keys), [true, true]);
+    }
+
+    #[test]
+    fn the_config_resource_reflects_the_builder() {
+        let sampler = TriangleSamplerConfig {
+            mag_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        };
+        let shader = Handle::<Shader>::weak(HandleId::random::<Shader>());
+        let key = TrianglePipelineKey::from_msaa_samples(4) | TrianglePipelineKey::BLEND_ADD;
+        let plugin = TriangleRenderPlugin::default()
+            .with_front_face(wgpu::FrontFace::Cw)
+            .with_cull_mode(None)
+            .with_sampler(sampler)
+            .with_shader_def("WOBBLE")
+            .with_srgb_input(true)
+            .with_camera_relative(true)
+            .with_custom_shader(shader.clone())
+            .with_log_level(None)
+            .with_prewarm_key(key)
+            .with_max_batch_vertices(300);
+        let mut app = crate::tests::app();
+        app.add_plugin(plugin);
+
+        let front_face = app.world.get_resource::<TriangleFrontFace>().unwrap();
+        assert_eq!(front_face.0, wgpu::FrontFace::Cw);
+        let config = app.world.get_resource::<TriangleRenderConfig>().unwrap();
+        assert_eq!(config.front_face, wgpu::FrontFace::Cw);
+        assert_eq!(config.cull_mode, None);
+        assert_eq!(config.sampler, sampler);
+        assert_eq!(config.shader_defs, ["WOBBLE"]);
+        assert!(config.srgb_input);
+        assert!(config.camera_relative);
+        assert_eq!(config.shader, Some(shader));
+        assert_eq!(config.log_level, None);
+        assert_eq!(config.prewarm_keys, [key]);
+        assert_eq!(config.max_batch_vertices, 300);
+
+        let default = TriangleRenderConfig::default();
+        assert_eq!(default.cull_mode, Some(wgpu::Face::Back));
+        assert_eq!(default.max_batch_vertices, 1 << 16);
+    }
+}
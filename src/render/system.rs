@@ -0,0 +1,2200 @@
+use bevy::{
+    core::FloatOrd,
+    core_pipeline::Transparent2d,
+    ecs::system::SystemParam,
+    prelude::*,
+    render::{
+        mesh::{GpuBufferInfo, Indices, VertexAttributeValues},
+        render_asset::RenderAssets,
+        render_component::ComponentUniforms,
+        render_phase::{DrawFunctions, RenderPhase},
+        render_resource::{RenderPipelineCache, ShaderProcessor, SpecializedPipelines},
+        renderer::{RenderDevice, RenderQueue},
+        view::{ExtractedView, ViewUniforms, VisibleEntities},
+    },
+};
+use itertools::Itertools;
+
+use super::*;
+use crate::{
+    AlphaClamp, Fog, StreamingVertex, TextureAlphaMode, TriangleBatched, TriangleGlobalTint,
+    TriangleHdr, TriangleInstance, TriangleInstanceUv, TriangleInstances, TriangleMeshHandle,
+    TriangleOrder, TriangleSamplerConfig, TriangleScissor, TriangleTexture,
+};
+use pipeline::TrianglePipeline;
+use plugin::TriangleRenderConfig;
+
+/// The first camera with a window, which extract measures triangles against.
+#[derive(SystemParam)]
+pub struct ExtractCamera<'w, 's> {
+    config: Res<'w, TriangleRenderConfig>,
+    windows: Res<'w, Windows>,
+    camera_q: Query<'w, 's, (&'static Camera, &'static GlobalTransform)>,
+}
+
+impl<'w, 's> ExtractCamera<'w, 's> {
+    fn camera(&self) -> Option<(&Camera, &GlobalTransform, Vec2)> {
+        self.camera_q.iter().find_map(|(camera, tform)| {
+            let window = self.windows.get(camera.window)?;
+            Some((camera, tform, Vec2::new(window.width(), window.height())))
+        })
+    }
+
+    /// The camera's view-projection and window size in pixels.
+    pub fn view(&self) -> Option<(Mat4, Vec2)> {
+        self.camera().map(|(camera, tform, size)| {
+            (
+                camera.projection_matrix * tform.compute_matrix().inverse(),
+                size,
+            )
+        })
+    }
+
+    /// What to subtract from triangle translations: the camera's `x` and `y` in
+    /// camera-relative mode, zero otherwise. `z` is kept so triangles still sort against
+    /// other 2d items.
+    pub fn offset(&self) -> Vec3 {
+        match self.camera() {
+            Some((_, tform, _)) if self.config.camera_relative => {
+                tform.translation.truncate().extend(0.0)
+            }
+            _ => Vec3::ZERO,
+        }
+    }
+
+    /// The matrix of `tform`, with the camera's rotation instead of its own if
+    /// `billboard`.
+    pub fn transform(&self, tform: &GlobalTransform, billboard: Option<&Billboard>) -> Mat4 {
+        match (billboard, self.camera()) {
+            (Some(_), Some((_, camera, _))) => GlobalTransform {
+                rotation: camera.rotation,
+                ..*tform
+            }
+            .compute_matrix(),
+            _ => tform.compute_matrix(),
+        }
+    }
+
+    /// `transform` moved by `-offset()`.
+    pub fn relative(&self, transform: Mat4) -> Mat4 {
+        Mat4::from_translation(-self.offset()) * transform
+    }
+
+    /// The plane giving a point's depth along the camera's forward axis, see
+    /// `TriangleGlobals::fog_plane`.
+    pub fn depth_plane(&self) -> Vec4 {
+        self.camera().map_or(Vec4::ZERO, |(_, tform, _)| {
+            let forward = tform.rotation * -Vec3::Z;
+            let eye = tform.translation - self.offset();
+            forward.extend(-forward.dot(eye))
+        })
+    }
+
+    /// The view-projection that draws `relative` transforms where the absolute ones
+    /// would be.
+    pub fn relative_view_proj(&self) -> Mat4 {
+        self.camera().map_or(Mat4::IDENTITY, |(camera, tform, _)| {
+            let mut view = *tform;
+            view.translation -= self.offset();
+            camera.projection_matrix * view.compute_matrix().inverse()
+        })
+    }
+}
+
+/// A `Vec` of bundles for `insert_or_spawn_batch` that comes back, emptied, once the
+/// command has been applied, so an extract system fills the same allocation every frame
+/// instead of handing a fresh one to the render world's command queue.
+pub struct BatchBuffer<B>(Arc<Mutex<Vec<(Entity, B)>>>);
+
+impl<B> Default for BatchBuffer<B> {
+    fn default() -> Self {
+        Self(Default::default())
+    }
+}
+
+impl<B: Bundle> BatchBuffer<B> {
+    /// The buffer to fill, empty but with the capacity it grew to last frame.
+    pub fn take(&self) -> Vec<(Entity, B)> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+
+    /// Queues `Commands::insert_or_spawn_batch` of `batch`, returning it here afterwards.
+    pub fn insert_or_spawn(&self, commands: &mut Commands, batch: Vec<(Entity, B)>) {
+        commands.add(InsertOrSpawnBuffer {
+            batch,
+            buffer: self.0.clone(),
+        });
+    }
+}
+
+struct InsertOrSpawnBuffer<B> {
+    batch: Vec<(Entity, B)>,
+    buffer: Arc<Mutex<Vec<(Entity, B)>>>,
+}
+
+impl<B: Bundle> bevy::ecs::system::Command for InsertOrSpawnBuffer<B> {
+    fn write(mut self, world: &mut World) {
+        if let Err(invalid) = world.insert_or_spawn_batch(self.batch.drain(..)) {
+            error!(
+                "Failed to 'insert or spawn' {} into {:?}",
+                std::any::type_name::<B>(),
+                invalid
+            );
+        }
+        *self.buffer.lock().unwrap() = self.batch;
+    }
+}
+
+/// How much to scale a triangle whose `transform` puts it on screen through `view`
+/// (view-projection and window size in pixels) so it is at least `min_pixels` across.
+/// Never less than 1.
+fn min_pixel_scale(
+    triangle: &Triangle,
+    transform: Mat4,
+    min_pixels: f32,
+    (view_proj, window_size): (Mat4, Vec2),
+) -> f32 {
+    let min = triangle.a.min(triangle.b).min(triangle.c);
+    let max = triangle.a.max(triangle.b).max(triangle.c);
+    let clip = view_proj * transform;
+    let (lo, hi) = [min, Vec2::new(min.x, max.y), max, Vec2::new(max.x, min.y)]
+        .iter()
+        .map(|corner| {
+            let ndc = clip.project_point3(corner.extend(0.0)).truncate();
+            ndc * window_size / 2.0
+        })
+        .fold(
+            (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+            |(lo, hi), p| (lo.min(p), hi.max(p)),
+        );
+    let pixels = (hi - lo).max_element();
+    if pixels > 0.0 && pixels.is_finite() {
+        (min_pixels / pixels).max(1.0)
+    } else {
+        1.0
+    }
+}
+
+/// Reads `GlobalTransform` rather than `Transform`, so triangles parented to other
+/// entities render with the combined transform once `transform_propagate_system` has run
+/// (it always has by the time the extract stage runs).
+#[allow(clippy::type_complexity)]
+pub fn extract_triangle_meshes(
+    mut commands: Commands,
+    buffers: Local<(
+        BatchBuffer<(TriangleMeshHandle, TriangleUniform, TriangleFeatures)>,
+        BatchBuffer<(TriangleEdgeUniform,)>,
+        BatchBuffer<(TriangleFillUniform,)>,
+    )>,
+    camera: ExtractCamera,
+    triangle_mesh_q: Query<
+        (
+            Entity,
+            &TriangleMeshHandle,
+            &GlobalTransform,
+            &ComputedVisibility,
+            Option<&TrianglePattern>,
+            Option<&TriangleAlphaCutoff>,
+            Option<(&Triangle, &TriangleEdgeColor)>,
+            Option<(&Triangle, &MinPixelSize, Option<&TriangleAnchor>)>,
+            Option<&Billboard>,
+            Option<&TriangleSplit>,
+            Option<&PremultipliedVertexColor>,
+            Option<&TriangleReveal>,
+            (
+                Option<&TriangleBackColor>,
+                Option<&TriangleOpacity>,
+                Option<&TriangleUvTransform>,
+                Option<&TriangleGroup>,
+                Option<&TriangleUvScale>,
+                Option<&TriangleDissolve>,
+                Option<(&Triangle, &SmoothEdges)>,
+                Option<(&Triangle, &TriangleEdgeColors)>,
+                Option<&TriangleUvScroll>,
+            ),
+        ),
+        Without<TriangleBatched>,
+    >,
+    group_effects: Res<TriangleGroupEffects>,
+) {
+    let view = camera.view();
+    let mut components = buffers.0.take();
+    let mut edges = buffers.1.take();
+    let mut fills = buffers.2.take();
+    for (
+        entity,
+        triangle_mesh_handle,
+        tform,
+        vis,
+        pattern,
+        cutoff,
+        edge,
+        min_size,
+        billboard,
+        split,
+        premultiplied,
+        reveal,
+        (
+            back_color,
+            opacity,
+            uv_transform,
+            group,
+            uv_scale,
+            dissolve,
+            smooth,
+            edge_colors,
+            uv_scroll,
+        ),
+    ) in triangle_mesh_q.iter()
+    {
+        if !vis.is_visible {
+            continue;
+        }
+        let pattern = pattern.copied().unwrap_or_default();
+        let mut transform = camera.transform(tform, billboard);
+        if let (Some((triangle, min_size, anchor)), Some(view)) = (min_size, view) {
+            // The mesh is built anchored, so measure the anchored triangle.
+            let triangle = triangle.anchored(anchor.copied().unwrap_or_default());
+            let scale = min_pixel_scale(&triangle, transform, min_size.0, view);
+            transform *= Mat4::from_scale(Vec3::new(scale, scale, 1.0));
+        }
+        let mut uniform = TriangleUniform {
+            opacity: opacity.copied().unwrap_or_default().0,
+            uv_transform: uv_transform.map_or(Mat3::IDENTITY, |uv| uv.matrix())
+                * Mat3::from_scale(uv_scale.copied().unwrap_or_default().0),
+            uv_scroll: uv_scroll.copied().unwrap_or_default().0,
+            ..TriangleUniform::new(camera.relative(transform))
+        };
+        if let Some(effect) = group.and_then(|group| group_effects.get(group.0)) {
+            uniform.tint = Vec4::from(effect.tint);
+            uniform.opacity *= effect.opacity;
+        }
+        let mut edge_uniform = None;
+        let mut fill_uniform = None;
+        let mut features = pipeline::TrianglePipelineKey::from_pattern(pattern);
+        if features != pipeline::TrianglePipelineKey::NONE {
+            fill_uniform
+                .get_or_insert_with(TriangleFillUniform::default)
+                .pattern = pattern.params();
+        }
+        if let Some(cutoff) = cutoff {
+            fill_uniform
+                .get_or_insert_with(TriangleFillUniform::default)
+                .alpha_cutoff = cutoff.0;
+            features |= pipeline::TrianglePipelineKey::ALPHA_MASK;
+        }
+        if let Some((triangle, edge)) = edge {
+            let edge_uniform = edge_uniform.get_or_insert_with(TriangleEdgeUniform::default);
+            edge_uniform.feather = Vec3::from(triangle.heights()).extend(0.0);
+            edge_uniform.edge_color = Vec4::from(edge.color);
+            edge_uniform.edge_thickness = edge.thickness;
+            features |= pipeline::TrianglePipelineKey::EDGE_COLOR;
+        }
+        if let Some(split) = split {
+            let fill_uniform = fill_uniform.get_or_insert_with(TriangleFillUniform::default);
+            fill_uniform.split = split.params();
+            fill_uniform.split_color_a = Vec4::from(split.color_a);
+            fill_uniform.split_color_b = Vec4::from(split.color_b);
+            features |= pipeline::TrianglePipelineKey::SPLIT;
+        }
+        if premultiplied.is_some() {
+            features |= pipeline::TrianglePipelineKey::PREMULTIPLIED_ALPHA;
+        }
+        if let Some(reveal) = reveal {
+            fill_uniform
+                .get_or_insert_with(TriangleFillUniform::default)
+                .reveal = reveal.params();
+            features |= pipeline::TrianglePipelineKey::REVEAL;
+        }
+        if let Some(back_color) = back_color {
+            fill_uniform
+                .get_or_insert_with(TriangleFillUniform::default)
+                .back_color = Vec4::from(back_color.0);
+            features |= pipeline::TrianglePipelineKey::BACK_COLOR;
+        }
+        if let Some(dissolve) = dissolve {
+            let fill_uniform = fill_uniform.get_or_insert_with(TriangleFillUniform::default);
+            fill_uniform.dissolve = dissolve.params();
+            fill_uniform.dissolve_color = Vec4::from(dissolve.edge_color);
+            features |= pipeline::TrianglePipelineKey::DISSOLVE;
+        }
+        if let Some((triangle, _)) = smooth {
+            edge_uniform
+                .get_or_insert_with(TriangleEdgeUniform::default)
+                .feather = Vec3::from(triangle.heights()).extend(0.0);
+            features |= pipeline::TrianglePipelineKey::SMOOTH_EDGES;
+        }
+        if let Some((_, edge_colors)) = edge_colors {
+            let edge_uniform = edge_uniform.get_or_insert_with(TriangleEdgeUniform::default);
+            let [a, b, c] = edge_colors.0.map(Vec4::from);
+            edge_uniform.edge_color_a = a;
+            edge_uniform.edge_color_b = b;
+            edge_uniform.edge_color_c = c;
+            features |= pipeline::TrianglePipelineKey::EDGE_COLORS;
+        }
+        if let Some(edge_uniform) = edge_uniform {
+            edges.push((entity, (edge_uniform,)));
+        }
+        if let Some(fill_uniform) = fill_uniform {
+            fills.push((entity, (fill_uniform,)));
+        }
+        components.push((
+            entity,
+            (
+                triangle_mesh_handle.clone_weak(),
+                uniform,
+                TriangleFeatures(features),
+            ),
+        ));
+    }
+    buffers.0.insert_or_spawn(&mut commands, components);
+    buffers.1.insert_or_spawn(&mut commands, edges);
+    buffers.2.insert_or_spawn(&mut commands, fills);
+}
+
+/// Spawns a render-world entity per glowing triangle, drawn with the triangle's mesh
+/// through an additive `FEATHER` pipeline.
+#[allow(clippy::type_complexity)]
+pub fn extract_triangle_glows(
+    mut commands: Commands,
+    camera: ExtractCamera,
+    glow_q: Query<(
+        Entity,
+        &Triangle,
+        &TriangleGlow,
+        Option<&TriangleAnchor>,
+        &TriangleMeshHandle,
+        &GlobalTransform,
+        &ComputedVisibility,
+        Option<&Billboard>,
+    )>,
+) {
+    let features =
+        pipeline::TrianglePipelineKey::FEATHER | pipeline::TrianglePipelineKey::BLEND_ADD;
+    let glows = glow_q
+        .iter()
+        .filter(|(_, _, glow, .., vis, _)| vis.is_visible && glow.radius > 0.0)
+        .filter_map(
+            |(entity, triangle, glow, anchor, handle, tform, _, billboard)| {
+                let triangle = triangle.anchored(anchor.copied().unwrap_or_default());
+                let (uniform, edge) = TriangleUniform::feathered(
+                    camera.relative(camera.transform(tform, billboard)),
+                    &triangle,
+                    glow.color,
+                    glow.radius,
+                )?;
+                Some((
+                    handle.clone_weak(),
+                    uniform,
+                    edge,
+                    TriangleFeatures(features),
+                    HaloSource { entity, depth: 1.0 },
+                ))
+            },
+        )
+        .collect_vec();
+    commands.spawn_batch(glows);
+}
+
+/// Like `extract_triangle_glows`, but alpha blended and offset in world space.
+#[allow(clippy::type_complexity)]
+pub fn extract_triangle_shadows(
+    mut commands: Commands,
+    camera: ExtractCamera,
+    shadow_q: Query<(
+        Entity,
+        &Triangle,
+        &TriangleShadow,
+        Option<&TriangleAnchor>,
+        &TriangleMeshHandle,
+        &GlobalTransform,
+        &ComputedVisibility,
+        Option<&Billboard>,
+    )>,
+) {
+    let shadows = shadow_q
+        .iter()
+        .filter(|(.., vis, _)| vis.is_visible)
+        .filter_map(
+            |(entity, triangle, shadow, anchor, handle, tform, _, billboard)| {
+                let triangle = triangle.anchored(anchor.copied().unwrap_or_default());
+                let transform = Mat4::from_translation(shadow.offset.extend(0.0))
+                    * camera.transform(tform, billboard);
+                let (uniform, edge) = TriangleUniform::feathered(
+                    camera.relative(transform),
+                    &triangle,
+                    shadow.color,
+                    shadow.blur,
+                )?;
+                Some((
+                    handle.clone_weak(),
+                    uniform,
+                    edge,
+                    TriangleFeatures(pipeline::TrianglePipelineKey::FEATHER),
+                    HaloSource { entity, depth: 2.0 },
+                ))
+            },
+        )
+        .collect_vec();
+    commands.spawn_batch(shadows);
+}
+
+pub fn extract_streaming_triangles(
+    mut commands: Commands,
+    camera: ExtractCamera,
+    streaming_q: Query<(
+        Entity,
+        &StreamingTriangle,
+        &GlobalTransform,
+        &ComputedVisibility,
+    )>,
+) {
+    let streaming = streaming_q
+        .iter()
+        .filter(|(_, streaming, _, vis)| vis.is_visible && !streaming.vertices.is_empty())
+        .map(|(entity, streaming, tform, _)| {
+            let uniform = TriangleUniform::new(camera.relative(tform.compute_matrix()));
+            (
+                entity,
+                (
+                    streaming.clone(),
+                    uniform,
+                    TriangleFeatures(pipeline::TrianglePipelineKey::NONE),
+                ),
+            )
+        })
+        .collect_vec();
+    commands.insert_or_spawn_batch(streaming);
+}
+
+/// Writes each `StreamingTriangle` into its buffer, reallocating only when it grows.
+pub fn prepare_streaming_triangles(
+    mut commands: Commands,
+    mut buffers: ResMut<StreamingBuffers>,
+    device: Res<RenderDevice>,
+    queue: Res<RenderQueue>,
+    streaming_q: Query<(Entity, &StreamingTriangle)>,
+    #[cfg(debug_assertions)] mut last_uploaded: ResMut<LastUploadedTriangleData>,
+) {
+    buffers
+        .0
+        .retain(|entity, _| streaming_q.get(*entity).is_ok());
+    #[cfg(debug_assertions)]
+    last_uploaded.streaming.clear();
+    for (entity, streaming) in streaming_q.iter() {
+        let bytes: &[u8] = bevy::core::cast_slice(&streaming.vertices);
+        let (buffer, _) = buffers
+            .0
+            .entry(entity)
+            .and_modify(|(buffer, size)| {
+                if *size < bytes.len() as u64 {
+                    (*buffer, *size) = create_streaming_buffer(&device, bytes.len());
+                }
+            })
+            .or_insert_with(|| create_streaming_buffer(&device, bytes.len()));
+        queue.write_buffer(buffer, 0, bytes);
+        #[cfg(debug_assertions)]
+        last_uploaded
+            .streaming
+            .insert(entity, streaming.vertices.clone());
+        commands.entity(entity).insert(draw::StreamingVertexBuffer {
+            buffer: buffer.clone(),
+            vertex_count: streaming.vertices.len() as u32,
+        });
+    }
+}
+
+/// Collects the visible `TriangleInstance`s of each visible `TriangleInstances` entity.
+pub fn extract_triangle_instances(
+    mut commands: Commands,
+    group_q: Query<(Entity, &GlobalTransform, &ComputedVisibility), With<TriangleInstances>>,
+    instance_q: Query<(
+        &TriangleInstance,
+        &GlobalTransform,
+        &ComputedVisibility,
+        Option<&TriangleInstanceUv>,
+    )>,
+) {
+    let mut groups: HashMap<Entity, (Mat4, Vec<TriangleInstanceData>)> = group_q
+        .iter()
+        .filter(|(_, _, vis)| vis.is_visible)
+        .map(|(entity, tform, _)| (entity, (tform.compute_matrix().inverse(), Vec::new())))
+        .collect();
+    for (instance, tform, vis, uv) in instance_q.iter() {
+        if !vis.is_visible {
+            continue;
+        }
+        if let Some((inverse, instances)) = groups.get_mut(&instance.0) {
+            let uv = uv.copied().unwrap_or_default();
+            instances.push(TriangleInstanceData {
+                transform: (*inverse * tform.compute_matrix()).to_cols_array_2d(),
+                uv: [uv.offset.x, uv.offset.y, uv.scale.x, uv.scale.y],
+            });
+        }
+    }
+    let groups = groups
+        .into_iter()
+        .map(|(entity, (_, instances))| (entity, (ExtractedTriangleInstances(instances),)))
+        .collect_vec();
+    commands.insert_or_spawn_batch(groups);
+}
+
+/// Writes each group's instances into a vertex buffer of their own, recreated every frame
+/// since they're extracted from scratch anyway.
+pub fn prepare_triangle_instances(
+    mut commands: Commands,
+    device: Res<RenderDevice>,
+    instances_q: Query<(Entity, &ExtractedTriangleInstances)>,
+    #[cfg(debug_assertions)] mut last_uploaded: ResMut<LastUploadedTriangleData>,
+) {
+    #[cfg(debug_assertions)]
+    last_uploaded.instances.clear();
+    for (entity, instances) in instances_q.iter() {
+        if instances.0.is_empty() {
+            continue;
+        }
+        #[cfg(debug_assertions)]
+        last_uploaded.instances.insert(entity, instances.0.clone());
+        let buffer = device.create_buffer_with_data(&wgpu::BufferInitDescriptor {
+            label: Some("triangle instance buffer"),
+            contents: bevy::core::cast_slice(&instances.0),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        commands
+            .entity(entity)
+            .insert(draw::TriangleInstanceBuffer {
+                buffer,
+                instance_count: instances.0.len() as u32,
+            });
+    }
+}
+
+/// Copies the meshes of visible `TriangleBatched` triangles into `TriangleBatch`es of at
+/// most `TriangleRenderConfig::max_batch_vertices`, filled in the order they're drawn.
+#[allow(clippy::type_complexity)]
+pub fn extract_triangle_batches(
+    mut commands: Commands,
+    config: Res<TriangleRenderConfig>,
+    camera: ExtractCamera,
+    meshes: Res<Assets<Mesh>>,
+    batched_q: Query<
+        (
+            Entity,
+            &TriangleMeshHandle,
+            &GlobalTransform,
+            &ComputedVisibility,
+            Option<&TriangleOrder>,
+        ),
+        With<TriangleBatched>,
+    >,
+) {
+    let max_vertices = (config.max_batch_vertices as usize / 3 * 3).max(3);
+    let mut triangles = batched_q
+        .iter()
+        .filter(|(_, _, _, vis, _)| vis.is_visible)
+        .filter_map(|(entity, mesh_handle, tform, _, order)| {
+            let transform = camera.relative(tform.compute_matrix());
+            let vertices = batch_vertices(meshes.get(&mesh_handle.0)?, transform)?;
+            let sort_z = TriangleOrder::sort_z(order, transform.w_axis.z);
+            Some((entity, sort_z, vertices))
+        })
+        .collect_vec();
+    triangles.sort_by_key(|(_, sort_z, _)| FloatOrd(*sort_z));
+    let mut batches = vec![TriangleBatch::default()];
+    for (entity, sort_z, vertices) in triangles {
+        let mut vertices = &vertices[..];
+        while !vertices.is_empty() {
+            if batches.last().unwrap().vertices.len() == max_vertices {
+                batches.push(TriangleBatch::default());
+            }
+            let batch = batches.last_mut().unwrap();
+            let room = max_vertices - batch.vertices.len();
+            let (piece, rest) = vertices.split_at(room.min(vertices.len()));
+            let start = batch.vertices.len() as u32;
+            batch.vertices.extend_from_slice(piece);
+            batch.triangles.push(BatchedTriangle {
+                entity,
+                sort_z,
+                range: start..batch.vertices.len() as u32,
+            });
+            vertices = rest;
+        }
+    }
+    // The vertices are already moved, camera-relative ones included.
+    let uniform = TriangleUniform::new(Mat4::IDENTITY);
+    commands.spawn_batch(
+        batches
+            .into_iter()
+            .filter(|batch| !batch.vertices.is_empty())
+            .map(move |batch| (batch, uniform.clone()))
+            .collect_vec(),
+    );
+}
+
+/// The whole triangles of `mesh` as a plain list of vertices moved by `transform`. `None`
+/// unless it's a triangle list with positions and colors.
+fn batch_vertices(mesh: &Mesh, transform: Mat4) -> Option<Vec<StreamingVertex>> {
+    if mesh.primitive_topology() != wgpu::PrimitiveTopology::TriangleList {
+        return None;
+    }
+    let positions = match mesh.attribute(Mesh::ATTRIBUTE_POSITION)? {
+        VertexAttributeValues::Float32x3(positions) => positions,
+        _ => return None,
+    };
+    let colors = match mesh.attribute(Mesh::ATTRIBUTE_COLOR)? {
+        VertexAttributeValues::Float32x4(colors) => colors,
+        _ => return None,
+    };
+    let uvs = match mesh.attribute(Mesh::ATTRIBUTE_UV_0) {
+        Some(VertexAttributeValues::Float32x2(uvs)) => Some(uvs),
+        _ => None,
+    };
+    let vertex = |i: usize| {
+        Some(StreamingVertex {
+            color: *colors.get(i)?,
+            position: transform
+                .transform_point3(Vec3::from(*positions.get(i)?))
+                .into(),
+            uv: uvs.and_then(|uvs| uvs.get(i).copied()).unwrap_or_default(),
+        })
+    };
+    let mut vertices = match mesh.indices() {
+        Some(Indices::U16(indices)) => indices.iter().map(|&i| vertex(i as usize)).collect(),
+        Some(Indices::U32(indices)) => indices.iter().map(|&i| vertex(i as usize)).collect(),
+        None => (0..positions.len()).map(vertex).collect::<Option<Vec<_>>>(),
+    }?;
+    vertices.truncate(vertices.len() / 3 * 3);
+    Some(vertices)
+}
+
+/// Uploads each `TriangleBatch` into a vertex buffer of its own. Batches are rebuilt every
+/// frame, so the buffers are too.
+pub fn prepare_triangle_batches(
+    mut commands: Commands,
+    device: Res<RenderDevice>,
+    batch_q: Query<(Entity, &TriangleBatch)>,
+    #[cfg(debug_assertions)] mut last_uploaded: ResMut<LastUploadedTriangleData>,
+) {
+    #[cfg(debug_assertions)]
+    last_uploaded.batches.clear();
+    for (entity, batch) in batch_q.iter() {
+        #[cfg(debug_assertions)]
+        last_uploaded.batches.push(batch.clone());
+        let buffer = device.create_buffer_with_data(&wgpu::BufferInitDescriptor {
+            label: Some("triangle batch vertex buffer"),
+            contents: bevy::core::cast_slice(&batch.vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        commands
+            .entity(entity)
+            .insert(draw::TriangleBatchBuffer(buffer));
+    }
+}
+
+/// A buffer for at least `len` bytes, and its actual size.
+fn create_streaming_buffer(device: &RenderDevice, len: usize) -> (Buffer, u64) {
+    let size = len.next_power_of_two() as u64;
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("streaming triangle vertex buffer"),
+        size,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    (buffer, size)
+}
+
+#[allow(clippy::type_complexity)]
+pub fn extract_triangle_textures(
+    mut commands: Commands,
+    texture_q: Query<(
+        Entity,
+        &TriangleTexture,
+        Option<&TextureAlphaMode>,
+        Option<&TriangleSamplerConfig>,
+        &ComputedVisibility,
+    )>,
+) {
+    let mut textures = Vec::new();
+    let mut samplers = Vec::new();
+    for (entity, texture, alpha_mode, sampler, vis) in texture_q.iter() {
+        if !vis.is_visible {
+            continue;
+        }
+        let alpha_mode = alpha_mode.copied().unwrap_or_default();
+        textures.push((entity, (texture.clone_weak(), alpha_mode)));
+        if let Some(sampler) = sampler {
+            samplers.push((entity, (*sampler,)));
+        }
+    }
+    commands.insert_or_spawn_batch(textures);
+    commands.insert_or_spawn_batch(samplers);
+}
+
+pub fn extract_triangle_scissors(
+    mut commands: Commands,
+    scissor_q: Query<(Entity, &TriangleScissor, &ComputedVisibility)>,
+) {
+    let scissors = scissor_q
+        .iter()
+        .filter(|(_, _, vis)| vis.is_visible)
+        .map(|(entity, scissor, _)| (entity, (*scissor,)))
+        .collect_vec();
+    commands.insert_or_spawn_batch(scissors);
+}
+
+pub fn extract_triangle_orders(
+    mut commands: Commands,
+    order_q: Query<(Entity, &TriangleOrder, &ComputedVisibility)>,
+) {
+    let orders = order_q
+        .iter()
+        .filter(|(_, _, vis)| vis.is_visible)
+        .map(|(entity, order, _)| (entity, (*order,)))
+        .collect_vec();
+    commands.insert_or_spawn_batch(orders);
+}
+
+pub fn extract_awaiting_ready(
+    mut commands: Commands,
+    awaiting_q: Query<(Entity, &TriangleMeshHandle), Without<TriangleReady>>,
+) {
+    let awaiting = awaiting_q
+        .iter()
+        .map(|(entity, handle)| (entity, (AwaitingReady(handle.0.clone_weak()),)))
+        .collect_vec();
+    commands.insert_or_spawn_batch(awaiting);
+}
+
+pub fn extract_globals(
+    mut commands: Commands,
+    time: Res<Time>,
+    tint: Res<TriangleGlobalTint>,
+    alpha_clamp: Res<AlphaClamp>,
+    fog: Res<Fog>,
+    camera: ExtractCamera,
+) {
+    commands.insert_resource(TriangleGlobals {
+        time: time.seconds_since_startup() as f32,
+        delta: time.delta_seconds(),
+        tint: Vec4::from(tint.0),
+        relative_view_proj: camera.relative_view_proj(),
+        alpha_clamp: Vec2::new(alpha_clamp.min, alpha_clamp.max),
+        fog_color: Vec4::from(fog.color),
+        fog_plane: camera.depth_plane(),
+        fog_range: Vec2::new(fog.start, fog.end),
+    });
+}
+
+pub fn prepare_globals(
+    globals: Res<TriangleGlobals>,
+    mut buffer: ResMut<GlobalsBuffer>,
+    device: Res<RenderDevice>,
+    queue: Res<RenderQueue>,
+    #[cfg(debug_assertions)] mut last_uploaded: ResMut<LastUploadedTriangleData>,
+) {
+    buffer.0.clear();
+    buffer.0.push(*globals);
+    buffer.0.write_buffer(&device, &queue);
+    #[cfg(debug_assertions)]
+    {
+        last_uploaded.globals = Some(*globals);
+    }
+}
+
+/// Copies the pipeline's shader into the render world as `ExtractedTriangleShader` when
+/// `Assets<Shader>` changes, so `check_shader_uniform_sizes` sees edits to it.
+#[cfg(debug_assertions)]
+pub fn extract_triangle_shader(
+    mut commands: Commands,
+    config: Option<Res<TriangleRenderConfig>>,
+    shaders: Res<Assets<Shader>>,
+) {
+    if !shaders.is_changed() {
+        return;
+    }
+    let config = config.map(|config| config.clone()).unwrap_or_default();
+    let handle = config
+        .descriptor(pipeline::TrianglePipelineKey::NONE)
+        .vertex
+        .shader;
+    if let Some(shader) = shaders.get(handle) {
+        commands.insert_resource(ExtractedTriangleShader(shader.clone()));
+    }
+}
+
+/// Checks the uniform structs of the shader each newly specialized key compiles against
+/// the layout entries they're bound to, see `assert_shader_uniform_sizes`. Every key is
+/// checked again once the shader changes.
+#[cfg(debug_assertions)]
+pub fn check_shader_uniform_sizes(
+    pipeline: Res<TrianglePipeline>,
+    specializations: Res<pipeline::TrianglePipelineSpecializations>,
+    shader: Option<Res<ExtractedTriangleShader>>,
+    mut checked: Local<bevy::utils::HashSet<pipeline::TrianglePipelineKey>>,
+) {
+    let shader = match shader {
+        Some(shader) => shader,
+        None => return,
+    };
+    if shader.is_changed() {
+        checked.clear();
+    }
+    for key in specializations.keys() {
+        if checked.insert(key) {
+            let descriptor = pipeline.config.descriptor(key);
+            assert_shader_uniform_sizes(
+                &shader.0,
+                &descriptor.vertex.shader_defs,
+                &pipeline.uniform_bindings(key),
+            );
+        }
+    }
+}
+
+/// Panics if a uniform struct in `shader`, preprocessed with `shader_defs`, is larger than
+/// the layout entry in `bindings` it's bound to, naming the Rust type written there. wgpu
+/// rejects such a pipeline without saying which struct is stale. A smaller struct is fine,
+/// like the shader's `View`, which only reads the start of `ViewUniform`. Shaders that
+/// don't preprocess or parse on their own, like ones with imports, aren't checked and are
+/// left for the pipeline cache to report.
+pub fn assert_shader_uniform_sizes(
+    shader: &Shader,
+    shader_defs: &[String],
+    bindings: &[((u32, u32), &str, u64)],
+) {
+    let module = match ShaderProcessor::default()
+        .process(
+            shader,
+            shader_defs,
+            &HashMap::default(),
+            &HashMap::default(),
+        )
+        .ok()
+        .and_then(|processed| processed.reflect().ok())
+    {
+        Some(reflection) => reflection.module,
+        None => return,
+    };
+    for &((group, binding), type_name, size) in bindings {
+        if let Some(shader_size) = pipeline::uniform_size(&module, group, binding) {
+            assert!(
+                shader_size <= size,
+                "{} is {} bytes but the shader's uniform at group {} binding {} is {}; \
+                 keep the WGSL struct and its std140 layout in step",
+                type_name,
+                size,
+                group,
+                binding,
+                shader_size,
+            );
+        }
+    }
+}
+
+pub fn queue_view_bind_groups(
+    mut commands: Commands,
+    device: Res<RenderDevice>,
+    pipeline: Res<TrianglePipeline>,
+    view_uniforms: Res<ViewUniforms>,
+    globals: Res<GlobalsBuffer>,
+    views: Query<Entity, With<ExtractedView>>,
+) {
+    let (view_binding, globals_binding) =
+        match (view_uniforms.uniforms.binding(), globals.0.binding()) {
+            (Some(view), Some(globals)) => (view, globals),
+            _ => return,
+        };
+    for entity in views.iter() {
+        let view_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: view_binding.clone(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: globals_binding.clone(),
+                },
+            ],
+            label: Some("texture view bind group"),
+            layout: &pipeline.view_layout,
+        });
+
+        commands
+            .entity(entity)
+            .insert(draw::ViewBindGroup(view_bind_group));
+    }
+}
+
+/// Binds this frame's `TriangleUniform` buffer, with every combination of the effect
+/// uniform buffers, as the `MeshBindGroups` resource. With no triangles there's no buffer,
+/// and the resource is removed rather than left bound to a stale one.
+pub fn queue_mesh_bind_groups(
+    mut commands: Commands,
+    pipeline: Res<TrianglePipeline>,
+    render_device: Res<RenderDevice>,
+    mesh_uniforms: Res<ComponentUniforms<TriangleUniform>>,
+    edge_uniforms: Res<ComponentUniforms<TriangleEdgeUniform>>,
+    fill_uniforms: Res<ComponentUniforms<TriangleFillUniform>>,
+) {
+    let binding = match mesh_uniforms.uniforms().binding() {
+        Some(binding) => binding,
+        None => {
+            commands.remove_resource::<draw::MeshBindGroups>();
+            return;
+        }
+    };
+    let edge_binding = edge_uniforms.uniforms().binding();
+    let fill_binding = fill_uniforms.uniforms().binding();
+    let mut bind_groups = HashMap::default();
+    for bits in 0..=pipeline::MeshBindings::all().bits() {
+        let bindings = pipeline::MeshBindings::from_bits_truncate(bits);
+        // Combinations needing a buffer no triangle filled this frame aren't drawn.
+        let effects = [
+            (pipeline::MeshBindings::EDGE, 1, &edge_binding),
+            (pipeline::MeshBindings::FILL, 2, &fill_binding),
+        ]
+        .into_iter()
+        .filter(|(flag, ..)| bindings.contains(*flag))
+        .map(|(_, index, binding)| {
+            Some(wgpu::BindGroupEntry {
+                binding: index,
+                resource: binding.clone()?,
+            })
+        })
+        .collect::<Option<Vec<_>>>();
+        let entries = match effects {
+            Some(effects) => std::iter::once(wgpu::BindGroupEntry {
+                binding: 0,
+                resource: binding.clone(),
+            })
+            .chain(effects)
+            .collect_vec(),
+            None => continue,
+        };
+        let bind_group = render_device.create_bind_group(&wgpu::BindGroupDescriptor {
+            entries: &entries,
+            label: Some("body_mesh_bind_group"),
+            layout: pipeline.mesh_layout(bindings),
+        });
+        bind_groups.insert(bindings, bind_group);
+    }
+    commands.insert_resource(draw::MeshBindGroups(bind_groups));
+}
+
+pub fn extract_changed_images(
+    mut commands: Commands,
+    mut image_events: EventReader<AssetEvent<Image>>,
+) {
+    let changed = image_events
+        .iter()
+        .filter_map(|event| match event {
+            AssetEvent::Modified { handle } | AssetEvent::Removed { handle } => Some(handle.id),
+            AssetEvent::Created { .. } => None,
+        })
+        .collect_vec();
+    commands.insert_resource(ChangedImages(changed));
+}
+
+/// Textured triangles get a bind group for their image and their sampler override, or
+/// the plugin's sampler config. Triangles sharing both share the bind group, which is
+/// cached until the image changes.
+#[allow(clippy::too_many_arguments)]
+pub fn queue_texture_bind_groups(
+    mut commands: Commands,
+    pipeline: Res<TrianglePipeline>,
+    render_device: Res<RenderDevice>,
+    config: Res<TriangleRenderConfig>,
+    images: Res<RenderAssets<Image>>,
+    changed_images: Res<ChangedImages>,
+    mut cache: ResMut<TextureBindGroupCache>,
+    texture_q: Query<(Entity, &TriangleTexture, Option<&TriangleSamplerConfig>)>,
+) {
+    cache
+        .0
+        .retain(|(image, _), _| !changed_images.0.contains(image));
+    for (entity, texture, sampler_config) in texture_q.iter() {
+        let gpu_image = match images.get(&texture.0) {
+            Some(x) => x,
+            None => continue,
+        };
+        let sampler_config = *sampler_config.unwrap_or(&config.sampler);
+        let key = (texture.0.id, sampler_config);
+        if let Some(bind_group) = cache.0.get(&key) {
+            commands
+                .entity(entity)
+                .insert(draw::TextureBindGroup(bind_group.clone()));
+            continue;
+        }
+        let sampler = render_device.create_sampler(&sampler_config.descriptor());
+        let bind_group = render_device.create_bind_group(&wgpu::BindGroupDescriptor {
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&gpu_image.texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+            label: Some("triangle texture bind group"),
+            layout: &pipeline.texture_layout,
+        });
+        cache.0.insert(key, bind_group.clone());
+        commands
+            .entity(entity)
+            .insert(draw::TextureBindGroup(bind_group));
+    }
+}
+
+/// Marks the views of `TriangleHdr` cameras.
+pub fn extract_hdr_views(
+    mut commands: Commands,
+    camera_q: Query<Entity, (With<Camera>, With<TriangleHdr>)>,
+) {
+    let views = camera_q
+        .iter()
+        .map(|entity| (entity, (TriangleHdr,)))
+        .collect_vec();
+    commands.insert_or_spawn_batch(views);
+}
+
+/// The key bits that follow from the view rather than the triangle.
+fn view_key(hdr: Option<&TriangleHdr>) -> pipeline::TrianglePipelineKey {
+    match hdr {
+        Some(_) => pipeline::TrianglePipelineKey::HDR,
+        None => pipeline::TrianglePipelineKey::NONE,
+    }
+}
+
+/// Reports triangles whose meshes made it through `RenderStage::Prepare`.
+pub fn queue_ready_triangles(
+    prepared: Res<PreparedTriangles>,
+    render_meshes: Res<RenderAssets<Mesh>>,
+    awaiting_q: Query<(Entity, &AwaitingReady)>,
+) {
+    let ready = awaiting_q
+        .iter()
+        .filter(|(_, awaiting)| render_meshes.contains_key(&awaiting.0))
+        .map(|(entity, _)| entity);
+    prepared.0.lock().unwrap().extend(ready);
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn queue_triangles(
+    mut view_q: Query<(
+        &VisibleEntities,
+        &mut RenderPhase<Transparent2d>,
+        Option<&TriangleHdr>,
+    )>,
+    draw_functions: Res<DrawFunctions<Transparent2d>>,
+    msaa: Res<Msaa>,
+    mut pipelines: ResMut<SpecializedPipelines<TrianglePipeline>>,
+    mut specializations: ResMut<pipeline::TrianglePipelineSpecializations>,
+    mut pipeline_cache: ResMut<RenderPipelineCache>,
+    pipeline: Res<TrianglePipeline>,
+    render_meshes: Res<RenderAssets<Mesh>>,
+    images: Res<RenderAssets<Image>>,
+    mesh_q: Query<(
+        Entity,
+        &TriangleUniform,
+        &TriangleMeshHandle,
+        &TriangleFeatures,
+        Option<(&TriangleTexture, &TextureAlphaMode)>,
+        Option<&HaloSource>,
+        Option<&ExtractedTriangleInstances>,
+    )>,
+    order_q: Query<&TriangleOrder>,
+) {
+    let draw_function = draw_functions
+        .read()
+        .get_id::<draw::DrawTriangle>()
+        .unwrap();
+    let msaa_key = pipeline::TrianglePipelineKey::from_msaa_samples(msaa.samples);
+    view_q.iter_mut().for_each(|(visible, mut phase, hdr)| {
+        let view_key = msaa_key | view_key(hdr);
+        for (entity, uniform, mesh_handle, features, texture, halo, instances) in mesh_q.iter() {
+            let visible_entity = halo.map_or(entity, |halo| halo.entity);
+            if !visible.entities.contains(&visible_entity) {
+                continue;
+            }
+            let gpu_mesh = match render_meshes.get(&mesh_handle.0) {
+                Some(x) => x,
+                None => continue,
+            };
+            let index_format = match &gpu_mesh.buffer_info {
+                GpuBufferInfo::Indexed { index_format, .. } => Some(*index_format),
+                GpuBufferInfo::NonIndexed { .. } => None,
+            };
+            let mut key = view_key
+                | features.0
+                | pipeline::TrianglePipelineKey::from_primitive_topology(
+                    gpu_mesh.primitive_topology,
+                )
+                | pipeline::TrianglePipelineKey::from_strip_index_format(
+                    gpu_mesh.primitive_topology,
+                    index_format,
+                );
+            if gpu_mesh.has_tangents {
+                key |= pipeline::TrianglePipelineKey::VERTEX_TANGENTS;
+            }
+            if let Some(instances) = instances {
+                if instances.0.is_empty() {
+                    continue;
+                }
+                key |= pipeline::TrianglePipelineKey::INSTANCED;
+            }
+            if let Some((texture, alpha_mode)) = texture {
+                // Wait for the image rather than flashing the untextured color.
+                if images.get(&texture.0).is_none() {
+                    continue;
+                }
+                key |= pipeline::TrianglePipelineKey::TEXTURED;
+                key |= match alpha_mode {
+                    TextureAlphaMode::Straight => pipeline::TrianglePipelineKey::NONE,
+                    TextureAlphaMode::Premultiplied => {
+                        pipeline::TrianglePipelineKey::TEXTURE_PREMULTIPLIED
+                    }
+                    TextureAlphaMode::Opaque => pipeline::TrianglePipelineKey::TEXTURE_OPAQUE,
+                };
+            }
+            let pipeline_id =
+                specializations.specialize(&mut pipelines, &mut pipeline_cache, &pipeline, key);
+            // Leave the item out until its pipeline has compiled instead of queueing a
+            // draw that `SetItemPipeline` would fail.
+            if pipeline_cache.get(pipeline_id).is_none() {
+                continue;
+            }
+            // Glows and shadows are layered with the triangle they belong to, and sort
+            // from its z: a glow's own transform is scaled about the incenter, which
+            // moves its z when the triangle is turned out of the xy plane.
+            let order = order_q.get(visible_entity).ok();
+            let z = match halo.and_then(|halo| mesh_q.get(halo.entity).ok()) {
+                Some((_, source, ..)) => source.translation().z,
+                None => uniform.translation().z,
+            };
+            let mut mesh_z = TriangleOrder::sort_z(order, z);
+            if let Some(halo) = halo {
+                // Just behind the triangle it belongs to.
+                mesh_z -= halo.depth * mesh_z.abs().max(1.0) * f32::EPSILON;
+            }
+            phase.add(Transparent2d {
+                entity,
+                draw_function,
+                pipeline: pipeline_id,
+                sort_key: FloatOrd(mesh_z),
+                batch_range: None,
+            });
+        }
+    });
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn queue_streaming_triangles(
+    mut view_q: Query<(
+        &VisibleEntities,
+        &mut RenderPhase<Transparent2d>,
+        Option<&TriangleHdr>,
+    )>,
+    draw_functions: Res<DrawFunctions<Transparent2d>>,
+    msaa: Res<Msaa>,
+    mut pipelines: ResMut<SpecializedPipelines<TrianglePipeline>>,
+    mut specializations: ResMut<pipeline::TrianglePipelineSpecializations>,
+    mut pipeline_cache: ResMut<RenderPipelineCache>,
+    pipeline: Res<TrianglePipeline>,
+    streaming_q: Query<
+        (
+            Entity,
+            &TriangleUniform,
+            &TriangleFeatures,
+            Option<&TriangleOrder>,
+        ),
+        With<StreamingTriangle>,
+    >,
+) {
+    let draw_function = draw_functions
+        .read()
+        .get_id::<draw::DrawStreamingTriangle>()
+        .unwrap();
+    let msaa_key = pipeline::TrianglePipelineKey::from_msaa_samples(msaa.samples);
+    view_q.iter_mut().for_each(|(visible, mut phase, hdr)| {
+        let view_key = msaa_key | view_key(hdr);
+        for (entity, uniform, features, order) in streaming_q.iter() {
+            if !visible.entities.contains(&entity) {
+                continue;
+            }
+            let key = view_key | features.0;
+            let pipeline_id =
+                specializations.specialize(&mut pipelines, &mut pipeline_cache, &pipeline, key);
+            if pipeline_cache.get(pipeline_id).is_none() {
+                continue;
+            }
+            phase.add(Transparent2d {
+                entity,
+                draw_function,
+                pipeline: pipeline_id,
+                sort_key: FloatOrd(TriangleOrder::sort_z(order, uniform.translation().z)),
+                batch_range: None,
+            });
+        }
+    });
+}
+
+/// Queues a `Transparent2d` item per visible triangle in each `TriangleBatch`, with its range
+/// of the batch as `batch_range`. Bevy's `batch_phase_system` merges the ones left next to
+/// each other in the same batch after sorting into one draw.
+#[allow(clippy::too_many_arguments)]
+pub fn queue_triangle_batches(
+    mut view_q: Query<(
+        &VisibleEntities,
+        &mut RenderPhase<Transparent2d>,
+        Option<&TriangleHdr>,
+    )>,
+    draw_functions: Res<DrawFunctions<Transparent2d>>,
+    msaa: Res<Msaa>,
+    mut pipelines: ResMut<SpecializedPipelines<TrianglePipeline>>,
+    mut specializations: ResMut<pipeline::TrianglePipelineSpecializations>,
+    mut pipeline_cache: ResMut<RenderPipelineCache>,
+    pipeline: Res<TrianglePipeline>,
+    batch_q: Query<(Entity, &TriangleBatch)>,
+) {
+    let draw_function = draw_functions
+        .read()
+        .get_id::<draw::DrawBatchedTriangles>()
+        .unwrap();
+    let msaa_key = pipeline::TrianglePipelineKey::from_msaa_samples(msaa.samples);
+    view_q.iter_mut().for_each(|(visible, mut phase, hdr)| {
+        let key = msaa_key | view_key(hdr);
+        let pipeline_id =
+            specializations.specialize(&mut pipelines, &mut pipeline_cache, &pipeline, key);
+        if pipeline_cache.get(pipeline_id).is_none() {
+            return;
+        }
+        for (entity, batch) in batch_q.iter() {
+            for triangle in &batch.triangles {
+                if !visible.entities.contains(&triangle.entity) {
+                    continue;
+                }
+                phase.add(Transparent2d {
+                    entity,
+                    draw_function,
+                    pipeline: pipeline_id,
+                    sort_key: FloatOrd(triangle.sort_z),
+                    batch_range: Some(triangle.range.clone()),
+                });
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::CommandQueue;
+    use bevy::render::primitives::Frustum;
+    use bevy::render::{RenderApp, RenderStage};
+    use bevy::sprite::SpritePlugin;
+
+    use crate::render::plugin::TriangleRenderPlugin;
+    use crate::render::testing;
+    use crate::{MeshDirty, TriangleBundle, TriangleEdgeColor, TriangleGroupEffect};
+
+    #[test]
+    fn extract_uses_the_propagated_transform() {
+        let mut app = testing::headless_app(TriangleRenderConfig::default());
+        let mut render_world = World::new();
+        let parent = app
+            .world
+            .spawn()
+            .insert_bundle((Transform::default(), GlobalTransform::default()))
+            .id();
+        let child = app
+            .world
+            .spawn()
+            .insert_bundle(TriangleBundle::at(
+                Triangle::side(10.0),
+                Vec3::new(10.0, 0.0, 1.0),
+            ))
+            .id();
+        app.world.entity_mut(parent).push_children(&[child]);
+
+        for step in 0..4 {
+            let parent_transform = Transform::from_xyz(100.0, 50.0, 0.0)
+                .with_rotation(Quat::from_rotation_z(step as f32 * 0.7));
+            *app.world.get_mut::<Transform>(parent).unwrap() = parent_transform;
+            app.update();
+            testing::extract(&mut app.world, &mut render_world, extract_triangle_meshes);
+
+            let expected = parent_transform.compute_matrix()
+                * Mat4::from_translation(Vec3::new(10.0, 0.0, 1.0));
+            let uniform = render_world.get::<TriangleUniform>(child).unwrap();
+            assert!(
+                uniform.transform.abs_diff_eq(expected, 1e-4),
+                "step {}: {:?} != {:?}",
+                step,
+                uniform.transform,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn zooming_far_out_clamps_the_scale_to_the_min_pixel_size() {
+        let mut app = testing::headless_app(TriangleRenderConfig::default());
+        let zoomed = |zoom: f32| {
+            let half = testing::SIZE as f32 / 2.0 * zoom;
+            Mat4::orthographic_rh(-half, half, -half, half, 0.0, 1000.0)
+        };
+        let camera =
+            testing::windowed_camera(&mut app, zoomed(1.0), Transform::from_xyz(0.0, 0.0, 999.9));
+        let triangle = app
+            .world
+            .spawn()
+            .insert_bundle(TriangleBundle::new(Triangle::side(10.0)))
+            .insert(MinPixelSize(4.0))
+            .id();
+
+        // 10 units across is 10 pixels at first, and 4 pixels at a zoom of 2.5.
+        for (zoom, scale) in [(1.0, 1.0), (2.5, 1.0), (100.0, 40.0), (1000.0, 400.0)] {
+            app.world
+                .get_mut::<Camera>(camera)
+                .unwrap()
+                .projection_matrix = zoomed(zoom);
+            app.update();
+            let mut render_world = World::new();
+            testing::extract(&mut app.world, &mut render_world, extract_triangle_meshes);
+            let uniform = render_world.get::<TriangleUniform>(triangle).unwrap();
+            let expected = Mat4::from_scale(Vec3::new(scale, scale, 1.0));
+            assert!(
+                uniform.transform.abs_diff_eq(expected, 1e-2),
+                "zoom {}: {:?} != {:?}",
+                zoom,
+                uniform.transform,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn camera_relative_uniforms_stay_near_the_origin() {
+        let mut app = testing::headless_app(TriangleRenderConfig {
+            camera_relative: true,
+            ..Default::default()
+        });
+        testing::windowed_camera(
+            &mut app,
+            Mat4::orthographic_rh(-32.0, 32.0, -32.0, 32.0, 0.0, 1000.0),
+            Transform::from_xyz(1e6, 1e6, 999.9),
+        );
+        let triangle = app
+            .world
+            .spawn()
+            .insert_bundle(TriangleBundle::at(
+                Triangle::side(10.0),
+                Vec3::new(1e6 + 3.0, 1e6 - 2.0, 1.0),
+            ))
+            .id();
+        app.update();
+        let mut render_world = World::new();
+        testing::extract(&mut app.world, &mut render_world, extract_triangle_meshes);
+
+        // `z` is kept for sorting against other 2d items.
+        let uniform = render_world.get::<TriangleUniform>(triangle).unwrap();
+        assert_eq!(uniform.transform.w_axis, Vec4::new(3.0, -2.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn billboards_face_a_rotated_camera() {
+        let mut app = testing::headless_app(TriangleRenderConfig::default());
+        let camera = Transform::from_xyz(0.0, 0.0, 100.0)
+            .with_rotation(Quat::from_rotation_y(0.5) * Quat::from_rotation_z(0.3));
+        testing::windowed_camera(
+            &mut app,
+            Mat4::perspective_rh(1.0, 1.0, 0.1, 1000.0),
+            camera,
+        );
+        let tform = Transform::from_xyz(5.0, 0.0, 0.0)
+            .with_rotation(Quat::from_rotation_x(1.0))
+            .with_scale(Vec3::splat(2.0));
+        let [billboard, plain] = [true, false].map(|billboard| {
+            let mut entity = app.world.spawn();
+            entity.insert_bundle(TriangleBundle::with_transform(Triangle::side(10.0), tform));
+            if billboard {
+                entity.insert(Billboard);
+            }
+            entity.id()
+        });
+        app.update();
+        let mut render_world = World::new();
+        testing::extract(&mut app.world, &mut render_world, extract_triangle_meshes);
+
+        // In view space the billboard lies in the camera plane, only scaled and moved.
+        let in_view = |entity| {
+            camera.compute_matrix().inverse()
+                * render_world
+                    .get::<TriangleUniform>(entity)
+                    .unwrap()
+                    .transform
+        };
+        let (scale, rotation, translation) = in_view(billboard).to_scale_rotation_translation();
+        assert!(scale.abs_diff_eq(Vec3::splat(2.0), 1e-4), "{:?}", scale);
+        assert!(rotation.abs_diff_eq(Quat::IDENTITY, 1e-4), "{:?}", rotation);
+        let expected = camera
+            .compute_matrix()
+            .inverse()
+            .transform_point3(tform.translation);
+        assert!(translation.abs_diff_eq(expected, 1e-3), "{:?}", translation);
+        // Without the marker the triangle keeps its own rotation.
+        let (_, rotation, _) = in_view(plain).to_scale_rotation_translation();
+        assert!(!rotation.abs_diff_eq(Quat::IDENTITY, 1e-2));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "render::TriangleUniform is 160 bytes but the shader's uniform at group 1 binding 0 is 176"
+    )]
+    fn a_stale_shader_uniform_names_its_type() {
+        let config = TriangleRenderConfig::default();
+        let key = pipeline::TrianglePipelineKey::from_msaa_samples(1)
+            | pipeline::TrianglePipelineKey::EDGE_COLOR;
+        let shader_defs = config.descriptor(key).vertex.shader_defs;
+        let bindings = [
+            (
+                (1, 0),
+                std::any::type_name::<TriangleUniform>(),
+                TriangleUniform::std140_size_static() as u64,
+            ),
+            (
+                (1, 1),
+                std::any::type_name::<TriangleEdgeUniform>(),
+                TriangleEdgeUniform::std140_size_static() as u64,
+            ),
+        ];
+        // The built-in shader passes, and one whose `Mesh` grew a field doesn't.
+        let source = include_str!("triangle.wgsl");
+        assert_shader_uniform_sizes(&Shader::from_wgsl(source), &shader_defs, &bindings);
+        let stale = source.replacen(
+            "    uv_scroll: vec2<f32>;\n};",
+            "    uv_scroll: vec2<f32>;\n    wobble: vec4<f32>;\n};",
+            1,
+        );
+        assert_ne!(stale, source);
+        assert_shader_uniform_sizes(&Shader::from_wgsl(stale), &shader_defs, &bindings);
+    }
+
+    #[test]
+    fn reused_extract_buffers_leave_out_despawned_triangles() {
+        let mut app = testing::headless_app(TriangleRenderConfig::default());
+        let triangles = (0..3)
+            .map(|i| {
+                app.world
+                    .spawn()
+                    .insert_bundle(TriangleBundle::at(
+                        Triangle::side(10.0),
+                        Vec3::new(i as f32 * 20.0, 0.0, 0.0),
+                    ))
+                    .insert(TriangleEdgeColor {
+                        color: [1.0; 4],
+                        thickness: 1.0,
+                    })
+                    .insert(TriangleAlphaCutoff(0.5))
+                    .id()
+            })
+            .collect_vec();
+        // One stage throughout, so the system keeps its buffers.
+        let mut stage = SystemStage::single_threaded().with_system(extract_triangle_meshes);
+        stage.set_apply_buffers(false);
+        let mut extract = |main_world: &mut World| {
+            // Like the render world, which is cleared every frame.
+            let mut render_world = World::new();
+            stage.run(main_world);
+            stage.apply_buffers(&mut render_world);
+            // Each of the three buffers fills in its own component.
+            let mut extracted = render_world.query_filtered::<Entity, (
+                With<TriangleUniform>,
+                With<TriangleEdgeUniform>,
+                With<TriangleFillUniform>,
+            )>();
+            let mut extracted = extracted.iter(&render_world).collect_vec();
+            extracted.sort();
+            (extracted, render_world.entities().len())
+        };
+
+        app.update();
+        assert_eq!(extract(&mut app.world), (triangles.clone(), 3));
+
+        app.world.despawn(triangles[1]);
+        app.update();
+        assert_eq!(
+            extract(&mut app.world),
+            (vec![triangles[0], triangles[2]], 2)
+        );
+        app.update();
+        assert_eq!(
+            extract(&mut app.world),
+            (vec![triangles[0], triangles[2]], 2)
+        );
+    }
+
+    #[test]
+    fn group_effects_reach_every_member() {
+        let mut app = testing::headless_app(TriangleRenderConfig::default());
+        let mut render_world = World::new();
+        let mut spawn = |group: Option<u32>, opacity: f32| {
+            let mut triangle = app.world.spawn();
+            triangle
+                .insert_bundle(TriangleBundle::new(Triangle::side(10.0)))
+                .insert(TriangleOpacity(opacity));
+            if let Some(group) = group {
+                triangle.insert(TriangleGroup(group));
+            }
+            triangle.id()
+        };
+        let members = [spawn(Some(1), 1.0), spawn(Some(1), 0.5)];
+        let other_group = spawn(Some(2), 1.0);
+        let ungrouped = spawn(None, 1.0);
+        *app.world
+            .get_resource_mut::<TriangleGroupEffects>()
+            .unwrap()
+            .get_mut(1) = TriangleGroupEffect {
+            tint: [1.0, 0.5, 0.25, 1.0],
+            opacity: 0.4,
+        };
+
+        app.update();
+        testing::extract(&mut app.world, &mut render_world, extract_triangle_meshes);
+
+        let uniform = |entity| render_world.get::<TriangleUniform>(entity).unwrap();
+        for (member, opacity) in members.into_iter().zip([0.4, 0.2]) {
+            let uniform = uniform(member);
+            assert!(
+                (uniform.opacity - opacity).abs() < 1e-6,
+                "{}",
+                uniform.opacity
+            );
+            assert_eq!(uniform.tint, Vec4::new(1.0, 0.5, 0.25, 1.0));
+        }
+        for entity in [other_group, ungrouped] {
+            let uniform = uniform(entity);
+            assert_eq!(uniform.opacity, 1.0);
+            assert_eq!(uniform.tint, Vec4::ONE);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn draws_resume_after_frames_without_triangles() {
+        let mut app = testing::gpu_app(TriangleRenderPlugin::default());
+        let drawn = |app: &mut App| {
+            testing::pixel_at(&testing::render(app), Vec2::ZERO) == [255, 0, 0, 255]
+        };
+        assert!(!drawn(&mut app));
+
+        let entity = app
+            .world
+            .spawn()
+            .insert_bundle(TriangleBundle::new(
+                Triangle::side(20.0).with_rgba([1.0, 0.0, 0.0, 1.0]),
+            ))
+            .id();
+        assert!(drawn(&mut app));
+
+        // An empty mesh leaves nothing to draw, then the triangle's own comes back.
+        let handle = app
+            .world
+            .get::<TriangleMeshHandle>(entity)
+            .unwrap()
+            .0
+            .clone();
+        let mut empty = Mesh::new(wgpu::PrimitiveTopology::TriangleList);
+        empty.set_attribute(Mesh::ATTRIBUTE_POSITION, Vec::<[f32; 3]>::new());
+        empty.set_attribute(Mesh::ATTRIBUTE_COLOR, Vec::<[f32; 4]>::new());
+        app.world
+            .get_resource_mut::<Assets<Mesh>>()
+            .unwrap()
+            .set_untracked(handle, empty);
+        assert!(!drawn(&mut app));
+        app.world.entity_mut(entity).insert(MeshDirty);
+        assert!(drawn(&mut app));
+
+        app.world.despawn(entity);
+        assert!(!drawn(&mut app));
+        app.world.spawn().insert_bundle(TriangleBundle::new(
+            Triangle::side(20.0).with_rgba([1.0, 0.0, 0.0, 1.0]),
+        ));
+        assert!(drawn(&mut app));
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn triangles_and_sprites_interleave_by_z() {
+        let mut app = testing::gpu_app(TriangleRenderPlugin::default());
+        app.add_plugin(SpritePlugin);
+        for z in [1.0, 3.0] {
+            app.world.spawn().insert_bundle(SpriteBundle {
+                sprite: Sprite {
+                    custom_size: Some(Vec2::splat(10.0)),
+                    ..Default::default()
+                },
+                transform: Transform::from_xyz(0.0, 0.0, z),
+                ..Default::default()
+            });
+        }
+        for z in [2.0, 4.0] {
+            app.world.spawn().insert_bundle(TriangleBundle::at(
+                Triangle::side(10.0),
+                Vec3::new(0.0, 0.0, z),
+            ));
+        }
+        // The phase is gone once the frame ends, so note it down after sorting.
+        let sorted = Arc::new(Mutex::new(Vec::new()));
+        let record = sorted.clone();
+        app.sub_app_mut(RenderApp).add_system_to_stage(
+            RenderStage::Render,
+            move |draw_functions: Res<DrawFunctions<Transparent2d>>,
+                  phase_q: Query<&RenderPhase<Transparent2d>>| {
+                let triangle = draw_functions.read().get_id::<draw::DrawTriangle>();
+                *record.lock().unwrap() = phase_q
+                    .iter()
+                    .flat_map(|phase| &phase.items)
+                    .map(|item| (Some(item.draw_function) == triangle, item.sort_key.0))
+                    .collect_vec();
+            },
+        );
+
+        testing::render(&mut app);
+        assert_eq!(
+            *sorted.lock().unwrap(),
+            [(false, 1.0), (true, 2.0), (false, 3.0), (true, 4.0)]
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn orders_draw_in_order_regardless_of_z() {
+        // No z reaches past the next order, not even order 0's.
+        let sort_z = |order: i32, z: f32| TriangleOrder::sort_z(Some(&TriangleOrder(order)), z);
+        assert!(TriangleOrder::sort_z(None, 1e6) < sort_z(1, -1e6));
+        assert!(sort_z(1, 1e6) < sort_z(2, -1e6));
+
+        let mut app = testing::gpu_app(TriangleRenderPlugin::default());
+        let mut spawn = |rgba, order, z| {
+            app.world
+                .spawn()
+                .insert_bundle(TriangleBundle::at(
+                    Triangle::side(20.0).with_rgba(rgba),
+                    Vec3::new(0.0, 0.0, z),
+                ))
+                .insert(TriangleOrder(order))
+                .id()
+        };
+        let red = spawn([1.0, 0.0, 0.0, 1.0], 2, 1.0);
+        let blue = spawn([0.0, 0.0, 1.0, 1.0], 1, 500.0);
+        let pixels = testing::render(&mut app);
+        assert_eq!(testing::pixel_at(&pixels, Vec2::ZERO), [255, 0, 0, 255]);
+
+        app.world.entity_mut(red).insert(TriangleOrder(1));
+        app.world.entity_mut(blue).insert(TriangleOrder(2));
+        let pixels = testing::render(&mut app);
+        assert_eq!(testing::pixel_at(&pixels, Vec2::ZERO), [0, 0, 255, 255]);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn grid_instances_draw_where_they_are() {
+        let mut app = testing::gpu_app(TriangleRenderPlugin::default());
+        let mut queue = CommandQueue::default();
+        let root = app
+            .world
+            .resource_scope(|world, mut meshes: Mut<Assets<Mesh>>| {
+                let mut commands = Commands::new(&mut queue, world);
+                let template = Triangle::side(8.0).with_rgba([1.0, 0.0, 0.0, 1.0]);
+                crate::spawn_triangle_grid(&mut commands, &mut meshes, 2, 2, 20.0, &template)
+            });
+        queue.apply(&mut app.world);
+        app.world.get_mut::<Transform>(root).unwrap().translation = Vec3::new(4.0, 2.0, 0.0);
+        let mut instances = app
+            .world
+            .query::<(&TriangleInstance, &Transform, &mut Visibility)>();
+        for (_, tform, mut visibility) in instances.iter_mut(&mut app.world) {
+            visibility.is_visible = tform.translation != Vec3::new(-10.0, -10.0, 0.0);
+        }
+
+        let pixels = testing::render(&mut app);
+        let red = [255, 0, 0, 255];
+        let at = |x: f32, y: f32| testing::pixel_at(&pixels, Vec2::new(x + 4.0, y + 2.0));
+        assert_eq!(at(-10.0, 10.0), red);
+        assert_eq!(at(10.0, 10.0), red);
+        assert_eq!(at(10.0, -10.0), red);
+        assert_eq!(at(-10.0, -10.0), [0; 4]);
+        assert_eq!(at(0.0, 0.0), [0; 4]);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn instances_sample_their_own_atlas_cells() {
+        let mut app = testing::gpu_app(TriangleRenderPlugin::default());
+        const RED: [u8; 4] = [255, 0, 0, 255];
+        const WHITE: [u8; 4] = [255; 4];
+        let image = Image::new(
+            wgpu::Extent3d {
+                width: 2,
+                height: 2,
+                depth_or_array_layers: 1,
+            },
+            wgpu::TextureDimension::D2,
+            [RED, [0, 255, 0, 255], [0, 0, 255, 255], WHITE].concat(),
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+        );
+        let image = app
+            .world
+            .get_resource_mut::<Assets<Image>>()
+            .unwrap()
+            .add(image);
+        let mut queue = CommandQueue::default();
+        let root = app
+            .world
+            .resource_scope(|world, mut meshes: Mut<Assets<Mesh>>| {
+                let mut commands = Commands::new(&mut queue, world);
+                let template = Triangle::side(12.0).with_rgba([1.0; 4]);
+                crate::spawn_triangle_grid(&mut commands, &mut meshes, 2, 1, 20.0, &template)
+            });
+        queue.apply(&mut app.world);
+        app.world.entity_mut(root).insert(TriangleTexture(image));
+        let mut instances = app.world.query::<(Entity, &TriangleInstance, &Transform)>();
+        let cells = instances
+            .iter(&app.world)
+            .map(|(entity, _, tform)| {
+                let cell = if tform.translation.x < 0.0 { 0 } else { 3 };
+                (entity, TriangleInstanceUv::atlas_cell(2, 2, cell))
+            })
+            .collect_vec();
+        for (entity, uv) in cells {
+            app.world.entity_mut(entity).insert(uv);
+        }
+
+        let pixels = testing::render(&mut app);
+        assert_eq!(testing::pixel_at(&pixels, Vec2::new(-10.0, 0.0)), RED);
+        assert_eq!(testing::pixel_at(&pixels, Vec2::new(10.0, 0.0)), WHITE);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn batches_split_past_max_batch_vertices() {
+        for (max_batch_vertices, draws) in [(6, vec![(0, 6), (0, 3)]), (1 << 16, vec![(0, 9)])] {
+            let plugin = TriangleRenderPlugin {
+                config: TriangleRenderConfig {
+                    max_batch_vertices,
+                    ..Default::default()
+                },
+            };
+            let mut app = testing::gpu_app(plugin);
+            for x in [-16.0, 0.0, 16.0] {
+                app.world
+                    .spawn()
+                    .insert_bundle(TriangleBundle::at(
+                        Triangle::side(8.0).with_rgba([1.0, 0.0, 0.0, 1.0]),
+                        Vec3::new(x, 0.0, 2.0 + x / 16.0),
+                    ))
+                    .insert(TriangleBatched);
+            }
+            // Batched items are merged at the end of `PhaseSort`.
+            let drawn = Arc::new(Mutex::new(Vec::new()));
+            let record = drawn.clone();
+            app.sub_app_mut(RenderApp).add_system_to_stage(
+                RenderStage::Render,
+                move |phase_q: Query<&RenderPhase<Transparent2d>>| {
+                    *record.lock().unwrap() = phase_q
+                        .iter()
+                        .flat_map(|phase| &phase.items)
+                        .filter_map(|item| item.batch_range.as_ref())
+                        .map(|range| (range.start, range.end))
+                        .collect_vec();
+                },
+            );
+
+            let pixels = testing::render(&mut app);
+            assert_eq!(*drawn.lock().unwrap(), draws);
+            for x in [-16.0, 0.0, 16.0] {
+                assert_eq!(
+                    testing::pixel_at(&pixels, Vec2::new(x, 0.0)),
+                    [255, 0, 0, 255]
+                );
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn views_with_different_formats_get_their_own_pipelines() {
+        let mut app = testing::gpu_app(TriangleRenderPlugin::default());
+        let hdr = testing::spawn_view(&mut app);
+        app.world.entity_mut(hdr).insert(TriangleHdr);
+        app.world
+            .spawn()
+            .insert_bundle(TriangleBundle::new(Triangle::side(10.0)));
+        let render_app = app.sub_app_mut(RenderApp);
+        // Give the HDR view a phase to queue into; it isn't drawn, since the offscreen
+        // target isn't HDR.
+        render_app.add_system_to_stage(
+            RenderStage::Extract,
+            |mut commands: Commands,
+             view_q: Query<(Entity, &VisibleEntities), With<TriangleHdr>>| {
+                for (entity, visible) in view_q.iter() {
+                    commands
+                        .get_or_spawn(entity)
+                        .insert_bundle((visible.clone(), RenderPhase::<Transparent2d>::default()));
+                }
+            },
+        );
+        let queued = Arc::new(Mutex::new(Vec::new()));
+        let record = queued.clone();
+        render_app.add_system_to_stage(
+            RenderStage::Render,
+            move |phase_q: Query<(&RenderPhase<Transparent2d>, Option<&TriangleHdr>)>| {
+                let mut queued = phase_q
+                    .iter()
+                    .flat_map(|(phase, hdr)| {
+                        phase
+                            .items
+                            .iter()
+                            .map(move |item| (hdr.is_some(), item.pipeline))
+                    })
+                    .collect_vec();
+                queued.sort_by_key(|(hdr, _)| *hdr);
+                *record.lock().unwrap() = queued;
+            },
+        );
+
+        testing::render(&mut app);
+        let first = queued.lock().unwrap().clone();
+        let specialized = |app: &mut App| {
+            app.sub_app_mut(RenderApp)
+                .world
+                .get_resource::<pipeline::TrianglePipelineSpecializations>()
+                .unwrap()
+                .len()
+        };
+        let count = specialized(&mut app);
+        app.update();
+        assert_eq!(*queued.lock().unwrap(), first);
+        assert_eq!(specialized(&mut app), count);
+
+        let (ldr_id, hdr_id) = match first[..] {
+            [(false, ldr), (true, hdr)] => (ldr, hdr),
+            _ => panic!("expected a triangle in each view, got {:?}", first),
+        };
+        assert_ne!(ldr_id, hdr_id);
+        let world = &app.sub_app_mut(RenderApp).world;
+        let specializations = world
+            .get_resource::<pipeline::TrianglePipelineSpecializations>()
+            .unwrap();
+        let key = |id| specializations.key(id).unwrap();
+        assert!(!key(ldr_id).contains(pipeline::TrianglePipelineKey::HDR));
+        assert_eq!(
+            key(hdr_id),
+            key(ldr_id) | pipeline::TrianglePipelineKey::HDR
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn shadows_draw_at_their_offset_behind_the_triangle() {
+        let mut app = testing::gpu_app(TriangleRenderPlugin::default());
+        app.world
+            .spawn()
+            .insert_bundle(TriangleBundle::new(
+                Triangle::side(16.0).with_rgba([1.0, 0.0, 0.0, 1.0]),
+            ))
+            .insert(TriangleShadow {
+                offset: Vec2::new(6.0, -6.0),
+                color: [0.0, 0.0, 1.0, 1.0],
+                blur: 0.0,
+            });
+        let pixels = testing::render(&mut app);
+        // The shadow peeks out below and to the right, and the triangle covers the rest.
+        assert_eq!(testing::pixel_at(&pixels, Vec2::ZERO), [255, 0, 0, 255]);
+        assert_eq!(
+            testing::pixel_at(&pixels, Vec2::new(8.0, -10.0)),
+            [0, 0, 255, 255]
+        );
+        assert_eq!(testing::pixel_at(&pixels, Vec2::new(-6.0, 6.0)), [0; 4]);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn the_global_tint_multiplies_every_triangle() {
+        let mut app = testing::gpu_app(TriangleRenderPlugin::default());
+        app.insert_resource(TriangleGlobalTint([0.5, 1.0, 1.0, 1.0]));
+        app.world.spawn().insert_bundle(TriangleBundle::new(
+            Triangle::side(20.0).with_rgba([1.0; 4]),
+        ));
+        let pixels = testing::render(&mut app);
+        // Half of linear red, encoded as sRGB.
+        let [r, g, b, a] = testing::pixel_at(&pixels, Vec2::ZERO);
+        assert!((187..=188).contains(&r), "red is {}", r);
+        assert_eq!([g, b, a], [255; 3]);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn opacity_scales_the_alpha_of_opaque_triangles() {
+        let mut app = testing::gpu_app(TriangleRenderPlugin::default());
+        app.world
+            .spawn()
+            .insert_bundle(TriangleBundle::new(
+                Triangle::side(20.0).with_rgba([1.0, 0.0, 0.0, 1.0]),
+            ))
+            .insert(TriangleOpacity(0.5));
+        let [_, _, _, a] = testing::pixel_at(&testing::render(&mut app), Vec2::ZERO);
+        assert!((127..=128).contains(&a), "alpha is {}", a);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn the_alpha_clamp_raises_invisible_triangles() {
+        let mut app = testing::gpu_app(TriangleRenderPlugin::default());
+        app.insert_resource(AlphaClamp { min: 0.2, max: 1.0 });
+        app.world.spawn().insert_bundle(TriangleBundle::new(
+            Triangle::side(20.0).with_rgba([1.0, 0.0, 0.0, 0.0]),
+        ));
+        let [_, _, _, a] = testing::pixel_at(&testing::render(&mut app), Vec2::ZERO);
+        assert!((50..=52).contains(&a), "alpha is {}", a);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn fog_fades_farther_triangles_more() {
+        let mut app = testing::gpu_app(TriangleRenderPlugin::default());
+        // Extract measures depth from the first camera with a window, and `gpu_app`'s view
+        // has none, so give the plane extract would for a camera like it.
+        app.sub_app_mut(RenderApp).add_system_to_stage(
+            RenderStage::Prepare,
+            (|mut globals: ResMut<TriangleGlobals>| {
+                globals.fog_plane = Vec4::new(0.0, 0.0, -1.0, 999.9)
+            })
+            .before(plugin::TriangleRenderSystem::PrepareGlobals),
+        );
+        app.insert_resource(Fog {
+            color: [0.0, 0.0, 1.0, 1.0],
+            start: 0.0,
+            end: 1000.0,
+        });
+        // About 200 and 900 units in front of the camera.
+        for (x, z) in [(-15.0, 800.0), (15.0, 100.0)] {
+            app.world.spawn().insert_bundle(TriangleBundle::at(
+                Triangle::side(20.0).with_rgba([1.0, 0.0, 0.0, 1.0]),
+                Vec3::new(x, 0.0, z),
+            ));
+        }
+        let pixels = testing::render(&mut app);
+        let near = testing::pixel_at(&pixels, Vec2::new(-15.0, 0.0));
+        let far = testing::pixel_at(&pixels, Vec2::new(15.0, 0.0));
+        assert!(
+            near[0] > far[0] && near[2] < far[2],
+            "{:?} vs {:?}",
+            near,
+            far
+        );
+        // Not so near as to be untouched, nor so far as to be all fog.
+        assert!(near[2] > 0 && far[0] > 0, "{:?} vs {:?}", near, far);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn the_uploaded_time_advances_between_frames() {
+        let mut app = testing::gpu_app(TriangleRenderPlugin::default());
+        let uploaded_time = |app: &mut App| {
+            app.update();
+            let world = &app.sub_app_mut(RenderApp).world;
+            let last = world.get_resource::<LastUploadedTriangleData>().unwrap();
+            last.globals.unwrap().time
+        };
+        let first = uploaded_time(&mut app);
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        let second = uploaded_time(&mut app);
+        assert!(second > first, "{} then {}", first, second);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn the_last_upload_records_vertex_counts_and_offsets() {
+        let mut app = testing::gpu_app(TriangleRenderPlugin {
+            config: TriangleRenderConfig {
+                max_batch_vertices: 6,
+                ..Default::default()
+            },
+        });
+        let batched = [-16.0, 0.0, 16.0].map(|x| {
+            app.world
+                .spawn()
+                .insert_bundle(TriangleBundle::at(
+                    Triangle::side(8.0),
+                    Vec3::new(x, 0.0, 2.0 + x / 16.0),
+                ))
+                .insert(TriangleBatched)
+                .id()
+        });
+        let vertex = StreamingVertex {
+            color: [1.0; 4],
+            position: [0.0; 3],
+            uv: [0.0; 2],
+        };
+        let streaming = app
+            .world
+            .spawn()
+            .insert_bundle((
+                StreamingTriangle {
+                    vertices: vec![vertex; 6],
+                },
+                Transform::default(),
+                GlobalTransform::default(),
+                Visibility::default(),
+                ComputedVisibility::default(),
+            ))
+            .id();
+        let mut queue = CommandQueue::default();
+        let root = app
+            .world
+            .resource_scope(|world, mut meshes: Mut<Assets<Mesh>>| {
+                let mut commands = Commands::new(&mut queue, world);
+                let template = Triangle::side(4.0);
+                crate::spawn_triangle_grid(&mut commands, &mut meshes, 2, 2, 20.0, &template)
+            });
+        queue.apply(&mut app.world);
+
+        app.update();
+        let world = &app.sub_app_mut(RenderApp).world;
+        let last = world.get_resource::<LastUploadedTriangleData>().unwrap();
+        assert_eq!(last.streaming[&streaming].len(), 6);
+        assert_eq!(last.instances[&root].len(), 4);
+        // Filled back to front, the first batch full before the second is started.
+        let mut batches = last
+            .batches
+            .iter()
+            .map(|batch| {
+                let ranges = batch
+                    .triangles
+                    .iter()
+                    .map(|triangle| (triangle.entity, triangle.range.clone()))
+                    .collect_vec();
+                (batch.vertices.len(), ranges)
+            })
+            .collect_vec();
+        batches.sort_by_key(|(len, _)| std::cmp::Reverse(*len));
+        assert_eq!(
+            batches,
+            [
+                (6, vec![(batched[0], 0..3), (batched[1], 3..6)]),
+                (3, vec![(batched[2], 0..3)]),
+            ]
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn glows_light_up_just_outside_the_edge() {
+        let mut app = testing::gpu_app(TriangleRenderPlugin::default());
+        let entity = app
+            .world
+            .spawn()
+            .insert_bundle(TriangleBundle::new(
+                Triangle::side(20.0).with_rgba([1.0, 0.0, 0.0, 1.0]),
+            ))
+            .id();
+        // A unit and a half below the bottom edge.
+        let outside = Vec2::new(0.0, -10.0);
+        assert_eq!(
+            testing::pixel_at(&testing::render(&mut app), outside),
+            [0; 4]
+        );
+
+        app.world.entity_mut(entity).insert(TriangleGlow {
+            color: [0.0, 1.0, 0.0, 1.0],
+            radius: 4.0,
+        });
+        let [r, g, b, _] = testing::pixel_at(&testing::render(&mut app), outside);
+        assert!(g > 0 && r == 0 && b == 0, "{:?}", [r, g, b]);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn triangles_render_through_a_perspective_camera() {
+        let mut app = testing::gpu_app(TriangleRenderPlugin::default());
+        // As wide as the orthographic view at `z = 0`, and twice as zoomed in halfway there.
+        let fov = 2.0 * (testing::SIZE as f32 / 2.0 / 999.9).atan();
+        let projection = Mat4::perspective_infinite_reverse_rh(fov, 1.0, 0.1);
+        let mut view_q = app
+            .world
+            .query::<(&mut Camera, &mut Frustum, &GlobalTransform)>();
+        for (mut camera, mut frustum, tform) in view_q.iter_mut(&mut app.world) {
+            camera.projection_matrix = projection;
+            *frustum = Frustum::from_view_projection(
+                &(projection * tform.compute_matrix().inverse()),
+                &tform.translation,
+                &tform.back(),
+                camera.far,
+            );
+        }
+        for (side, translation) in [(20.0, Vec3::ZERO), (6.0, Vec3::new(8.0, 0.0, 500.0))] {
+            app.world.spawn().insert_bundle(TriangleBundle::at(
+                Triangle::side(side).with_rgba([1.0, 0.0, 0.0, 1.0]),
+                translation,
+            ));
+        }
+        let pixels = testing::render(&mut app);
+        assert_eq!(testing::pixel_at(&pixels, Vec2::ZERO), [255, 0, 0, 255]);
+        // The nearer one shows up 12 pixels across at `x = 16`, leaving a gap to the other.
+        assert_eq!(
+            testing::pixel_at(&pixels, Vec2::new(16.0, 0.0)),
+            [255, 0, 0, 255]
+        );
+        assert_eq!(testing::pixel_at(&pixels, Vec2::new(11.0, 0.0)), [0; 4]);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn triangles_sharing_an_image_share_a_cached_bind_group() {
+        let mut app = testing::gpu_app(TriangleRenderPlugin::default());
+        let image = Image::new(
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            wgpu::TextureDimension::D2,
+            vec![255; 4],
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+        );
+        let image = app
+            .world
+            .get_resource_mut::<Assets<Image>>()
+            .unwrap()
+            .add(image);
+        for x in [-15.0, 15.0] {
+            app.world
+                .spawn()
+                .insert_bundle(TriangleBundle::at(
+                    Triangle::side(10.0),
+                    Vec3::new(x, 0.0, 0.0),
+                ))
+                .insert(TriangleTexture(image.clone()));
+        }
+        let cached = |app: &mut App| {
+            let world = &app.sub_app_mut(RenderApp).world;
+            let cache = world.get_resource::<TextureBindGroupCache>().unwrap();
+            cache
+                .0
+                .values()
+                .map(|bind_group| bind_group.id())
+                .collect_vec()
+        };
+
+        testing::render(&mut app);
+        let first = cached(&mut app);
+        assert_eq!(first.len(), 1);
+        app.update();
+        assert_eq!(cached(&mut app), first);
+    }
+}
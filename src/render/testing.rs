@@ -0,0 +1,416 @@
+//! Helpers for the render tests: a headless app to extract from, and a way to run extract
+//! systems against a render world without a `RenderApp`, and an app that renders into a
+//! texture whose pixels can be read back.
+
+use std::num::NonZeroU32;
+
+use bevy::asset::AssetPlugin;
+use bevy::core::CorePlugin;
+use bevy::core_pipeline::{draw_2d_graph, CorePipelinePlugin, Transparent2d};
+use bevy::ecs::schedule::IntoSystemDescriptor;
+use bevy::prelude::*;
+use bevy::render::options::{Backends, WgpuOptions};
+use bevy::render::primitives::Frustum;
+use bevy::render::render_graph::{Node, NodeRunError, RenderGraph, RenderGraphContext, SlotValue};
+use bevy::render::render_phase::{DrawFunctions, RenderPhase, TrackedRenderPass};
+use bevy::render::renderer::{RenderContext, RenderDevice, RenderInstance};
+use bevy::render::texture::BevyDefault;
+use bevy::render::view::{ExtractedView, ViewTarget, VisibleEntities};
+use bevy::render::{RenderApp, RenderPlugin, RenderStage};
+use bevy::transform::TransformPlugin;
+use bevy::window::{WindowId, WindowPlugin};
+use raw_window_handle::{RawWindowHandle, WebHandle};
+
+use super::plugin::{TriangleExtractPlugin, TriangleRenderConfig};
+use crate::{wgpu, TrianglePlugin};
+
+/// An app with `TrianglePlugin` and the main-world half of the render plugin, but no
+/// renderer, so it runs anywhere.
+pub fn headless_app(config: TriangleRenderConfig) -> App {
+    let mut app = crate::tests::app();
+    app.add_plugin(TriangleExtractPlugin { config });
+    app
+}
+
+/// Adds a primary window `SIZE` pixels square to `app`, and a camera on it with
+/// `projection` at `transform`, for extract systems that only look at cameras with a
+/// window. Nothing draws into the window: its handle is empty.
+pub fn windowed_camera(app: &mut App, projection: Mat4, transform: Transform) -> Entity {
+    let window = Window::new(
+        WindowId::primary(),
+        &WindowDescriptor::default(),
+        SIZE,
+        SIZE,
+        1.0,
+        None,
+        RawWindowHandle::Web(WebHandle::empty()),
+    );
+    app.world.get_resource_mut::<Windows>().unwrap().add(window);
+    app.world
+        .spawn()
+        .insert_bundle((
+            Camera {
+                projection_matrix: projection,
+                ..Default::default()
+            },
+            GlobalTransform::from(transform),
+        ))
+        .id()
+}
+
+/// Runs `system` like the `Extract` stage would: against the main world, with its commands
+/// applied to `render_world`.
+pub fn extract<Params>(
+    main_world: &mut World,
+    render_world: &mut World,
+    system: impl IntoSystemDescriptor<Params>,
+) {
+    let mut stage = SystemStage::single_threaded().with_system(system);
+    stage.set_apply_buffers(false);
+    stage.run(main_world);
+    stage.apply_buffers(render_world);
+}
+
+/// Side in pixels of the square texture `gpu_app` renders into. A row of 64 RGBA8 pixels
+/// is 256 bytes, the alignment texture-to-buffer copies need.
+pub const SIZE: u32 = 64;
+
+const OFFSCREEN: &str = "offscreen";
+const OFFSCREEN_DRAW_2D: &str = "offscreen_draw_2d";
+const OFFSCREEN_READ_BACK: &str = "offscreen_read_back";
+
+/// Marks the camera `gpu_app` draws into its texture. It isn't active, so the core 2d
+/// pipeline leaves it alone: Bevy 0.6 cameras only render to windows.
+#[derive(Component)]
+struct OffscreenView;
+
+/// The texture `OffscreenNode` draws into, and the buffer it's copied to for reading back.
+struct Offscreen {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    buffer: wgpu::Buffer,
+}
+
+/// The backends `WGPU_BACKEND` names, or all of them.
+fn backends() -> Backends {
+    match std::env::var("WGPU_BACKEND") {
+        Ok(_) => WgpuOptions::default().backends.unwrap(),
+        Err(_) => Backends::all(),
+    }
+}
+
+/// A device on the first adapter `gpu_app` would render with, without an app around it.
+/// Panics if there's no adapter, like `gpu_app`.
+pub fn render_device() -> RenderDevice {
+    let backends = backends();
+    let instance = RenderInstance::new(backends);
+    let adapter = instance
+        .enumerate_adapters(backends)
+        .next()
+        .unwrap_or_else(|| panic!("no GPU adapter for {:?}", backends));
+    let (device, _) =
+        futures_lite::future::block_on(adapter.request_device(&Default::default(), None)).unwrap();
+    RenderDevice::from(std::sync::Arc::new(device))
+}
+
+/// An app that renders with `plugin`, usually `TriangleRenderPlugin`, into a `SIZE`
+/// square texture, through an orthographic view centered on the origin with one pixel
+/// per world unit. Panics if there's no adapter to render with: tests using it are
+/// `#[ignore]`d, so they only run when asked for with `cargo test -- --ignored`, and then
+/// mustn't pass without drawing.
+///
+/// Pick the backend with `WGPU_BACKEND`, e.g. `WGPU_BACKEND=gl` for a software
+/// rasterizer.
+pub fn gpu_app(plugin: impl Plugin) -> App {
+    let backends = backends();
+    assert!(
+        RenderInstance::new(backends)
+            .enumerate_adapters(backends)
+            .next()
+            .is_some(),
+        "no GPU adapter for {:?}",
+        backends
+    );
+    let mut app = App::new();
+    app.add_plugin(CorePlugin)
+        .add_plugin(TransformPlugin)
+        .add_plugin(AssetPlugin)
+        .add_plugin(WindowPlugin {
+            add_primary_window: false,
+            exit_on_close: false,
+        })
+        .init_resource::<Input<MouseButton>>()
+        .insert_resource(WgpuOptions {
+            backends: Some(backends),
+            ..Default::default()
+        })
+        .insert_resource(Msaa { samples: 1 })
+        .add_plugin(RenderPlugin)
+        .add_plugin(CorePipelinePlugin)
+        .add_plugin(TrianglePlugin)
+        .add_plugin(plugin);
+
+    let view = spawn_view(&mut app);
+    app.world.entity_mut(view).insert(OffscreenView);
+
+    let render_app = app.sub_app_mut(RenderApp);
+    let device = render_app.world.get_resource::<RenderDevice>().unwrap();
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("offscreen_target"),
+        size: wgpu::Extent3d {
+            width: SIZE,
+            height: SIZE,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::bevy_default(),
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("offscreen_read_back"),
+        size: (SIZE * SIZE * 4) as u64,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let node = OffscreenNode::new(&mut render_app.world);
+    render_app
+        .insert_resource(Offscreen {
+            texture,
+            view,
+            buffer,
+        })
+        .add_system_to_stage(RenderStage::Extract, extract_offscreen_view);
+    let mut graph = render_app.world.get_resource_mut::<RenderGraph>().unwrap();
+    graph.add_node(OFFSCREEN, node);
+    graph.add_node(OFFSCREEN_READ_BACK, OffscreenReadBackNode);
+    graph.add_node_edge(OFFSCREEN, OFFSCREEN_READ_BACK).unwrap();
+    app
+}
+
+/// Has a `gpu_app` draw its view through Bevy's 2d graph as well, after its own pass and
+/// before the read-back, for nodes plugins add to that graph. Only the graph's main pass
+/// and what follows it show up: it clears the texture to `ClearColor`, which
+/// `Color::NONE` keeps like the own pass left it.
+pub fn draw_2d_graph(app: &mut App) {
+    let render_app = app.sub_app_mut(RenderApp);
+    render_app.add_system_to_stage(RenderStage::Prepare, add_offscreen_view_target);
+    let node = Draw2dGraphNode {
+        query: QueryState::new(&mut render_app.world),
+    };
+    let mut graph = render_app.world.get_resource_mut::<RenderGraph>().unwrap();
+    graph.add_node(OFFSCREEN_DRAW_2D, node);
+    graph.add_node_edge(OFFSCREEN, OFFSCREEN_DRAW_2D).unwrap();
+    graph
+        .add_node_edge(OFFSCREEN_DRAW_2D, OFFSCREEN_READ_BACK)
+        .unwrap();
+}
+
+/// Spawns a camera like the one `gpu_app` draws through, for `check_visibility` to fill in
+/// the entities in its view. Only the one `gpu_app` spawns is drawn.
+pub fn spawn_view(app: &mut App) -> Entity {
+    let half = SIZE as f32 / 2.0;
+    let far = 1000.0;
+    let projection = Mat4::orthographic_rh(-half, half, -half, half, 0.0, far);
+    let transform = GlobalTransform::from_xyz(0.0, 0.0, far - 0.1);
+    app.world
+        .spawn()
+        .insert_bundle((
+            Camera {
+                projection_matrix: projection,
+                near: 0.0,
+                far,
+                ..Default::default()
+            },
+            Frustum::from_view_projection(
+                &(projection * transform.compute_matrix().inverse()),
+                &transform.translation,
+                &transform.back(),
+                far,
+            ),
+            VisibleEntities::default(),
+            transform,
+        ))
+        .id()
+}
+
+/// Runs a few frames, so pipelines compile and meshes are prepared, and returns the last
+/// one's pixels, row by row from the top.
+pub fn render(app: &mut App) -> Vec<[u8; 4]> {
+    for _ in 0..3 {
+        app.update();
+    }
+    read_back(app)
+}
+
+/// The pixels of the last frame, row by row from the top.
+pub fn read_back(app: &mut App) -> Vec<[u8; 4]> {
+    let render_app = app.sub_app_mut(RenderApp);
+    let device = render_app.world.get_resource::<RenderDevice>().unwrap();
+    let offscreen = render_app.world.get_resource::<Offscreen>().unwrap();
+    let slice = offscreen.buffer.slice(..);
+    device.map_buffer(&slice, wgpu::MapMode::Read);
+    let pixels = slice
+        .get_mapped_range()
+        .chunks_exact(4)
+        .map(|p| [p[0], p[1], p[2], p[3]])
+        .collect();
+    offscreen.buffer.unmap();
+    pixels
+}
+
+/// The pixel covering the world-space point `p`.
+pub fn pixel_at(pixels: &[[u8; 4]], p: Vec2) -> [u8; 4] {
+    let half = SIZE as f32 / 2.0;
+    let x = (p.x + half).floor() as usize;
+    let y = (half - p.y).floor() as usize;
+    pixels[y * SIZE as usize + x]
+}
+
+/// Extracts the offscreen view with the entities `check_visibility` found in its frustum.
+fn extract_offscreen_view(
+    mut commands: Commands,
+    view_q: Query<(Entity, &Camera, &GlobalTransform, &VisibleEntities), With<OffscreenView>>,
+) {
+    for (entity, camera, transform, visible) in view_q.iter() {
+        commands.get_or_spawn(entity).insert_bundle((
+            ExtractedView {
+                projection: camera.projection_matrix,
+                transform: *transform,
+                width: SIZE,
+                height: SIZE,
+                near: camera.near,
+                far: camera.far,
+            },
+            visible.clone(),
+            RenderPhase::<Transparent2d>::default(),
+            OffscreenView,
+        ));
+    }
+}
+
+/// Draws the offscreen view's `Transparent2d` phase over transparent black.
+struct OffscreenNode {
+    query: QueryState<(Entity, &'static RenderPhase<Transparent2d>), With<OffscreenView>>,
+}
+
+impl OffscreenNode {
+    fn new(world: &mut World) -> Self {
+        Self {
+            query: QueryState::new(world),
+        }
+    }
+}
+
+impl Node for OffscreenNode {
+    fn update(&mut self, world: &mut World) {
+        self.query.update_archetypes(world);
+    }
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let offscreen = world.get_resource::<Offscreen>().unwrap();
+        let draw_functions = world
+            .get_resource::<DrawFunctions<Transparent2d>>()
+            .unwrap();
+        for (view_entity, phase) in self.query.iter_manual(world) {
+            let pass_descriptor = wgpu::RenderPassDescriptor {
+                label: Some("offscreen"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &offscreen.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(Color::NONE.into()),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            };
+            let render_pass = render_context
+                .command_encoder
+                .begin_render_pass(&pass_descriptor);
+            let mut draw_functions = draw_functions.write();
+            let mut tracked_pass = TrackedRenderPass::new(render_pass);
+            for item in phase.items.iter() {
+                let draw_function = draw_functions.get_mut(item.draw_function).unwrap();
+                draw_function.draw(world, &mut tracked_pass, view_entity, item);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Copies the offscreen texture to the read-back buffer.
+struct OffscreenReadBackNode;
+
+impl Node for OffscreenReadBackNode {
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let offscreen = world.get_resource::<Offscreen>().unwrap();
+        render_context.command_encoder.copy_texture_to_buffer(
+            offscreen.texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &offscreen.buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(SIZE * 4),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: SIZE,
+                height: SIZE,
+                depth_or_array_layers: 1,
+            },
+        );
+        Ok(())
+    }
+}
+
+/// Points the 2d graph's passes at the offscreen texture.
+fn add_offscreen_view_target(
+    mut commands: Commands,
+    offscreen: Res<Offscreen>,
+    view_q: Query<Entity, With<OffscreenView>>,
+) {
+    for entity in view_q.iter() {
+        commands.entity(entity).insert(ViewTarget {
+            view: offscreen.view.clone(),
+            sampled_target: None,
+        });
+    }
+}
+
+/// Runs the 2d graph for the offscreen view: Bevy 0.6 only runs it for cameras with a
+/// window.
+struct Draw2dGraphNode {
+    query: QueryState<Entity, With<OffscreenView>>,
+}
+
+impl Node for Draw2dGraphNode {
+    fn update(&mut self, world: &mut World) {
+        self.query.update_archetypes(world);
+    }
+
+    fn run(
+        &self,
+        graph: &mut RenderGraphContext,
+        _render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        for view in self.query.iter_manual(world) {
+            graph.run_sub_graph(draw_2d_graph::NAME, vec![SlotValue::Entity(view)])?;
+        }
+        Ok(())
+    }
+}
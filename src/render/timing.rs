@@ -0,0 +1,222 @@
+//! Measures the GPU time of the 2d main pass with timestamp queries written before and
+//! after it, reported as `TriangleRenderDiagnostics::main_pass_gpu_time`.
+//!
+//! Bevy's render passes don't expose timestamps to draw commands, so this times the whole
+//! pass, sprites included. With several 2d cameras only the last one's pass is kept.
+//! Results are read back without blocking, so they lag a frame or two.
+
+use bevy::core_pipeline::draw_2d_graph;
+use bevy::prelude::*;
+use bevy::render::render_graph::{Node, NodeRunError, RenderGraph, RenderGraphContext};
+use bevy::render::render_resource::Buffer;
+use bevy::render::renderer::{RenderContext, RenderDevice, RenderQueue};
+use bevy::render::RenderStage;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+use std::time::Duration;
+
+use super::{wgpu, TriangleRenderDiagnostics};
+
+const BEGIN: &str = "triangle_timestamp_begin";
+const END: &str = "triangle_timestamp_end";
+const READBACK_SIZE: u64 = 2 * std::mem::size_of::<u64>() as u64;
+
+type MapFuture = Pin<Box<dyn Future<Output = Result<(), ::wgpu::BufferAsyncError>> + Send>>;
+
+enum Readback {
+    /// The read buffer is free for the next pair of timestamps.
+    Idle,
+    /// The timestamps were copied into the read buffer this frame.
+    Copied,
+    /// Waiting for the read buffer to be mapped.
+    Mapping(MapFuture),
+}
+
+pub struct GpuTimer {
+    query_set: ::wgpu::QuerySet,
+    resolve_buffer: Buffer,
+    read_buffer: Buffer,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+    readback: Mutex<Readback>,
+}
+
+/// Adds the timestamp nodes around the 2d main pass and the readback system, unless
+/// the device lacks `TIMESTAMP_QUERY`, which leaves `main_pass_gpu_time` at `None`.
+pub fn add_gpu_timer(render_app: &mut App) {
+    let device = render_app.world.get_resource::<RenderDevice>().unwrap();
+    if !device
+        .features()
+        .contains(wgpu::WgpuFeatures::TIMESTAMP_QUERY)
+    {
+        info!("the render device doesn't support TIMESTAMP_QUERY, not timing the main pass");
+        return;
+    }
+    let query_set = device
+        .wgpu_device()
+        .create_query_set(&::wgpu::QuerySetDescriptor {
+            label: Some("triangle timestamps"),
+            ty: ::wgpu::QueryType::Timestamp,
+            count: 2,
+        });
+    let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("triangle timestamp resolve buffer"),
+        size: READBACK_SIZE,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    let read_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("triangle timestamp read buffer"),
+        size: READBACK_SIZE,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let period = render_app
+        .world
+        .get_resource::<RenderQueue>()
+        .unwrap()
+        .get_timestamp_period();
+    render_app
+        .insert_resource(GpuTimer {
+            query_set,
+            resolve_buffer,
+            read_buffer,
+            period,
+            readback: Mutex::new(Readback::Idle),
+        })
+        .add_system_to_stage(RenderStage::Cleanup, read_gpu_time);
+
+    let mut graph = render_app.world.get_resource_mut::<RenderGraph>().unwrap();
+    let draw_2d = graph.get_sub_graph_mut(draw_2d_graph::NAME).unwrap();
+    draw_2d.add_node(BEGIN, TimestampNode { end: false });
+    draw_2d.add_node(END, TimestampNode { end: true });
+    draw_2d
+        .add_node_edge(BEGIN, draw_2d_graph::node::MAIN_PASS)
+        .unwrap();
+    draw_2d
+        .add_node_edge(draw_2d_graph::node::MAIN_PASS, END)
+        .unwrap();
+}
+
+/// Writes the timestamp before (`end: false`) or after the main pass, and after it
+/// copies both out for reading if the read buffer is free.
+struct TimestampNode {
+    end: bool,
+}
+
+impl Node for TimestampNode {
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let timer = world.get_resource::<GpuTimer>().unwrap();
+        let encoder = &mut render_context.command_encoder;
+        encoder.write_timestamp(&timer.query_set, self.end as u32);
+        if !self.end {
+            return Ok(());
+        }
+        let mut readback = timer.readback.lock().unwrap();
+        if let Readback::Idle = *readback {
+            encoder.resolve_query_set(&timer.query_set, 0..2, &timer.resolve_buffer, 0);
+            encoder.copy_buffer_to_buffer(
+                &timer.resolve_buffer,
+                0,
+                &timer.read_buffer,
+                0,
+                READBACK_SIZE,
+            );
+            *readback = Readback::Copied;
+        }
+        Ok(())
+    }
+}
+
+/// A waker that does nothing; `read_gpu_time` polls the map future once a frame
+/// instead of being woken.
+fn noop_waker() -> Waker {
+    fn clone(_: *const ()) -> RawWaker {
+        RawWaker::new(std::ptr::null(), &VTABLE)
+    }
+    fn noop(_: *const ()) {}
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+    // SAFETY: every vtable function ignores the null data pointer.
+    unsafe { Waker::from_raw(RawWaker::new(std::ptr::null(), &VTABLE)) }
+}
+
+/// Starts mapping freshly copied timestamps, and once mapped, stores their difference
+/// in `TriangleRenderDiagnostics`.
+fn read_gpu_time(
+    timer: Res<GpuTimer>,
+    device: Res<RenderDevice>,
+    diagnostics: Res<TriangleRenderDiagnostics>,
+) {
+    let mut readback = timer.readback.lock().unwrap();
+    if let Readback::Copied = *readback {
+        let future = timer.read_buffer.slice(..).map_async(wgpu::MapMode::Read);
+        *readback = Readback::Mapping(Box::pin(future));
+    }
+    let future = match &mut *readback {
+        Readback::Mapping(future) => future,
+        _ => return,
+    };
+    device.poll(::wgpu::Maintain::Poll);
+    let waker = noop_waker();
+    let mapped = match future.as_mut().poll(&mut Context::from_waker(&waker)) {
+        Poll::Ready(mapped) => mapped,
+        Poll::Pending => return,
+    };
+    if mapped.is_ok() {
+        let slice = timer.read_buffer.slice(..);
+        let bytes = slice.get_mapped_range();
+        let [begin, end]: [u64; 2] = bevy::core::cast_slice::<u8, u64>(&bytes)
+            .try_into()
+            .unwrap();
+        let nanos = end.saturating_sub(begin) as f64 * timer.period as f64;
+        *diagnostics.main_pass_gpu_time.lock().unwrap() = Some(Duration::from_nanos(nanos as u64));
+    }
+    timer.read_buffer.unmap();
+    *readback = Readback::Idle;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::render::RenderApp;
+
+    use crate::render::plugin::TriangleRenderPlugin;
+    use crate::render::testing;
+    use crate::{Triangle, TriangleBundle};
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn the_main_pass_gpu_time_is_measured_where_timestamps_are_supported() {
+        let mut app = testing::gpu_app(TriangleRenderPlugin::default());
+        let render_app = app.sub_app_mut(RenderApp);
+        let supported = render_app
+            .world
+            .get_resource::<RenderDevice>()
+            .unwrap()
+            .features()
+            .contains(wgpu::WgpuFeatures::TIMESTAMP_QUERY);
+        assert_eq!(render_app.world.contains_resource::<GpuTimer>(), supported);
+        // The timestamps are written around the 2d graph's main pass.
+        testing::draw_2d_graph(&mut app);
+        app.world
+            .spawn()
+            .insert_bundle(TriangleBundle::new(Triangle::side(20.0)));
+
+        // Timestamps are read back without blocking, so give them a few frames.
+        for _ in 0..10 {
+            app.update();
+        }
+        let diagnostics = app
+            .world
+            .get_resource::<TriangleRenderDiagnostics>()
+            .unwrap();
+        assert_eq!(diagnostics.main_pass_gpu_time().is_some(), supported);
+    }
+}
@@ -0,0 +1,356 @@
+struct View {
+    view_proj: mat4x4<f32>;
+};
+
+struct Globals {
+    time: f32;
+    delta: f32;
+    tint: vec4<f32>;
+    relative_view_proj: mat4x4<f32>;
+    alpha_clamp: vec2<f32>;
+    fog_color: vec4<f32>;
+    fog_plane: vec4<f32>;
+    fog_range: vec2<f32>;
+};
+
+struct Mesh {
+    transform: mat4x4<f32>;
+    tint: vec4<f32>;
+    opacity: f32;
+    uv_transform: mat3x3<f32>;
+    uv_scroll: vec2<f32>;
+};
+
+#ifdef EDGE_UNIFORM
+struct EdgeUniform {
+    feather: vec4<f32>;
+    glow: vec4<f32>;
+    edge_color: vec4<f32>;
+    edge_thickness: f32;
+    edge_color_a: vec4<f32>;
+    edge_color_b: vec4<f32>;
+    edge_color_c: vec4<f32>;
+};
+#endif
+
+#ifdef FILL_UNIFORM
+struct FillUniform {
+    pattern: vec4<f32>;
+    alpha_cutoff: f32;
+    split: vec4<f32>;
+    split_color_a: vec4<f32>;
+    split_color_b: vec4<f32>;
+    reveal: vec4<f32>;
+    back_color: vec4<f32>;
+    dissolve: vec4<f32>;
+    dissolve_color: vec4<f32>;
+};
+#endif
+
+struct Vertex {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] color: vec4<f32>;
+    [[location(2)]] uv: vec2<f32>;
+#ifdef VERTEX_TANGENTS
+    [[location(3)]] tangent: vec4<f32>;
+#endif
+#ifdef INSTANCED
+    // The columns of the instance's transform, relative to `mesh.transform`.
+    [[location(4)]] instance_0: vec4<f32>;
+    [[location(5)]] instance_1: vec4<f32>;
+    [[location(6)]] instance_2: vec4<f32>;
+    [[location(7)]] instance_3: vec4<f32>;
+    // `TriangleInstanceUv`: the offset in `xy`, the scale in `zw`.
+    [[location(8)]] instance_uv: vec4<f32>;
+#endif
+#ifdef BARYCENTRIC
+    [[builtin(vertex_index)]] index: u32;
+#endif
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] color: vec4<f32>;
+    [[location(1)]] uv: vec2<f32>;
+#ifdef VERTEX_TANGENTS
+    // For custom normal-mapping shaders; the built-in fragment stage is unlit.
+    [[location(2)]] world_tangent: vec4<f32>;
+#endif
+#ifdef BARYCENTRIC
+    [[location(3)]] barycentric: vec3<f32>;
+#endif
+#ifdef SPLIT
+    [[location(4)]] local_position: vec2<f32>;
+#endif
+    [[location(5)]] depth: f32;
+};
+
+[[group(0), binding(0)]]
+var<uniform> view: View;
+
+[[group(0), binding(1)]]
+var<uniform> globals: Globals;
+
+[[group(1), binding(0)]]
+var<uniform> mesh: Mesh;
+
+#ifdef EDGE_UNIFORM
+[[group(1), binding(1)]]
+var<uniform> edges: EdgeUniform;
+#endif
+
+#ifdef FILL_UNIFORM
+[[group(1), binding(2)]]
+var<uniform> fill: FillUniform;
+#endif
+
+#ifdef TEXTURED
+[[group(2), binding(0)]]
+var triangle_texture: texture_2d<f32>;
+[[group(2), binding(1)]]
+var triangle_sampler: sampler;
+#endif
+
+[[stage(vertex)]]
+fn vertex(in: Vertex) -> VertexOutput {
+#ifdef INSTANCED
+    let transform = mesh.transform * mat4x4<f32>(in.instance_0, in.instance_1, in.instance_2, in.instance_3);
+#else
+    let transform = mesh.transform;
+#endif
+    let world_position = transform * vec4<f32>(in.position, 1.0);
+
+    var out: VertexOutput;
+    out.color = in.color;
+#ifdef INSTANCED
+    out.uv = in.uv * in.instance_uv.zw + in.instance_uv.xy;
+#else
+    out.uv = in.uv;
+#endif
+#ifdef VERTEX_TANGENTS
+    out.world_tangent = vec4<f32>((transform * vec4<f32>(in.tangent.xyz, 0.0)).xyz, in.tangent.w);
+#endif
+#ifdef BARYCENTRIC
+    // Meshes of `Triangle`s are plain triangle lists, so the corner follows from the index.
+    let corner = in.index % 3u;
+    out.barycentric = vec3<f32>(
+        select(0.0, 1.0, corner == 0u),
+        select(0.0, 1.0, corner == 1u),
+        select(0.0, 1.0, corner == 2u),
+    );
+#endif
+#ifdef SPLIT
+    out.local_position = in.position.xy;
+#endif
+    out.depth = dot(globals.fog_plane.xyz, world_position.xyz) + globals.fog_plane.w;
+#ifdef CAMERA_RELATIVE
+    // `world_position` is relative to the camera; see `TriangleRenderConfig::camera_relative`.
+    out.clip_position = globals.relative_view_proj * world_position;
+#else
+    out.clip_position = view.view_proj * world_position;
+#endif
+    return out;
+}
+
+struct FragmentInput {
+    [[builtin(front_facing)]] is_front: bool;
+    [[location(0)]] color: vec4<f32>;
+    [[location(1)]] uv: vec2<f32>;
+#ifdef BARYCENTRIC
+    [[location(3)]] barycentric: vec3<f32>;
+#endif
+#ifdef SPLIT
+    [[location(4)]] local_position: vec2<f32>;
+#endif
+    [[location(5)]] depth: f32;
+};
+
+#ifdef SRGB_INPUT
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    let low = c / 12.92;
+    let high = pow((c + vec3<f32>(0.055)) / 1.055, vec3<f32>(2.4));
+    return select(high, low, c <= vec3<f32>(0.04045));
+}
+#endif
+
+#ifdef BARYCENTRIC
+// Distance to the nearest edge in local units, from the heights in `edges.feather`.
+fn edge_distance(barycentric: vec3<f32>) -> f32 {
+    let distances = barycentric * edges.feather.xyz;
+    return min(min(distances.x, distances.y), distances.z);
+}
+#endif
+
+// Colors read as straight alpha, premultiplied for `PREMULTIPLIED_ALPHA` pipelines.
+fn from_straight(color: vec4<f32>) -> vec4<f32> {
+#ifdef PREMULTIPLIED_ALPHA
+    return vec4<f32>(color.rgb * color.a, color.a);
+#else
+    return color;
+#endif
+}
+
+#ifdef TEXTURED
+// The texture sample, premultiplied for `PREMULTIPLIED_ALPHA` pipelines and straight otherwise.
+fn sample_texture(uv: vec2<f32>) -> vec4<f32> {
+    // Wrapping the scroll keeps the UVs small, so sampling stays precise as time goes on.
+    let uv = (mesh.uv_transform * vec3<f32>(uv, 1.0)).xy + fract(mesh.uv_scroll * globals.time);
+    let sample = textureSample(triangle_texture, triangle_sampler, uv);
+#ifdef TEXTURE_OPAQUE
+    return vec4<f32>(sample.rgb, 1.0);
+#else
+#ifdef TEXTURE_PREMULTIPLIED
+#ifdef PREMULTIPLIED_ALPHA
+    return sample;
+#else
+    return vec4<f32>(sample.rgb / max(sample.a, 0.0001), sample.a);
+#endif
+#else
+    return from_straight(sample);
+#endif
+#endif
+}
+#endif
+
+#ifdef DISSOLVE
+fn hash(p: vec2<f32>) -> f32 {
+    return fract(sin(dot(p, vec2<f32>(127.1, 311.7))) * 43758.5453);
+}
+
+// Smoothly interpolated lattice noise in 0 to 1, one lattice cell per unit of `p`.
+fn value_noise(p: vec2<f32>) -> f32 {
+    let i = floor(p);
+    let f = fract(p);
+    let u = f * f * (3.0 - 2.0 * f);
+    let bottom = mix(hash(i), hash(i + vec2<f32>(1.0, 0.0)), u.x);
+    let top = mix(hash(i + vec2<f32>(0.0, 1.0)), hash(i + vec2<f32>(1.0, 1.0)), u.x);
+    return mix(bottom, top, u.y);
+}
+#endif
+
+// 1.0 where the pattern is filled, 0.0 in its gaps.
+fn pattern_mask(uv: vec2<f32>) -> f32 {
+    var mask = 1.0;
+#ifdef PATTERN_STRIPES
+    let across = vec2<f32>(cos(fill.pattern.y), sin(fill.pattern.y));
+    mask = select(0.0, 1.0, fract(dot(uv, across) / fill.pattern.x) < 0.5);
+#endif
+#ifdef PATTERN_CHECKER
+    let cell = floor(uv / fill.pattern.x);
+    mask = select(0.0, 1.0, (cell.x + cell.y) % 2.0 == 0.0);
+#endif
+#ifdef PATTERN_DOTS
+    let offset = (fract(uv / fill.pattern.x) - vec2<f32>(0.5)) * fill.pattern.x;
+    mask = select(0.0, 1.0, length(offset) <= fill.pattern.y);
+#endif
+    return mask;
+}
+
+[[stage(fragment)]]
+fn fragment(in: FragmentInput) -> [[location(0)]] vec4<f32> {
+#ifdef SMOOTH_EDGES
+    // Roughly how much of the pixel the triangle covers, from 0.5 on an edge to 1 a pixel in.
+    // Worked out before anything can discard, since derivatives need uniform control flow.
+    let distance = edge_distance(in.barycentric);
+    let coverage = clamp(distance / max(fwidth(distance), 0.0001) + 0.5, 0.0, 1.0);
+#endif
+#ifdef REVEAL
+    // How far along the axis the fragment is, 0 to 1 across the unit UV square.
+    let axis = fill.reveal.xy;
+    let low = min(axis.x, 0.0) + min(axis.y, 0.0);
+    let high = max(axis.x, 0.0) + max(axis.y, 0.0);
+    if ((dot(in.uv, axis) - low) / max(high - low, 0.0001) > fill.reveal.z) {
+        discard;
+    }
+#endif
+#ifdef DISSOLVE
+    let dissolve_noise = value_noise(in.uv * fill.dissolve.z);
+    if (dissolve_noise < fill.dissolve.x) {
+        discard;
+    }
+#endif
+    var color = in.color;
+#ifdef SRGB_INPUT
+    color = vec4<f32>(srgb_to_linear(color.rgb), color.a);
+#endif
+#ifdef EDGE_COLORS
+    // Each edge's weight is zero on the other two edges, so its color is pure along it.
+    let b = in.barycentric;
+    let weights = vec3<f32>(b.y * b.z, b.z * b.x, b.x * b.y) + vec3<f32>(0.000001);
+    let weights = weights / (weights.x + weights.y + weights.z);
+    color = from_straight(
+        edges.edge_color_a * weights.x
+        + edges.edge_color_b * weights.y
+        + edges.edge_color_c * weights.z
+    );
+#endif
+#ifdef BACK_COLOR
+    if (!in.is_front) {
+        color = from_straight(fill.back_color);
+    }
+#endif
+#ifdef FEATHER
+    let fade = smoothStep(0.0, edges.feather.w, edge_distance(in.barycentric));
+    color = vec4<f32>(edges.glow.rgb, edges.glow.a * fade);
+#endif
+#ifdef SPLIT
+    // Signed distance from the split line, ramped over the softness (a hard step at 0).
+    let side = dot(in.local_position, fill.split.xy) - fill.split.z;
+    let t = smoothStep(0.0, 1.0, clamp(side / max(fill.split.w, 0.0001) + 0.5, 0.0, 1.0));
+    color = from_straight(mix(fill.split_color_a, fill.split_color_b, t));
+#endif
+#ifdef TEXTURED
+    color = color * sample_texture(in.uv);
+#endif
+#ifdef PREMULTIPLIED_ALPHA
+    color = color * pattern_mask(in.uv);
+#else
+    color.a = color.a * pattern_mask(in.uv);
+#endif
+#ifdef EDGE_COLOR
+    if (edge_distance(in.barycentric) < edges.edge_thickness) {
+        color = from_straight(edges.edge_color);
+    }
+#endif
+#ifdef DISSOLVE
+    if (fill.dissolve.x > 0.0 && dissolve_noise < fill.dissolve.x + fill.dissolve.y) {
+        color = from_straight(fill.dissolve_color);
+    }
+#endif
+#ifdef ALPHA_MASK
+    if (color.a < fill.alpha_cutoff) {
+        discard;
+    }
+    color.a = 1.0;
+#endif
+#ifdef SMOOTH_EDGES
+#ifdef PREMULTIPLIED_ALPHA
+    color = color * coverage;
+#else
+    color.a = color.a * coverage;
+#endif
+#endif
+    let tint = globals.tint * mesh.tint;
+    let tint = vec4<f32>(tint.rgb, tint.a * mesh.opacity);
+    color = color * from_straight(tint);
+    let fog_range = globals.fog_range;
+    let fog = clamp((in.depth - fog_range.x) / max(fog_range.y - fog_range.x, 0.0001), 0.0, 1.0)
+        * globals.fog_color.a;
+#ifdef BLEND_ADD
+    // Fading an additive glow toward the fog color would brighten it, so it fades out.
+    color.a = color.a * (1.0 - fog);
+#else
+#ifdef PREMULTIPLIED_ALPHA
+    color = vec4<f32>(mix(color.rgb, globals.fog_color.rgb * color.a, fog), color.a);
+#else
+    color = vec4<f32>(mix(color.rgb, globals.fog_color.rgb, fog), color.a);
+#endif
+#endif
+    let alpha = clamp(color.a, globals.alpha_clamp.x, globals.alpha_clamp.y);
+#ifdef PREMULTIPLIED_ALPHA
+    color = vec4<f32>(color.rgb * (alpha / max(color.a, 0.0001)), alpha);
+#else
+    color.a = alpha;
+#endif
+    return color;
+}
\ No newline at end of file
@@ -0,0 +1,18 @@
+struct Highlight {
+    color: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> highlight: Highlight;
+
+// One triangle covering the whole viewport, from the vertex index alone.
+[[stage(vertex)]]
+fn vertex([[builtin(vertex_index)]] index: u32) -> [[builtin(position)]] vec4<f32> {
+    let corner = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(corner * 2.0 - 1.0, 0.0, 1.0);
+}
+
+[[stage(fragment)]]
+fn fragment() -> [[location(0)]] vec4<f32> {
+    return highlight.color;
+}
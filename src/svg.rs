@@ -0,0 +1,337 @@
+use anyhow::{anyhow, bail};
+use bevy::prelude::*;
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Command(char),
+    Number(f32),
+}
+
+/// The `name="value"` pairs of a tag, after its name.
+pub fn attributes(tag: &str) -> Vec<(&str, &str)> {
+    let mut attributes = Vec::new();
+    let mut rest = tag;
+    while let Some((name, value)) = rest.split_once('=') {
+        let value = value.trim_start();
+        let quote = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => quote,
+            _ => break,
+        };
+        let (value, after) = match value[1..].split_once(quote) {
+            Some(x) => x,
+            None => break,
+        };
+        attributes.push((name.trim(), value));
+        rest = after;
+    }
+    attributes
+}
+
+/// `svg` with its comments and CDATA sections left out, so tags inside them aren't read and
+/// a `<` in their text doesn't start one. An unterminated one runs to the end.
+pub fn without_comments(svg: &str) -> String {
+    let mut out = String::with_capacity(svg.len());
+    let mut rest = svg;
+    loop {
+        let next = [("<!--", "-->"), ("<![CDATA[", "]]>")]
+            .into_iter()
+            .filter_map(|(open, close)| Some((rest.find(open)?, open, close)))
+            .min_by_key(|(at, ..)| *at);
+        let (at, open, close) = match next {
+            Some(next) => next,
+            None => break,
+        };
+        out.push_str(&rest[..at]);
+        let inside = &rest[at + open.len()..];
+        rest = match inside.find(close) {
+            Some(end) => &inside[end + close.len()..],
+            None => "",
+        };
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The length of the number `s` starts with: an optional sign, digits with at most one
+/// dot, and an optional exponent.
+fn number_len(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    let mut len = match bytes.first() {
+        Some(b'+' | b'-') => 1,
+        _ => 0,
+    };
+    let mut dot = false;
+    while let Some(&b) = bytes.get(len) {
+        if !(b.is_ascii_digit() || (b == b'.' && !dot)) {
+            break;
+        }
+        dot |= b == b'.';
+        len += 1;
+    }
+    if let Some(b'e' | b'E') = bytes.get(len) {
+        let mut exponent = len + 1;
+        if let Some(b'+' | b'-') = bytes.get(exponent) {
+            exponent += 1;
+        }
+        if matches!(bytes.get(exponent), Some(b) if b.is_ascii_digit()) {
+            len = exponent;
+            while matches!(bytes.get(len), Some(b) if b.is_ascii_digit()) {
+                len += 1;
+            }
+        }
+    }
+    len
+}
+
+/// Commands and numbers, which may be separated by whitespace, commas or nothing but the
+/// sign or dot of the next number, as in `M10-5.5.5`.
+fn tokens(s: &str) -> anyhow::Result<Vec<Token>> {
+    let separator = |c: char| c.is_whitespace() || c == ',';
+    let mut tokens = Vec::new();
+    let mut rest = s.trim_start_matches(separator);
+    while let Some(c) = rest.chars().next() {
+        let len = if c.is_ascii_alphabetic() {
+            tokens.push(Token::Command(c));
+            1
+        } else {
+            let len = number_len(rest);
+            let number = rest[..len]
+                .parse()
+                .map_err(|_| anyhow!("expected a number in SVG data at {:?}", rest))?;
+            tokens.push(Token::Number(number));
+            len
+        };
+        rest = rest[len..].trim_start_matches(separator);
+    }
+    Ok(tokens)
+}
+
+fn numbers(s: &str) -> anyhow::Result<Vec<f32>> {
+    tokens(s)?
+        .into_iter()
+        .map(|token| match token {
+            Token::Number(number) => Ok(number),
+            Token::Command(c) => Err(anyhow!("expected a number in SVG data, got {:?}", c)),
+        })
+        .collect()
+}
+
+/// The points of a `<polygon>`, in SVG coordinates.
+pub fn points(s: &str) -> anyhow::Result<Vec<Vec2>> {
+    let numbers = numbers(s)?;
+    if numbers.len() % 2 != 0 {
+        bail!("SVG points {:?} have an odd number of coordinates", s);
+    }
+    Ok(numbers
+        .chunks_exact(2)
+        .map(|xy| Vec2::new(xy[0], xy[1]))
+        .collect())
+}
+
+/// The subpaths of a path's `d`, in SVG coordinates.
+pub fn path_outlines(d: &str) -> anyhow::Result<Vec<Vec<Vec2>>> {
+    let tokens = tokens(d)?;
+    let mut outlines = Vec::new();
+    let mut outline = Vec::new();
+    let (mut current, mut start) = (Vec2::ZERO, Vec2::ZERO);
+    let mut command = None;
+    let mut i = 0;
+    while i < tokens.len() {
+        if let Token::Command(c) = tokens[i] {
+            i += 1;
+            if c.eq_ignore_ascii_case(&'z') {
+                outlines.push(std::mem::take(&mut outline));
+                current = start;
+                command = None;
+                continue;
+            }
+            command = Some(c);
+        }
+        let c = match command {
+            Some(c) => c,
+            None => bail!("SVG path {:?} has numbers without a command", d),
+        };
+        let arity = match c.to_ascii_uppercase() {
+            'M' | 'L' => 2,
+            'H' | 'V' => 1,
+            _ => bail!(
+                "unsupported SVG path command {:?}, only straight lines are",
+                c
+            ),
+        };
+        let mut args = [0.0; 2];
+        for arg in args.iter_mut().take(arity) {
+            *arg = match tokens.get(i) {
+                Some(Token::Number(number)) => *number,
+                _ => bail!("SVG path command {:?} is missing coordinates", c),
+            };
+            i += 1;
+        }
+        let relative = c.is_ascii_lowercase();
+        let base = if relative { current } else { Vec2::ZERO };
+        let point = match c.to_ascii_uppercase() {
+            'H' => Vec2::new(base.x + args[0], current.y),
+            'V' => Vec2::new(current.x, base.y + args[0]),
+            _ => base + Vec2::from(args),
+        };
+        if c.eq_ignore_ascii_case(&'m') {
+            if !outline.is_empty() {
+                outlines.push(std::mem::take(&mut outline));
+            }
+            start = point;
+            // Further coordinate pairs after a move are lines.
+            command = Some(if relative { 'l' } else { 'L' });
+        } else if outline.is_empty() {
+            // A line straight after a `Z` starts a new subpath where the closed one did.
+            outline.push(start);
+        }
+        outline.push(point);
+        current = point;
+    }
+    if !outline.is_empty() {
+        outlines.push(outline);
+    }
+    Ok(outlines)
+}
+
+/// The linear fill color, or `None` for `fill="none"`.
+pub fn fill(fill: Option<&str>, opacity: Option<&str>) -> anyhow::Result<Option<[f32; 4]>> {
+    let mut color = match fill {
+        None => Color::BLACK,
+        Some("none") => return Ok(None),
+        Some(fill) => match fill.strip_prefix('#') {
+            Some(hex) if hex.len() == 3 || hex.len() == 6 => {
+                Color::hex(hex).map_err(|e| anyhow!("bad SVG fill {:?}: {:?}", fill, e))?
+            }
+            _ => bail!("unsupported SVG fill {:?}, only #rgb and #rrggbb are", fill),
+        },
+    };
+    if let Some(opacity) = opacity {
+        color.set_a(opacity.parse()?);
+    }
+    Ok(Some(color.as_linear_rgba_f32()))
+}
+
+/// The translation of a `transform`, with y flipped like the points.
+pub fn transform(transform: Option<&str>) -> anyhow::Result<Transform> {
+    let transform = match transform {
+        Some(transform) => transform,
+        None => return Ok(Transform::default()),
+    };
+    let args = transform
+        .strip_prefix("translate")
+        .and_then(|args| args.trim_start().strip_prefix('('))
+        .and_then(|args| args.strip_suffix(')'))
+        .ok_or_else(|| {
+            anyhow!(
+                "unsupported SVG transform {:?}, only translate is",
+                transform
+            )
+        })?;
+    let (x, y) = match numbers(args)?[..] {
+        [x] => (x, 0.0),
+        [x, y] => (x, y),
+        _ => bail!("SVG translate {:?} needs one or two numbers", transform),
+    };
+    Ok(Transform::from_xyz(x, -y, 0.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_end_at_the_next_sign_dot_or_letter() {
+        assert_eq!(number_len("10-5"), 2);
+        assert_eq!(number_len("-5.5.5"), 4);
+        assert_eq!(number_len(".5"), 2);
+        assert_eq!(number_len("1e-3L"), 4);
+        // An `e` without digits is the next command's, not an exponent.
+        assert_eq!(number_len("2e"), 1);
+        assert_eq!(number_len("+3E+2,"), 5);
+        assert_eq!(number_len("M"), 0);
+    }
+
+    #[test]
+    fn tokens_split_on_separators_or_nothing_at_all() {
+        use Token::*;
+        assert_eq!(
+            tokens("M10-5.5.5").unwrap(),
+            [Command('M'), Number(10.0), Number(-5.5), Number(0.5)]
+        );
+        assert_eq!(
+            tokens(" L 1,2\t3e1 z ").unwrap(),
+            [
+                Command('L'),
+                Number(1.0),
+                Number(2.0),
+                Number(30.0),
+                Command('z')
+            ]
+        );
+        assert!(tokens("M 1 # 2").is_err());
+    }
+
+    #[test]
+    fn comments_and_cdata_are_left_out() {
+        assert_eq!(
+            without_comments("<a/><!-- <b/> x < y --><c/><![CDATA[ <d/> ]]><e/>"),
+            "<a/><c/><e/>"
+        );
+        // `-->` inside CDATA doesn't end anything, and unterminated comments run to the end.
+        assert_eq!(
+            without_comments("<a/><![CDATA[-->]]><b/><!-- <c/>"),
+            "<a/><b/>"
+        );
+        assert_eq!(without_comments("<a/>"), "<a/>");
+    }
+
+    #[test]
+    fn path_outlines_follow_relative_and_closing_commands() {
+        let outlines = path_outlines("M10-5.5.5.5h10v-5Zm1 1 2 0 0 2z").unwrap();
+        assert_eq!(
+            outlines,
+            [
+                vec![
+                    Vec2::new(10.0, -5.5),
+                    Vec2::new(0.5, 0.5),
+                    Vec2::new(10.5, 0.5),
+                    Vec2::new(10.5, -4.5),
+                ],
+                // Relative to where the closed subpath started, then lines after the move.
+                vec![
+                    Vec2::new(11.0, -4.5),
+                    Vec2::new(13.0, -4.5),
+                    Vec2::new(13.0, -2.5),
+                ],
+            ]
+        );
+        assert_eq!(
+            path_outlines("M0 0 H5 V5 H0").unwrap(),
+            [vec![
+                Vec2::ZERO,
+                Vec2::new(5.0, 0.0),
+                Vec2::new(5.0, 5.0),
+                Vec2::new(0.0, 5.0),
+            ]]
+        );
+        // Drawing on after a `Z` without a move starts from the closed subpath's start.
+        assert_eq!(
+            path_outlines("M1 1 h4 v4 z l-1 3 H-2").unwrap(),
+            [
+                vec![
+                    Vec2::new(1.0, 1.0),
+                    Vec2::new(5.0, 1.0),
+                    Vec2::new(5.0, 5.0),
+                ],
+                vec![
+                    Vec2::new(1.0, 1.0),
+                    Vec2::new(0.0, 4.0),
+                    Vec2::new(-2.0, 4.0),
+                ],
+            ]
+        );
+        assert!(path_outlines("M0 0 C1 1 2 2 3 3").is_err());
+        assert!(path_outlines("0 0").is_err());
+    }
+}